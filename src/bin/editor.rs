@@ -1,3 +1,5 @@
+use std::collections::{ HashMap,HashSet };
+use std::ffi::OsString;
 use std::fs::File;
 use std::io::Write;
 
@@ -7,33 +9,98 @@ use bevy::input::mouse::MouseWheel;
 use bevy::{
     core_pipeline::tonemapping::Tonemapping,
     prelude::*,
-    input::mouse::MouseButton
+    input::mouse::MouseButton,
+    render::camera::ScalingMode,
+    tasks::{ IoTaskPool,Task }
 };
 use bevy_dolly::dolly::rig;
 use bevy_dolly::prelude::*;
 use bevy_egui::egui::epaint::tessellator::path;
-use bevy_egui::{ egui, EguiContexts };
+use bevy_egui::{ egui, EguiContext, EguiContexts, EguiPlugin, EguiPrimaryContextPass, PrimaryEguiContext };
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
 use bevy_mod_picking::prelude::*;
 use bevy_mod_sysfail::sysfail;
 use leafwing_input_manager::axislike::DualAxisDirection;
 use leafwing_input_manager::prelude::*;
+use futures_lite::future;
 use std::path::PathBuf;
 
 use crate::backend::prelude::PickSet;
 
 use world_editor::{
+    diff,
+    export,
     filepicker,
+    hot_reload,
     map,
+    merge,
     persistence,
     prelude::*,
-    tileset
+    obj,
+    recovery,
+    render,
+    tiled,
+    tileset,
+    validate
 };
 
+mod editor_cli;
 mod editor_ui;
 
 fn main() {
-    
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(code) = editor_cli::run(&args) {
+        std::process::exit(code);
+    }
+
+    App::new()
+        .add_plugins((
+            DefaultPlugins,
+            EguiPlugin::default(),
+            WorldInspectorPlugin::new().run_if(inspector_enabled),
+            InputManagerPlugin::<InputActions>::default()
+        ))
+        .add_plugins((
+            map::Plugin,
+            tileset::Plugin,
+            persistence::Plugin,
+            render::Plugin,
+            obj::Plugin,
+            tiled::Plugin,
+            export::scene::Plugin,
+            export::glb::Plugin,
+            recovery::Plugin,
+            diff::Plugin,
+            hot_reload::Plugin,
+            filepicker::Plugin::<PickerEvent>::default()
+        ))
+        .init_resource::<EditorState>()
+        .init_resource::<TileSelection>()
+        .init_resource::<TileSearch>()
+        .init_resource::<TilePalette>()
+        .init_resource::<editor_ui::notifications::Notifications>()
+        .add_event::<EditorUiEvent>()
+        .add_event::<PickerEvent>()
+        .add_event::<MapCursorMoveEvent>()
+        .add_systems(Startup, setup)
+        .add_systems(Update, (
+            (handle_input, update_camera).chain(),
+            handle_ui_events,
+            handle_picker_events,
+            handle_map_cursor_events.pipe(log_errors),
+            map_loaded,
+            (select_palette_slot, record_recent_tiles).chain(),
+            handle_io_events,
+            (update_cursor_model.pipe(log_errors), update_cursor.pipe(log_errors)).chain(),
+            autosave,
+            replay_journal
+        ))
+        .add_systems(EguiPrimaryContextPass, draw_ui)
+        .run();
+}
+
+fn log_errors(In(result): In<Result<()>>) {
+    result.log_err();
 }
 
 fn dump_main_schedule(app: &mut App) -> Result<()> {
@@ -59,12 +126,54 @@ fn dump_main_schedule(app: &mut App) -> Result<()> {
 }
 
 fn setup(
-    mut commands: Commands,  
-    mut contexts: EguiContexts,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>
+    mut commands: Commands
 ) {
+    let rig = Rig::builder()
+        .with(Position::new(Vec3::ZERO))
+        .with(YawPitch::new().yaw_degrees(45.0).pitch_degrees(-30.0))
+        .with(Smooth::new_position_rotation(0.5, 0.5))
+        .with(Arm::new(Vec3::Z * 50.0))
+        .build();
+
+    commands.spawn((
+        Name::new("camera"),
+        Camera3d::default(),
+        Tonemapping::None,
+        Projection::from(OrthographicProjection {
+            scaling_mode: ScalingMode::FixedVertical { viewport_height: 20.0 },
+            ..OrthographicProjection::default_3d()
+        }),
+        Transform::default(),
+        MainCamera,
+        RigComponent(rig),
+        input_map()
+    ));
+
+    commands.spawn((
+        Name::new("sun"),
+        DirectionalLight {
+            illuminance: 4000.0,
+            shadows_enabled: true,
+            ..default()
+        },
+        Transform::default().looking_to(Vec3::new(-1.0, -2.0, -1.5), Vec3::Y)
+    ));
+
+    commands.spawn((
+        Name::new("cursor"),
+        MapCursor::default(),
+        tileset::TileTransform::default(),
+        Transform::default(),
+        Visibility::default()
+    ));
+}
 
+fn update_camera(
+    time: Res<Time>,
+    mut camera: Query<(&mut RigComponent, &mut Transform), With<MainCamera>>
+) {
+    let Result::Ok((mut rig, mut transform)) = camera.single_mut() else { return };
+    *transform = rig.0.update(time.delta_secs());
 }
 
 #[derive(Resource, Debug)]
@@ -126,6 +235,48 @@ impl TileSelection {
     }
 }
 
+/// Filter for the tile picker
+#[derive(Resource, Default, Debug)]
+struct TileSearch(String);
+
+/// Number of palette slots, selectable with the number keys
+const PALETTE_SLOTS: usize = 9;
+
+/// Favourite and recently used tiles shown above the tile picker
+#[derive(Resource, Default, Debug)]
+struct TilePalette {
+    favourites: Vec<tileset::TileRef>,
+    recent: Vec<tileset::TileRef>
+}
+
+impl TilePalette {
+    pub fn is_favourite(&self, tile: &tileset::TileRef) -> bool {
+        self.favourites.contains(tile)
+    }
+
+    pub fn toggle_favourite(&mut self, tile: tileset::TileRef) {
+        if let Some(index) = self.favourites.iter().position(|t| *t == tile) {
+            self.favourites.remove(index);
+        } else {
+            self.favourites.push(tile);
+        }
+    }
+
+    pub fn record(&mut self, tile: tileset::TileRef) {
+        self.recent.retain(|t| *t != tile);
+        self.recent.insert(0, tile);
+        self.recent.truncate(PALETTE_SLOTS);
+    }
+
+    /// Favourites first, then recently used tiles
+    pub fn slots(&self) -> Vec<tileset::TileRef> {
+        let mut slots = self.favourites.clone();
+        slots.extend(self.recent.iter().filter(|t| !self.favourites.contains(t)));
+        slots.truncate(PALETTE_SLOTS);
+        slots
+    }
+}
+
 #[derive(Debug, Clone, Event)]
 enum EditorUiEvent {
     MapNew,
@@ -133,8 +284,40 @@ enum EditorUiEvent {
     MapSave(PathBuf),
     MapLoad(PathBuf),
     MapSaveAs,
+    MapExportScene(PathBuf),
+    MapExportGlb(PathBuf),
+    MapExportTiled(PathBuf),
+    MapCompare(PathBuf),
+    CloseDiff,
+    FocusLocation(map::Location),
+    RunValidation,
+    /// Point every missing tile matching the first `TileRef` at the second
+    RemapTiles(Vec<(tileset::TileRef, tileset::TileRef)>),
     DeleteTileset(Entity),
-    RedrawMapTiles
+    RedrawMapTiles,
+    RecoverSession(PathBuf),
+    DiscardSession(PathBuf),
+    /// Resolve a merge conflict by index, taking theirs if `true`
+    ResolveConflict(usize, bool),
+    /// Accept ours for a tile defined differently on both sides, by index
+    AcknowledgeTileConflict(usize),
+    TilesetResync(Entity),
+    /// Load a tileset again from its file
+    ReloadTileset(Entity),
+    /// Fix the integrity problems of a tileset
+    RepairTileset(Entity),
+    /// Write a tileset to a file, replacing the file if it exists
+    TilesetExport(Entity, PathBuf),
+    /// Add a tile for every part of a glTF file to a tileset
+    AddModelParts {
+        tileset: Entity,
+        path: PathBuf,
+        parts: Vec<tileset::ModelPart>
+    },
+    /// Open the relocate dialog with the tile models that do not exist
+    FindMissingAssets,
+    /// Point every tile using the first path at the file at the second
+    RelocateAssets(Vec<(PathBuf, PathBuf)>)
 }
 
 #[derive(Event, Debug, Clone, Copy)]
@@ -148,8 +331,18 @@ enum PickerEvent {
     },
     MapSave(Option<PathBuf>),
     MapLoad(Option<PathBuf>),
+    MapExportScene(Option<PathBuf>),
+    MapExportGlb(Option<PathBuf>),
+    MapExportTiled(Option<PathBuf>),
+    MapCompare(Option<PathBuf>),
     TilesetImport(Option<Vec<PathBuf>>),
-    TilesetExport(Entity, Option<PathBuf>)
+    TilesetFromFolder(Option<PathBuf>),
+    TilesetExport(Entity, Option<PathBuf>),
+    AddModelParts {
+        tileset_id: Entity,
+        file: Option<PathBuf>
+    },
+    RelocateSearch(Option<PathBuf>)
 }
 
 impl filepicker::PickerEvent for PickerEvent {
@@ -163,8 +356,18 @@ impl filepicker::PickerEvent for PickerEvent {
             },
             PickerEvent::MapSave(_) => PickerEvent::MapSave(Some(result[0].clone())),
             PickerEvent::MapLoad(_) => PickerEvent::MapLoad(Some(result[0].clone())),
+            PickerEvent::MapExportScene(_) => PickerEvent::MapExportScene(Some(result[0].clone())),
+            PickerEvent::MapExportGlb(_) => PickerEvent::MapExportGlb(Some(result[0].clone())),
+            PickerEvent::MapExportTiled(_) => PickerEvent::MapExportTiled(Some(result[0].clone())),
+            PickerEvent::MapCompare(_) => PickerEvent::MapCompare(Some(result[0].clone())),
             PickerEvent::TilesetImport(_) => PickerEvent::TilesetImport(Some(result)),
-            PickerEvent::TilesetExport(t, _) => PickerEvent::TilesetExport(t, Some(result[0].clone())) 
+            PickerEvent::TilesetFromFolder(_) => PickerEvent::TilesetFromFolder(Some(result[0].clone())),
+            PickerEvent::TilesetExport(t, _) => PickerEvent::TilesetExport(t, Some(result[0].clone())),
+            PickerEvent::AddModelParts { tileset_id, .. } => PickerEvent::AddModelParts {
+                tileset_id,
+                file: Some(result[0].clone())
+            },
+            PickerEvent::RelocateSearch(_) => PickerEvent::RelocateSearch(Some(result[0].clone()))
         };
     }
 }
@@ -194,7 +397,9 @@ pub enum InputActions {
     ResetCamera,
     ZeroCamera,
     TileRotateCW,
-    TileRotateCCW
+    TileRotateCCW,
+    /// Select the tile in a palette slot
+    PaletteSlot(usize)
 }
 
 fn input_map() -> InputMap<InputActions> {
@@ -212,12 +417,303 @@ fn input_map() -> InputMap<InputActions> {
     .insert(InputActions::LeftClick, MouseButton::Left)
     .insert_dual_axis(InputActions::CameraScale,mouse_wheel)
     .insert_dual_axis(InputActions::MouseMove, mouse_move)
+    .insert_multiple((0..PALETTE_SLOTS).zip([
+        KeyCode::Digit1,
+        KeyCode::Digit2,
+        KeyCode::Digit3,
+        KeyCode::Digit4,
+        KeyCode::Digit5,
+        KeyCode::Digit6,
+        KeyCode::Digit7,
+        KeyCode::Digit8,
+        KeyCode::Digit9
+    ]).map(|(slot, key)| (InputActions::PaletteSlot(slot), key)))
     .clone()
 }
 
 #[derive(Component)]
 pub struct RigComponent(Rig);
 
+fn focus_location(
+    In(location): In<map::Location>,
+    map: Query<&map::Map>,
+    mut camera: Query<&mut RigComponent, With<MainCamera>>
+) {
+    let Result::Ok(map) = map.single() else { return };
+    let Result::Ok(mut rig) = camera.single_mut() else { return };
+
+    if let Some(pos) = rig.0.try_driver_mut::<Position>() {
+        pos.position = map.translation(location);
+    }
+}
+
+/// Results of the last validation run, shown in the problems panel
+#[derive(Resource, Debug, Default)]
+struct Problems(Vec<validate::Problem>);
+
+const LINT_RULES: &str = "assets/lint.ron";
+
+fn run_validation(world: &mut World) -> Result<()> {
+    let root = world
+        .query_filtered::<Entity, With<map::Map>>()
+        .single(world)
+        .context("no map loaded")?;
+    let map = persistence::MapFormat::try_new(world, root)?;
+
+    let rules_path = std::path::Path::new(LINT_RULES);
+    let rules = if rules_path.exists() {
+        validate::Rules::load(rules_path)?
+    } else {
+        validate::Rules::default()
+    };
+
+    let asset_root = world.resource::<tileset::AssetRoot>().0.clone();
+    let problems = validate::validate(&map, &rules, &asset_root);
+    info!("validation found {} problems", problems.len());
+    world.insert_resource(Problems(problems));
+
+    Ok(())
+}
+
+fn remap_tiles(
+    In(remap): In<Vec<(tileset::TileRef, tileset::TileRef)>>,
+    mut state: ResMut<EditorState>,
+    mut tiles: Query<&mut tileset::TileRef, With<tileset::MissingTile>>
+) {
+    let remap: std::collections::HashMap<_, _> = remap.into_iter().collect();
+    let mut count = 0;
+
+    for mut tile_ref in &mut tiles {
+        if let Some(target) = remap.get(&*tile_ref) {
+            *tile_ref = *target;
+            count += 1;
+        }
+    }
+
+    info!("remapped {} tiles", count);
+    if count > 0 {
+        state.unsaved_changes = true;
+    }
+}
+
+fn resync_tileset(
+    In(entity): In<Entity>,
+    mut state: ResMut<EditorState>,
+    asset_root: Res<tileset::AssetRoot>,
+    mut tilesets: Query<&mut tileset::TileSet>
+) {
+    let Result::Ok(mut tileset) = tilesets.get_mut(entity) else {
+        warn!("tileset not found: {:?}", entity);
+        return;
+    };
+
+    match tileset.sync_dir(&asset_root.0) {
+        Result::Ok((added, removed)) => {
+            info!("synced tileset {}: {} added, {} removed", tileset.name, added, removed);
+            state.unsaved_changes = true;
+        }
+        Err(e) => error!("failed to sync tileset {}: {:?}", tileset.name, e)
+    }
+}
+
+fn reload_tileset(
+    In(entity): In<Entity>,
+    mut commands: Commands,
+    files: Query<&persistence::TilesetFile>
+) {
+    let Result::Ok(file) = files.get(entity) else {
+        warn!("tileset {:?} has no file to reload", entity);
+        return;
+    };
+
+    info!("reload tileset {}", file.path.to_string_lossy());
+    commands
+        .entity(entity)
+        .insert(tileset::TilesetImporter::new(file.path.clone()));
+}
+
+fn repair_tileset(
+    In(entity): In<Entity>,
+    mut commands: Commands,
+    mut state: ResMut<EditorState>,
+    mut tilesets: Query<&mut tileset::TileSet>
+) {
+    let Result::Ok(mut tileset) = tilesets.get_mut(entity) else {
+        warn!("tileset not found: {:?}", entity);
+        return;
+    };
+
+    let fixed = tileset.repair();
+    for problem in &fixed {
+        info!("repaired tileset {}: {}", tileset.name, problem);
+    }
+    commands.entity(entity).remove::<tileset::TilesetIntegrity>();
+    if !fixed.is_empty() {
+        state.unsaved_changes = true;
+    }
+}
+
+/// Scenes and nodes of a glTF file, offered as tiles by the model parts dialog
+#[derive(Resource, Debug)]
+struct ModelPartsImport {
+    tileset: Entity,
+    path: PathBuf,
+    parts: Vec<(tileset::ModelPart, bool)>
+}
+
+/// A tileset export waiting for the user to confirm replacing an existing file
+#[derive(Resource, Debug)]
+struct TilesetExportOverwrite {
+    tileset: Entity,
+    path: PathBuf
+}
+
+fn export_tileset(
+    In((tileset_id, path)): In<(Entity, PathBuf)>,
+    mut commands: Commands,
+    tilesets: Query<&tileset::TileSet>
+) {
+    commands.remove_resource::<TilesetExportOverwrite>();
+
+    let Result::Ok(tileset) = tilesets.get(tileset_id) else {
+        warn!("tileset not found: {:?}", tileset_id);
+        return;
+    };
+    commands.spawn(tileset::TilesetExporter::new(path.clone(), tileset_id, tileset.clone()));
+    // maps now reference the exported file instead of embedding the tileset
+    commands
+        .entity(tileset_id)
+        .insert(persistence::TilesetFile::new(path))
+        .remove::<persistence::TilesetFileChanged>();
+}
+
+fn add_model_parts(
+    In((tileset_id, path, parts)): In<(Entity, PathBuf, Vec<tileset::ModelPart>)>,
+    mut commands: Commands,
+    mut state: ResMut<EditorState>,
+    asset_root: Res<tileset::AssetRoot>,
+    mut tilesets: Query<&mut tileset::TileSet>
+) {
+    commands.remove_resource::<ModelPartsImport>();
+
+    let Result::Ok(mut tileset) = tilesets.get_mut(tileset_id) else {
+        warn!("tileset not found: {:?}", tileset_id);
+        return;
+    };
+
+    let path = asset_root.tile_path(&path);
+    for part in parts {
+        tileset.add_part(path.clone(), part);
+    }
+    state.unsaved_changes = true;
+}
+
+/// Tile models that do not exist and where they might have moved to, shown
+/// by the relocate dialog
+#[derive(Resource, Default)]
+struct RelocateAssets {
+    missing: Vec<PathBuf>,
+    search_dir: Option<PathBuf>,
+    search: Option<Task<HashMap<OsString, Vec<PathBuf>>>>,
+    /// Files with the same name as a missing path, and the one chosen
+    candidates: HashMap<PathBuf, (Vec<PathBuf>, usize)>
+}
+
+impl RelocateAssets {
+    /// Looks for the missing files by name in `dir` and its subdirectories
+    fn search(&mut self, dir: PathBuf) {
+        let names: HashSet<OsString> = self
+            .missing
+            .iter()
+            .filter_map(|path| path.file_name().map(|name| name.to_os_string()))
+            .collect();
+        let search_dir = dir.clone();
+        self.search = Some(IoTaskPool::get().spawn(async move { tileset::locate_files(&search_dir, &names) }));
+        self.search_dir = Some(dir);
+        self.candidates.clear();
+    }
+
+    /// Collects the search result once it is done; returns whether the search is still running
+    fn poll(&mut self) -> bool {
+        let Some(task) = self.search.as_mut() else { return false };
+        let Some(found) = future::block_on(future::poll_once(task)) else { return true };
+        self.search = None;
+
+        for path in &self.missing {
+            let Some(files) = path.file_name().and_then(|name| found.get(name)) else { continue };
+            self.candidates.insert(path.clone(), (files.clone(), 0));
+        }
+
+        false
+    }
+
+    /// The chosen file for every missing path that was found
+    fn relocations(&self) -> Vec<(PathBuf, PathBuf)> {
+        self.candidates
+            .iter()
+            .map(|(path, (files, chosen))| (path.clone(), files[*chosen].clone()))
+            .collect()
+    }
+}
+
+fn find_missing_assets(
+    mut commands: Commands,
+    asset_root: Res<tileset::AssetRoot>,
+    tilesets: Query<&tileset::TileSet>
+) {
+    let mut missing: Vec<PathBuf> = tilesets
+        .iter()
+        .flat_map(|tileset| tileset.tiles.values())
+        .map(|tile| tile.path.clone())
+        .filter(|path| !asset_root.resolve(path).exists())
+        .collect();
+    missing.sort();
+    missing.dedup();
+
+    info!("{} tile models are missing", missing.len());
+    commands.insert_resource(RelocateAssets {
+        missing,
+        ..default()
+    });
+}
+
+fn relocate_assets(
+    In(paths): In<Vec<(PathBuf, PathBuf)>>,
+    mut commands: Commands,
+    mut state: ResMut<EditorState>,
+    asset_root: Res<tileset::AssetRoot>,
+    mut tilesets: Query<(Entity, &mut tileset::TileSet)>
+) {
+    commands.remove_resource::<RelocateAssets>();
+
+    let paths: HashMap<PathBuf, PathBuf> = paths
+        .into_iter()
+        .map(|(path, file)| {
+            let file = asset_root.tile_path(&file);
+            if file.is_absolute() {
+                warn!("{} is outside the asset root; storing its absolute path", file.to_string_lossy());
+            }
+            (path, file)
+        })
+        .collect();
+    let mut relocated = Vec::new();
+
+    for (entity, mut tileset) in &mut tilesets {
+        for tile in tileset.tiles.values_mut() {
+            let Some(path) = paths.get(&tile.path) else { continue };
+            tile.path = path.clone();
+            relocated.push(tileset::TileRef { tileset: entity, tile: tile.id });
+        }
+    }
+
+    info!("relocated {} tiles", relocated.len());
+    if relocated.is_empty() {
+        return;
+    }
+    tileset::refresh_tiles(&mut commands, &relocated);
+    state.unsaved_changes = true;
+}
+
 fn handle_input(
     action_state: Query<&ActionState<InputActions>>,
     mut cursor: Query<&mut tileset::TileTransform, With<MapCursor>>,
@@ -305,12 +801,41 @@ fn handle_ui_events(world: &mut World) {
                 run_system(world, (), create_map);
             }
             MapClose => run_system(world, (), close_map),
-            MapSaveAs => {
-                world.spawn(filepicker::Picker::save_dialog(PickerEvent::MapSave(None)).build());
+            MapSaveAs => save_as_dialog(world),
+            // maps imported from Tiled are saved in the editor's own format
+            MapSave(path) if tiled::is_tiled_path(&path) => {
+                warn!("{} is a Tiled map; choose where to save it", path.to_string_lossy());
+                save_as_dialog(world);
             }
             MapSave(path) => run_system(world, path.clone(), save_map),
             MapLoad(path) => run_system(world, path.clone(), load_map),
+            MapExportScene(path) => run_system(world, path.clone(), export_scene),
+            MapExportGlb(path) => run_system(world, path.clone(), export_glb),
+            MapExportTiled(path) => run_system(world, path.clone(), export_tiled),
+            MapCompare(path) => {
+                world.spawn(diff::DiffLoader::new(path));
+            }
+            CloseDiff => {
+                world.remove_resource::<diff::MapDiff>();
+            }
+            FocusLocation(location) => run_system(world, location, focus_location),
+            RunValidation => run_system(world, (), run_validation).log_err(),
+            RemapTiles(remap) => run_system(world, remap, remap_tiles),
+            TilesetResync(entity) => run_system(world, entity, resync_tileset),
+            ReloadTileset(entity) => run_system(world, entity, reload_tileset),
+            RepairTileset(entity) => run_system(world, entity, repair_tileset),
+            TilesetExport(entity, path) => run_system(world, (entity, path), export_tileset),
+            AddModelParts { tileset, path, parts } => run_system(world, (tileset, path, parts), add_model_parts),
+            FindMissingAssets => run_system(world, (), find_missing_assets),
+            RelocateAssets(paths) => run_system(world, paths, relocate_assets),
             RedrawMapTiles => run_system(world, (), redraw_map_tiles),
+            RecoverSession(dir) => {
+                run_system(world, (), close_map);
+                run_system(world, dir.clone(), recover_session);
+            }
+            DiscardSession(dir) => run_system(world, dir.clone(), discard_session),
+            ResolveConflict(index, theirs) => run_system(world, (index, theirs), resolve_conflict).log_err(),
+            AcknowledgeTileConflict(index) => run_system(world, index, acknowledge_tile_conflict),
             DeleteTileset(entity) => run_system(world, entity, remove_tileset)
         }
     }
@@ -318,10 +843,19 @@ fn handle_ui_events(world: &mut World) {
     world.insert_resource(events);
 }
 
+fn save_as_dialog(world: &mut World) {
+    world.spawn(
+        filepicker::Picker::save_dialog(PickerEvent::MapSave(None))
+            .add_filter("RON", &["ron"])
+            .add_filter("JSON", &["json"])
+            .add_filter("Binary", &["bin", "zst", "gz"])
+            .build()
+    );
+}
+
 fn save_map(
     In(path): In<std::path::PathBuf>,
     mut commands: Commands,  
-    mut state: ResMut<EditorState>,
     map: Query<Entity, With<map::Map>>
 ) {
     let Result::Ok(entity) = map.single() else {
@@ -330,7 +864,174 @@ fn save_map(
     };
     info!("save map to {}", path.to_string_lossy());
     commands.queue(persistence::SaveMapCommand::new(path, entity));
-    state.unsaved_changes = false;
+}
+
+fn handle_io_events(
+    mut commands: Commands,
+    mut events: EventReader<persistence::IoCompleted>,
+    mut state: ResMut<EditorState>,
+    mut notifications: ResMut<editor_ui::notifications::Notifications>,
+    mut session: Option<ResMut<recovery::CurrentSession>>,
+    conflicts: Option<Res<MergeConflicts>>,
+    time: Res<Time>
+) {
+    for event in events.read() {
+        notifications.push(time.elapsed(), event);
+        if event.result.is_err() {
+            continue;
+        }
+
+        if let Some(session) = session.as_mut() {
+            let result = match event.operation {
+                persistence::IoOperation::Autosave => recovery::autosaved(&mut session.0),
+                // a recovered map is loaded from the autosave, but belongs to `map_path`
+                persistence::IoOperation::MapSave | persistence::IoOperation::MapLoad => {
+                    session.0.set_map_path(state.map_path.clone())
+                }
+                _ => Result::Ok(())
+            };
+            if let Err(e) = result {
+                warn!("failed to update recovery session: {:?}", e);
+            }
+        }
+
+        match event.operation {
+            persistence::IoOperation::MapSave => {
+                state.unsaved_changes = false;
+                if let Some(conflicts) = &conflicts {
+                    if let Err(e) = conflicts.0.save(&event.path) {
+                        warn!("failed to update merge conflicts: {:?}", e);
+                    }
+                }
+            }
+            persistence::IoOperation::MapLoad => match merge::MergeReport::load(&event.path) {
+                Result::Ok(Some(report)) => {
+                    info!("{} unresolved merge conflicts", report.hexes.len());
+                    commands.insert_resource(MergeConflicts(report));
+                }
+                Result::Ok(None) => commands.remove_resource::<MergeConflicts>(),
+                Err(e) => warn!("{:?}", e)
+            },
+            _ => ()
+        }
+    }
+}
+
+/// Conflicts left by `merge` in the open map
+#[derive(Resource, Debug)]
+struct MergeConflicts(merge::MergeReport);
+
+fn resolve_conflict(
+    In((index, theirs)): In<(usize, bool)>,
+    mut commands: Commands,
+    mut conflicts: ResMut<MergeConflicts>,
+    mut state: ResMut<EditorState>,
+    map: Query<&Children, With<map::Map>>,
+    layers: Query<(Entity, &map::Layer, Option<&Children>)>,
+    tiles: Query<&map::Location, With<tileset::TileRef>>
+) -> Result<()> {
+    if index >= conflicts.0.hexes.len() {
+        bail!("no merge conflict {}", index);
+    }
+    let conflict = conflicts.0.hexes.remove(index);
+    let map_children = map.single()?;
+
+    let find_layer = |name: &str| {
+        layers
+            .iter_many(map_children)
+            .find(|(_, layer, _)| layer.name == name)
+    };
+    let find_tile = |children: Option<&Children>| {
+        children
+            .into_iter()
+            .flatten()
+            .copied()
+            .find(|entity| tiles.get(*entity).is_ok_and(|l| *l == conflict.location))
+    };
+
+    let (layer, _, layer_children) = find_layer(&conflict.layer).context("conflict layer not found")?;
+    let conflict_layer = find_layer(&format!("{}{}", merge::CONFLICT_LAYER_PREFIX, conflict.layer));
+    let ours = find_tile(layer_children);
+    let their = conflict_layer.and_then(|(_, _, children)| find_tile(children));
+
+    if theirs {
+        if let Some(ours) = ours {
+            commands.entity(ours).despawn();
+        }
+        if let Some(their) = their {
+            commands.entity(their).insert(ChildOf(layer));
+        }
+    } else if let Some(their) = their {
+        commands.entity(their).despawn();
+    }
+
+    // drop the conflict layer once its last tile has been resolved
+    if let Some((conflict_layer, _, children)) = conflict_layer {
+        let remaining = children.map(|c| c.len()).unwrap_or(0) - their.is_some() as usize;
+        if remaining == 0 {
+            commands.entity(conflict_layer).despawn();
+        }
+    }
+
+    state.unsaved_changes = true;
+
+    Ok(())
+}
+
+/// The merge kept our definition of the tile; once acknowledged it is no
+/// longer reported, and the report goes away with the last conflict
+fn acknowledge_tile_conflict(
+    In(index): In<usize>,
+    mut conflicts: ResMut<MergeConflicts>,
+    mut state: ResMut<EditorState>
+) {
+    if index >= conflicts.0.tiles.len() {
+        warn!("no tile conflict {}", index);
+        return;
+    }
+    let (tileset, tile) = conflicts.0.tiles.remove(index);
+    info!("kept our definition of tile {} of tileset {}", tile, tileset);
+    state.unsaved_changes = true;
+}
+
+fn export_scene(
+    In(path): In<std::path::PathBuf>,
+    mut commands: Commands,
+    map: Query<Entity, With<map::Map>>
+) {
+    let Result::Ok(entity) = map.single() else {
+        warn!("no map loaded");
+        return;
+    };
+    info!("export map as scene to {}", path.to_string_lossy());
+    commands.queue(export::scene::ExportSceneCommand::new(path, entity));
+}
+
+fn export_glb(
+    In(path): In<std::path::PathBuf>,
+    mut commands: Commands,
+    asset_root: Res<tileset::AssetRoot>,
+    map: Query<Entity, With<map::Map>>
+) {
+    let Result::Ok(entity) = map.single() else {
+        warn!("no map loaded");
+        return;
+    };
+    info!("export map as glb to {}", path.to_string_lossy());
+    commands.queue(export::glb::ExportGlbCommand::new(path, entity, asset_root.0.clone()));
+}
+
+fn export_tiled(
+    In(path): In<std::path::PathBuf>,
+    mut commands: Commands,
+    map: Query<Entity, With<map::Map>>
+) {
+    let Result::Ok(entity) = map.single() else {
+        warn!("no map loaded");
+        return;
+    };
+    info!("export map to Tiled {}", path.to_string_lossy());
+    commands.queue(tiled::ExportTiledCommand::new(path, entity));
 }
 
 fn load_map(
@@ -345,6 +1046,7 @@ fn close_map(
     mut commands: Commands,
     mut state: ResMut<EditorState>,
     mut tile_selection: ResMut<TileSelection>,
+    mut palette: ResMut<TilePalette>,
     map: Query<Entity, With<map::Map>>,
     cursor: Query<Entity, With<MapCursor>>
 ) {
@@ -354,14 +1056,15 @@ fn close_map(
         info!("closing map {:?}", state.map_path);
     }
 
-    let cursor = cursor.single().unwrap();
-
-    commands
-        .entity(cursor)
-        .remove::<(tileset::TileRef, SceneRoot)>()
-        .despawn();
+    // the cursor stays for the next map, without a tile
+    if let Result::Ok(cursor) = cursor.single() {
+        commands
+            .entity(cursor)
+            .remove::<(tileset::TileRef, SceneRoot)>();
+    }
 
     tile_selection.tiles.clear();
+    *palette = TilePalette::default();
 
     if let Result::Ok(entity) = map.single() {
         commands.entity(entity).despawn();
@@ -475,6 +1178,8 @@ fn handle_picker_events(
     mut state: ResMut<EditorState>,
     mut tilesets: Query<&mut tileset::TileSet>,
     mut editor_events: EventWriter<EditorUiEvent>,
+    asset_root: Res<tileset::AssetRoot>,
+    mut relocate: Option<ResMut<RelocateAssets>>,
     map: Query<Entity, With<map::Map>>
 ) {
     for event in picker_events.read() {
@@ -483,26 +1188,39 @@ fn handle_picker_events(
                 let Result::Ok(mut tileset) = tilesets.get_mut(*tileset_id) else { continue; };
                 let Some(paths) = files else { continue; };
                 for path in paths {
-                    tileset.add_title(path.clone());
+                    tileset.add_title(asset_root.tile_path(path));
                 } 
                 state.unsaved_changes = true;
             }
             PickerEvent::MapSave(path) => {
                 let Some(path) = path else { continue; };
-                if state.map_path.is_none() {
-                    state.map_path = Some(path.clone());
-                }
+                // later saves go to the file picked by Save As
+                state.map_path = Some(path.clone());
 
                 editor_events.write(EditorUiEvent::MapSave(path.clone()));
             }
             PickerEvent::MapLoad(path) => {
                 let Some(path) = path else { continue; };
-                if state.map_path.is_none() {
-                    state.map_path = Some(path.clone());
-                }
+                state.map_path = Some(path.clone());
 
                 editor_events.write(EditorUiEvent::MapLoad(path.clone()));
             }
+            PickerEvent::MapExportScene(path) => {
+                let Some(path) = path else { continue; };
+                editor_events.write(EditorUiEvent::MapExportScene(path.clone()));
+            }
+            PickerEvent::MapExportGlb(path) => {
+                let Some(path) = path else { continue; };
+                editor_events.write(EditorUiEvent::MapExportGlb(path.clone()));
+            }
+            PickerEvent::MapExportTiled(path) => {
+                let Some(path) = path else { continue; };
+                editor_events.write(EditorUiEvent::MapExportTiled(path.clone()));
+            }
+            PickerEvent::MapCompare(path) => {
+                let Some(path) = path else { continue; };
+                editor_events.write(EditorUiEvent::MapCompare(path.clone()));
+            }
             PickerEvent::TilesetImport(paths) => {
                 let Some(paths) = paths else { continue; };
                 let Result::Ok(map) = map.single() else {
@@ -516,13 +1234,58 @@ fn handle_picker_events(
                     }
                 });
             }
-            PickerEvent::TilesetExport(tileset_id, path) => {
+            PickerEvent::AddModelParts { tileset_id, file } => {
+                let Some(path) = file else { continue; };
+                match tileset::model_parts(path) {
+                    Result::Ok(parts) if parts.is_empty() => {
+                        warn!("no named scenes or nodes in {:?}", path);
+                    }
+                    Result::Ok(parts) => commands.insert_resource(ModelPartsImport {
+                        tileset: *tileset_id,
+                        path: path.clone(),
+                        parts: parts.into_iter().map(|part| (part, false)).collect()
+                    }),
+                    Err(e) => error!("failed to read {:?}: {:?}", path, e)
+                }
+            }
+            PickerEvent::RelocateSearch(dir) => {
+                let Some(dir) = dir else { continue; };
+                let Some(relocate) = relocate.as_mut() else { continue; };
+                relocate.search(dir.clone());
+            }
+            PickerEvent::TilesetFromFolder(path) => {
                 let Some(path) = path else { continue; };
-                let Result::Ok(tileset) = tilesets.get(*tileset_id) else {
-                    warn!("tileset not found: {:?}", event);
+                let Result::Ok(map) = map.single() else {
+                    error!("no map found; not creating tileset");
                     continue;
                 };
-                commands.spawn(tileset::TilesetExporter::new(path.clone(), tileset.clone()));
+                let tileset = match tileset::TileSet::from_dir(path, &asset_root.0) {
+                    Result::Ok(tileset) => tileset,
+                    Err(e) => {
+                        error!("failed to create tileset from {:?}: {:?}", path, e);
+                        continue;
+                    }
+                };
+                info!("created tileset {} with {} tiles", tileset.name, tileset.tiles.len());
+
+                commands.entity(map).with_children(|map| {
+                    let id = map
+                        .spawn((Name::new(format!("tileset: {}", tileset.name)), tileset))
+                        .id();
+                    state.active_tileset = Some(id);
+                });
+                state.unsaved_changes = true;
+            }
+            PickerEvent::TilesetExport(tileset_id, path) => {
+                let Some(path) = path else { continue; };
+                if path.exists() {
+                    commands.insert_resource(TilesetExportOverwrite {
+                        tileset: *tileset_id,
+                        path: path.clone()
+                    });
+                    continue;
+                }
+                editor_events.write(EditorUiEvent::TilesetExport(*tileset_id, path.clone()));
             }
         }
     }
@@ -533,8 +1296,11 @@ fn handle_picker_events(
 fn handle_map_cursor_events(
     mut commands: Commands,
     mut events: EventReader<MapCursorMoveEvent>,
-    state: Res<EditorState>,
+    mut state: ResMut<EditorState>,
+    mut journal: ResMut<recovery::Journal>,
     map: Query<&map::Map>,
+    layers: Query<&map::Layer>,
+    tilesets: Query<&tileset::TileSet>,
     buttons: Res<ButtonInput<MouseButton>>,
     cursor: Query<(Entity, &tileset::TileRef, &tileset::TileTransform), With<MapCursor>>,
     tiles: Query<
@@ -561,6 +1327,7 @@ fn handle_map_cursor_events(
     }
 
     let layer = state.active_layer.context("no active layer")?;
+    let layer_name = layers.get(layer).context("active layer not found")?.name.clone();
 
     for (tile_entity, tile_location, tile_tile_ref, tile_tile_transform, tile_parent) in &tiles {
         if tile_parent.parent() != layer {
@@ -578,6 +1345,8 @@ fn handle_map_cursor_events(
         }
 
         commands.entity(tile_entity).despawn();
+        journal.record(&layer_name, location, recovery::JournalEdit::Remove);
+        state.unsaved_changes = true;
     }
 
     if buttons.pressed(MouseButton::Left) {
@@ -589,7 +1358,15 @@ fn handle_map_cursor_events(
                 Transform::default(),
                 Visibility::default()
             ))
-            .add_child(layer);
+            .insert(ChildOf(layer));
+
+        let tileset = tilesets.get(tile_ref.tileset).context("unknown tileset for cursor tile")?;
+        journal.record(&layer_name, location, recovery::JournalEdit::Insert {
+            tileset: tileset.name.clone(),
+            tile: tile_ref.tile,
+            rotation: tile_transform.rotation
+        });
+        state.unsaved_changes = true;
 
         debug!("insert tile: {:?} @ {:?}", tile_ref, location);
     }
@@ -597,6 +1374,194 @@ fn handle_map_cursor_events(
     Ok(())
 }
 
+fn autosave(
+    mut commands: Commands,
+    mut timer: Local<Option<Timer>>,
+    time: Res<Time>,
+    settings: Res<recovery::RecoverySettings>,
+    state: Res<EditorState>,
+    session: Option<Res<recovery::CurrentSession>>,
+    map: Query<Entity, (With<map::Map>, Without<persistence::MapImporter>)>
+) {
+    let Some(session) = session else { return };
+    let timer = timer.get_or_insert_with(|| Timer::new(settings.interval, TimerMode::Repeating));
+
+    if !timer.tick(time.delta()).just_finished() || !state.unsaved_changes {
+        return;
+    }
+    let Result::Ok(entity) = map.single() else { return };
+
+    let path = session.0.map_file();
+    debug!("autosave map to {}", path.to_string_lossy());
+    if let Err(e) = recovery::autosave_started(&session.0) {
+        warn!("failed to update recovery session: {:?}", e);
+        return;
+    }
+    commands.queue(persistence::SaveMapCommand::autosave(path, entity));
+}
+
+#[derive(Resource, Debug)]
+struct PendingJournal {
+    entries: Vec<recovery::JournalEntry>,
+    session: recovery::Session
+}
+
+fn recover_session(
+    In(dir): In<std::path::PathBuf>,
+    mut commands: Commands,
+    mut state: ResMut<EditorState>,
+    mut sessions: ResMut<recovery::RecoverableSessions>
+) {
+    sessions.0.retain(|s| s.dir != dir);
+
+    let session = match recovery::Session::load(&dir) {
+        Result::Ok(session) => session,
+        Err(e) => {
+            error!("failed to recover session: {:?}", e);
+            return;
+        }
+    };
+    let entries = match session.journal() {
+        Result::Ok(entries) => entries,
+        Err(e) => {
+            warn!("failed to read recovery journal: {:?}", e);
+            Vec::new()
+        }
+    };
+    info!(
+        "recovering session started {} ({} journaled edits)",
+        session.started.format("%Y-%m-%d %H:%M:%S"),
+        entries.len()
+    );
+
+    if session.has_autosave() {
+        commands.spawn(persistence::MapImporter::new(session.map_file()));
+    } else if let Some(map_path) = &session.map_path {
+        // crashed before the first autosave; the edits apply to the open file
+        commands.spawn(persistence::MapImporter::new(map_path.clone()));
+    } else {
+        commands.spawn((
+            Name::new("map"),
+            map::Map::default(),
+            Transform::default(),
+            Visibility::default()
+        ));
+    }
+
+    state.map_path = session.map_path.clone();
+    state.unsaved_changes = true;
+    commands.insert_resource(PendingJournal { entries, session });
+}
+
+fn discard_session(
+    In(dir): In<std::path::PathBuf>,
+    mut sessions: ResMut<recovery::RecoverableSessions>
+) {
+    if let Some(session) = sessions.0.iter().find(|s| s.dir == dir) {
+        session.discard().log_err();
+    }
+    sessions.0.retain(|s| s.dir != dir);
+}
+
+fn replay_journal(
+    mut commands: Commands,
+    pending: Option<Res<PendingJournal>>,
+    importers: Query<(), With<persistence::MapImporter>>,
+    map: Query<&Children, With<map::Map>>,
+    layers: Query<(Entity, &map::Layer)>,
+    tilesets: Query<(Entity, &tileset::TileSet)>,
+    tiles: Query<(Entity, &map::Location, &ChildOf), With<tileset::TileRef>>
+) {
+    let Some(pending) = pending else { return };
+    if !importers.is_empty() {
+        return;
+    }
+    let Result::Ok(map_children) = map.single() else { return };
+
+    let mut layer_entities = std::collections::HashMap::new();
+    for (entity, layer) in layers.iter_many(map_children) {
+        layer_entities.insert(layer.name.clone(), entity);
+    }
+    let mut placed = std::collections::HashMap::new();
+    for (entity, location, child_of) in &tiles {
+        placed.insert((child_of.parent(), *location), entity);
+    }
+
+    for entry in &pending.entries {
+        let Some(layer) = layer_entities.get(&entry.layer) else {
+            warn!("recovery journal references unknown layer {}", entry.layer);
+            continue;
+        };
+        if let Some(entity) = placed.remove(&(*layer, entry.location)) {
+            commands.entity(entity).despawn();
+        }
+
+        let recovery::JournalEdit::Insert { tileset, tile, rotation } = &entry.edit else { continue };
+        let Some((tileset_entity, _)) = tilesets
+            .iter_many(map_children)
+            .find(|(_, t)| t.name == *tileset)
+        else {
+            warn!("recovery journal references unknown tileset {}", tileset);
+            continue;
+        };
+
+        let entity = commands
+            .spawn((
+                entry.location,
+                tileset::TileRef { tileset: tileset_entity, tile: *tile },
+                tileset::TileTransform { rotation: *rotation },
+                Transform::default(),
+                Visibility::default(),
+                ChildOf(*layer)
+            ))
+            .id();
+        placed.insert((*layer, entry.location), entity);
+    }
+
+    pending.session.discard().log_err();
+    commands.remove_resource::<PendingJournal>();
+}
+
+fn select_palette_slot(
+    action_state: Query<&ActionState<InputActions>>,
+    palette: Res<TilePalette>,
+    tilesets: Query<&tileset::TileSet>,
+    mut state: ResMut<EditorState>,
+    mut tile_selection: ResMut<TileSelection>,
+    mut egui_contexts: EguiContexts
+) {
+    let Result::Ok(actions) = action_state.single() else { return };
+    if egui_contexts.ctx_mut().is_ok_and(|ctx| ctx.wants_keyboard_input()) {
+        return;
+    }
+
+    let slots = palette.slots();
+    for slot in 0..PALETTE_SLOTS {
+        if !actions.just_pressed(&InputActions::PaletteSlot(slot)) {
+            continue;
+        }
+        let Some(tile_ref) = slots.get(slot) else { return };
+        if tilesets.get(tile_ref.tileset).is_ok_and(|t| t.tiles.contains_key(&tile_ref.tile)) {
+            tile_selection.tiles.clear();
+            tile_selection.tiles.insert(*tile_ref);
+            state.active_tileset = Some(tile_ref.tileset);
+        }
+        return;
+    }
+}
+
+fn record_recent_tiles(
+    tile_selection: Res<TileSelection>,
+    mut palette: ResMut<TilePalette>
+) {
+    if !tile_selection.is_changed() || tile_selection.tiles.len() != 1 {
+        return;
+    }
+    if let Some(tile_ref) = tile_selection.active_tile() {
+        palette.record(*tile_ref);
+    }
+}
+
 fn update_cursor_model(
     mut commands: Commands,
     tile_selection: Res<TileSelection>,
@@ -651,8 +1616,93 @@ fn update_cursor(
     Ok(())
 }
 
+/// Draws `W` in a window of its own
+fn dialog<W: world_editor::ui::widget::BasicWidget + 'static>(
+    world: &mut World,
+    ctx: &egui::Context,
+    title: &str
+) {
+    let id = egui::Id::new(title);
+    egui::Window::new(title)
+        .id(id)
+        .collapsible(false)
+        .show(ctx, |ui| world_editor::ui::widget::basic_widget::<W>(world, ui, id.with("widget")));
+}
+
 pub fn draw_ui(world: &mut World) {
     use world_editor::ui::widget::*;
 
-    
+    let mut contexts = world.query_filtered::<&mut EguiContext, With<PrimaryEguiContext>>();
+    let Result::Ok(mut context) = contexts.single_mut(world) else { return };
+    let ctx = context.get_mut().clone();
+
+    egui::TopBottomPanel::top("menu_bar").show(&ctx, |ui| {
+        basic_widget::<editor_ui::menu::EditorMenuBar>(world, ui, egui::Id::new("menu_bar"));
+        // toasts and the error dialog are placed on the screen, not in the panel
+        basic_widget::<editor_ui::notifications::NotificationToasts>(world, ui, egui::Id::new("notifications"));
+    });
+
+    if world.resource::<EditorState>().right_panel {
+        egui::SidePanel::right("right_panel").show(&ctx, |ui| {
+            let id = egui::Id::new("right_panel");
+            basic_widget::<editor_ui::panel::TilesetViewer>(world, ui, id.with("tileset_viewer"));
+            ui.separator();
+            basic_widget::<editor_ui::problems::ProblemsPanel>(world, ui, id.with("problems"));
+            ui.separator();
+            // the layers list takes the rest of the panel
+            basic_widget::<editor_ui::panel::LayersPanel>(world, ui, id.with("layers"));
+        });
+    }
+
+    let conflicts = world
+        .get_resource::<MergeConflicts>()
+        .is_some_and(|conflicts| conflicts.0.has_conflicts());
+    let map_diff = world.contains_resource::<diff::MapDiff>();
+    if conflicts || map_diff {
+        egui::SidePanel::left("review_panel").show(&ctx, |ui| {
+            let id = egui::Id::new("review_panel");
+            basic_widget::<editor_ui::conflicts::ConflictPanel>(world, ui, id.with("conflicts"));
+            if conflicts && map_diff {
+                ui.separator();
+            }
+            basic_widget::<editor_ui::diff::DiffPanel>(world, ui, id.with("diff"));
+        });
+    }
+
+    let tilesets_changed = world
+        .query_filtered::<(), With<persistence::TilesetFileChanged>>()
+        .iter(world)
+        .next()
+        .is_some();
+    if tilesets_changed {
+        dialog::<editor_ui::tileset_changed::TilesetChangedPrompt>(world, &ctx, "Tilesets changed");
+    }
+
+    let missing_tiles = world
+        .query_filtered::<(), With<tileset::MissingTile>>()
+        .iter(world)
+        .next()
+        .is_some();
+    if missing_tiles {
+        dialog::<editor_ui::remap::RemapDialog>(world, &ctx, "Missing tiles");
+    }
+
+    if world.contains_resource::<RelocateAssets>() {
+        dialog::<editor_ui::relocate::RelocateDialog>(world, &ctx, "Relocate missing assets");
+    }
+
+    if world.contains_resource::<ModelPartsImport>() {
+        dialog::<editor_ui::model_parts::ModelPartsDialog>(world, &ctx, "Add model parts");
+    }
+
+    if world.contains_resource::<TilesetExportOverwrite>() {
+        dialog::<editor_ui::export_overwrite::ExportOverwriteDialog>(world, &ctx, "Replace tileset file");
+    }
+
+    if world
+        .get_resource::<recovery::RecoverableSessions>()
+        .is_some_and(|sessions| !sessions.0.is_empty())
+    {
+        dialog::<editor_ui::recovery::RecoveryPrompt>(world, &ctx, "Recover unsaved work");
+    }
 }
\ No newline at end of file
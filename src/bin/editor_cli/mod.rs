@@ -0,0 +1,317 @@
+use anyhow::{ bail,Context,Result };
+use bevy::prelude::*;
+use std::{
+    collections::BTreeMap,
+    path::{ Path,PathBuf },
+    time::{ Duration,Instant }
+};
+
+use world_editor::{
+    export,
+    persistence::{ self,IoCompleted },
+    render::ThumbnailSettings,
+    tiled,
+    tileset,
+    validate
+};
+
+const USAGE: &str = "\
+usage: editor <command> [options] <files...>
+
+commands:
+    validate [--rules <file>] [--assets <dir>] [--strict] <files...>
+                                           load every map headlessly and report problems;
+                                           warnings only fail with --strict
+    convert [--assets <dir>] [--compact] <input> <output>
+                                           convert between RON, JSON, binary and Tiled formats,
+                                           or export to .glb; Tiled maps use the tile previews
+                                           and .glb the models under the asset root
+    stats <files...>                       print layer, tile and tileset usage
+    replace-tile --tileset <name> --from <id> --to <id> [--dry-run] <files...>
+                                           replace a tile in every layer of the given maps
+    upgrade [--dry-run] <files...>         migrate maps to the current format version and
+                                           repair embedded tilesets
+
+running without a command opens the editor";
+
+/// Runs a subcommand; `None` if there are no arguments
+pub fn run(args: &[String]) -> Option<i32> {
+    let (command, args) = args.split_first()?;
+
+    let result = match command.as_str() {
+        "validate" => validate(args),
+        "convert" => convert(args),
+        "stats" => stats(args),
+        "replace-tile" => replace_tile(args),
+        "upgrade" => upgrade(args),
+        "help" | "-h" | "--help" => {
+            println!("{}", USAGE);
+            Ok(true)
+        }
+        _ => {
+            eprintln!("error: unknown command {:?}\n\n{}", command, USAGE);
+            return Some(2);
+        }
+    };
+
+    Some(match result {
+        Ok(true) => 0,
+        Ok(false) => 1,
+        Err(e) => {
+            eprintln!("error: {:?}\n\n{}", e, USAGE);
+            2
+        }
+    })
+}
+
+/// Splits `args` into `--flag value` options, bare `--switch`es and files
+struct Args {
+    options: BTreeMap<String, String>,
+    switches: Vec<String>,
+    files: Vec<PathBuf>
+}
+
+impl Args {
+    fn parse(args: &[String], with_value: &[&str]) -> Result<Self> {
+        let mut parsed = Args {
+            options: BTreeMap::new(),
+            switches: Vec::new(),
+            files: Vec::new()
+        };
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if !arg.starts_with("--") {
+                parsed.files.push(PathBuf::from(arg));
+            } else if with_value.contains(&arg.as_str()) {
+                let value = args.next().context(format!("missing value for {}", arg))?;
+                parsed.options.insert(arg.clone(), value.clone());
+            } else {
+                parsed.switches.push(arg.clone());
+            }
+        }
+        if parsed.files.is_empty() {
+            bail!("no files given");
+        }
+
+        Ok(parsed)
+    }
+
+    fn switch(&self, name: &str) -> bool {
+        self.switches.iter().any(|s| s == name)
+    }
+
+    fn option(&self, name: &str) -> Result<&str> {
+        self.options
+            .get(name)
+            .map(|s| s.as_str())
+            .context(format!("missing {}", name))
+    }
+
+    fn asset_root(&self) -> tileset::AssetRoot {
+        match self.option("--assets") {
+            Ok(path) => tileset::AssetRoot(PathBuf::from(path)),
+            Err(_) => tileset::AssetRoot::default()
+        }
+    }
+}
+
+/// How long a headless load may take before it is considered stuck
+const LOAD_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Spawns the map into a windowless app, the same way the editor loads it
+fn load_headless(path: &Path, map: persistence::MapFormat) -> Result<()> {
+    let mut app = App::new();
+    app.add_plugins((MinimalPlugins, persistence::Plugin));
+    app.world_mut().spawn(persistence::MapImporter::from_map(path.to_path_buf(), map));
+
+    let started = Instant::now();
+    loop {
+        if started.elapsed() > LOAD_TIMEOUT {
+            bail!("loading did not finish within {} seconds", LOAD_TIMEOUT.as_secs());
+        }
+        app.update();
+
+        let events = app.world().resource::<Events<IoCompleted>>();
+        if let Some(event) = events.iter_current_update_events().next() {
+            return match &event.result {
+                Ok(()) => Ok(()),
+                Err(e) => bail!("{}", e)
+            };
+        }
+    }
+}
+
+fn validate(args: &[String]) -> Result<bool> {
+    let args = Args::parse(args, &["--rules", "--assets"])?;
+    let rules = match args.option("--rules") {
+        Ok(path) => validate::Rules::load(Path::new(path))?,
+        Err(_) => validate::Rules::default()
+    };
+    let asset_root = args.asset_root().0;
+    let strict = args.switch("--strict");
+    let mut ok = true;
+
+    for path in &args.files {
+        let result = persistence::MapFormat::load(path).and_then(|map| {
+            let problems = validate::validate(&map, &rules, &asset_root);
+            load_headless(path, map).map(|_| problems)
+        });
+
+        match result {
+            Ok(problems) if problems.is_empty() => println!("ok: {}", path.to_string_lossy()),
+            Ok(problems) => {
+                for problem in problems {
+                    if problem.severity == validate::Severity::Error || strict {
+                        ok = false;
+                    }
+                    println!("{}: {}", path.to_string_lossy(), problem);
+                }
+            }
+            Err(e) => {
+                ok = false;
+                println!("{}: {:#}", path.to_string_lossy(), e);
+            }
+        }
+    }
+
+    Ok(ok)
+}
+
+fn write_map(map: &persistence::MapFormat, path: &Path, asset_root: &tileset::AssetRoot, compact: bool) -> Result<()> {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("tmx") | Some("tmj") => {
+            let previews_dir = ThumbnailSettings::default().previews_dir;
+            tiled::export(map, asset_root, &previews_dir, path)
+        }
+        Some("glb") => {
+            let glb = export::glb::export_glb(map, &asset_root.0)?;
+            std::fs::write(path, glb).context(format!("writing glb to {:?}", path))
+        }
+        _ => map.write_with_options(path, persistence::SaveOptions {
+            compact,
+            ..default()
+        })
+    }
+}
+
+fn convert(args: &[String]) -> Result<bool> {
+    let args = Args::parse(args, &["--assets"])?;
+    let [input, output] = args.files.as_slice() else {
+        bail!("convert takes an input and an output file");
+    };
+
+    let map = persistence::MapFormat::load(input)?;
+    write_map(&map, output, &args.asset_root(), args.switch("--compact"))?;
+    println!("{} -> {}", input.to_string_lossy(), output.to_string_lossy());
+
+    Ok(true)
+}
+
+fn stats(args: &[String]) -> Result<bool> {
+    let args = Args::parse(args, &[])?;
+
+    for path in &args.files {
+        let map = persistence::MapFormat::load(path)?;
+        println!("{} (version {})", path.to_string_lossy(), map.version);
+
+        for layer in &map.layers {
+            println!("    layer {}: {} tiles", layer.name, layer.tiles.len());
+        }
+
+        let mut usage: BTreeMap<(persistence::SaveId, tileset::TileId), usize> = BTreeMap::new();
+        for tile in map.layers.iter().flat_map(|l| &l.tiles) {
+            *usage.entry((tile.tileset, tile.tile_id)).or_default() += 1;
+        }
+        for (id, tileset) in &map.tilesets {
+            let used = usage.keys().filter(|(t, _)| t == id).count();
+            println!(
+                "    tileset {}: {} tiles, {} used",
+                tileset.name,
+                tileset.tiles.len(),
+                used
+            );
+            for tile_id in &tileset.tile_order {
+                let count = usage.get(&(*id, *tile_id)).copied().unwrap_or(0);
+                match tileset.tiles.get(tile_id) {
+                    Some(tile) => println!("        {:>6} {}", count, tile.name),
+                    None => println!("        {:>6} <tile {} listed but missing>", count, tile_id)
+                }
+            }
+        }
+    }
+
+    Ok(true)
+}
+
+fn replace_tile(args: &[String]) -> Result<bool> {
+    let args = Args::parse(args, &["--tileset", "--from", "--to"])?;
+    let tileset_name = args.option("--tileset")?;
+    let from: tileset::TileId = args.option("--from")?.parse().context("--from")?;
+    let to: tileset::TileId = args.option("--to")?.parse().context("--to")?;
+    let dry_run = args.switch("--dry-run");
+
+    // every map is checked before any is written, so a batch is replaced completely or not at all
+    let mut maps = Vec::new();
+    for path in &args.files {
+        let map = persistence::MapFormat::load(path)?;
+        let Some((id, tileset)) = map.tilesets.iter().find(|(_, t)| t.name == tileset_name) else {
+            println!("{}: no tileset {}", path.to_string_lossy(), tileset_name);
+            continue;
+        };
+        if !tileset.tiles.contains_key(&to) {
+            bail!("{}: tileset {} has no tile {}; no maps were changed", path.to_string_lossy(), tileset_name, to);
+        }
+        let id = *id;
+        maps.push((path, map, id));
+    }
+
+    for (path, mut map, id) in maps {
+        let mut replaced = 0;
+        for tile in map.layers.iter_mut().flat_map(|l| &mut l.tiles) {
+            if tile.tileset == id && tile.tile_id == from {
+                tile.tile_id = to;
+                replaced += 1;
+            }
+        }
+
+        println!("{}: {} tiles replaced", path.to_string_lossy(), replaced);
+        if replaced > 0 && !dry_run {
+            write_map(&map, path, &tileset::AssetRoot::default(), false)?;
+        }
+    }
+
+    Ok(true)
+}
+
+fn upgrade(args: &[String]) -> Result<bool> {
+    let args = Args::parse(args, &[])?;
+    let dry_run = args.switch("--dry-run");
+
+    for path in &args.files {
+        let mut map = persistence::MapFormat::load(path)?;
+        let from = map.version;
+
+        let mut changed = map.upgrade()?;
+        if changed {
+            println!("{}: version {} -> {}", path.to_string_lossy(), from, map.version);
+        }
+        // tilesets with their own file are not written with the map
+        for (_, tileset) in map.tilesets.iter_mut().filter(|(id, _)| !map.tileset_files.contains_key(*id)) {
+            for problem in tileset.repair() {
+                println!("{}: tileset {}: repaired: {}", path.to_string_lossy(), tileset.name, problem);
+                changed = true;
+            }
+        }
+
+        if !changed {
+            println!("{}: up to date", path.to_string_lossy());
+            continue;
+        }
+        if !dry_run {
+            write_map(&map, path, &tileset::AssetRoot::default(), false)?;
+        }
+    }
+
+    Ok(true)
+}
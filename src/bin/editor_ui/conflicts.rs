@@ -0,0 +1,76 @@
+use bevy::prelude::*;
+use bevy_egui::egui;
+
+use world_editor::{ merge,ui::widget::* };
+
+use crate::{ EditorUiEvent,MergeConflicts };
+
+fn describe(tile: &Option<merge::TileKey>) -> String {
+    match tile {
+        Some(tile) => format!("{} #{} {:?}", tile.tileset, tile.tile_id, tile.rotation),
+        None => "empty".into()
+    }
+}
+
+#[derive(Default, Clone)]
+pub struct ConflictPanel;
+
+impl BasicWidget for ConflictPanel {
+    fn new(_world: &mut World, _ui: &egui::Ui) -> Self {
+        Self::default()
+    }
+
+    fn draw(&mut self, world: &mut World, ui: &mut egui::Ui, _id: egui::Id) {
+        let Some(conflicts) = world.get_resource::<MergeConflicts>() else { return };
+        if !conflicts.0.has_conflicts() {
+            return;
+        }
+
+        let mut event = None;
+        ui.heading(format!("Merge conflicts ({})", conflicts.0.hexes.len() + conflicts.0.tiles.len()));
+
+        egui::ScrollArea::vertical().max_height(240.0).show(ui, |ui| {
+            egui::Grid::new("merge_conflicts").striped(true).show(ui, |ui| {
+                for (i, conflict) in conflicts.0.hexes.iter().enumerate() {
+                    ui.label(format!(
+                        "{} ({}, {})",
+                        conflict.layer, conflict.location.x, conflict.location.y
+                    ));
+                    if ui
+                        .button("Ours")
+                        .on_hover_text(describe(&conflict.ours))
+                        .clicked()
+                    {
+                        event = Some(EditorUiEvent::ResolveConflict(i, false));
+                    }
+                    if ui
+                        .button("Theirs")
+                        .on_hover_text(describe(&conflict.theirs))
+                        .clicked()
+                    {
+                        event = Some(EditorUiEvent::ResolveConflict(i, true));
+                    }
+                    ui.end_row();
+                }
+            });
+
+            egui::Grid::new("merge_tile_conflicts").striped(true).show(ui, |ui| {
+                for (i, (tileset, tile)) in conflicts.0.tiles.iter().enumerate() {
+                    ui.label(format!("tile {} of {} differs; kept ours", tile, tileset));
+                    if ui
+                        .button("OK")
+                        .on_hover_text("Keep our definition of the tile")
+                        .clicked()
+                    {
+                        event = Some(EditorUiEvent::AcknowledgeTileConflict(i));
+                    }
+                    ui.end_row();
+                }
+            });
+        });
+
+        if let Some(event) = event {
+            world.send_event(event);
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use bevy::prelude::*;
+use bevy_egui::egui;
+
+use world_editor::{ diff,ui::widget::* };
+
+use crate::EditorUiEvent;
+
+#[derive(Default, Clone)]
+pub struct DiffPanel;
+
+impl BasicWidget for DiffPanel {
+    fn new(_world: &mut World, _ui: &egui::Ui) -> Self {
+        Self::default()
+    }
+
+    fn draw(&mut self, world: &mut World, ui: &mut egui::Ui, _id: egui::Id) {
+        let Some(mut map_diff) = world.get_resource_mut::<diff::MapDiff>() else { return };
+        let mut event = None;
+
+        ui.horizontal(|ui| {
+            ui.heading(format!("Changes ({})", map_diff.changes.len()));
+            if ui.button("Close").clicked() {
+                event = Some(EditorUiEvent::CloseDiff);
+            }
+        });
+        ui.label(format!("compared with {}", map_diff.path.to_string_lossy()));
+
+        let mut layers: Vec<String> = map_diff.changes.iter().map(|c| c.layer.clone()).collect();
+        layers.dedup();
+
+        egui::ScrollArea::vertical().max_height(320.0).show(ui, |ui| {
+            for layer in layers {
+                let mut visible = !map_diff.hidden_layers.contains(&layer);
+                if ui.checkbox(&mut visible, &layer).changed() {
+                    if visible {
+                        map_diff.hidden_layers.remove(&layer);
+                    } else {
+                        map_diff.hidden_layers.insert(layer.clone());
+                    }
+                }
+                if !visible {
+                    continue;
+                }
+
+                ui.indent(&layer, |ui| {
+                    for change in map_diff.changes.iter().filter(|c| c.layer == layer) {
+                        let [r, g, b, _] = change.kind.color().to_srgba().to_u8_array();
+                        let text = egui::RichText::new(format!(
+                            "{:?} ({}, {})",
+                            change.kind, change.location.x, change.location.y
+                        ))
+                        .color(egui::Color32::from_rgb(r, g, b));
+                        if ui.selectable_label(false, text).clicked() {
+                            event = Some(EditorUiEvent::FocusLocation(change.location));
+                        }
+                    }
+                });
+            }
+        });
+
+        if let Some(event) = event {
+            world.send_event(event);
+        }
+    }
+}
@@ -0,0 +1,31 @@
+use bevy::prelude::*;
+use bevy_egui::egui;
+
+use world_editor::ui::widget::*;
+
+use crate::{ EditorUiEvent,TilesetExportOverwrite };
+
+/// Asks before a tileset export replaces an existing file
+#[derive(Default, Clone)]
+pub struct ExportOverwriteDialog;
+
+impl BasicWidget for ExportOverwriteDialog {
+    fn new(_world: &mut World, _ui: &egui::Ui) -> Self {
+        Self::default()
+    }
+
+    fn draw(&mut self, world: &mut World, ui: &mut egui::Ui, _id: egui::Id) {
+        let Some(export) = world.get_resource::<TilesetExportOverwrite>() else { return };
+        let (tileset, path) = (export.tileset, export.path.clone());
+
+        ui.label(format!("{} already exists. Replace it?", path.to_string_lossy()));
+        ui.horizontal(|ui| {
+            if ui.button("Replace").clicked() {
+                world.send_event(EditorUiEvent::TilesetExport(tileset, path));
+            }
+            if ui.button("Cancel").clicked() {
+                world.remove_resource::<TilesetExportOverwrite>();
+            }
+        });
+    }
+}
@@ -1,7 +1,7 @@
 use bevy::prelude::*;
 use bevy_egui::egui::{self, Ui};
 
-use world_editor::{ filepicker, prelude::*, ui::widget::* };
+use world_editor::{ filepicker, persistence, prelude::*, ui::widget::* };
 
 use crate::EditorUiEvent;
 
@@ -21,6 +21,22 @@ impl BasicWidget for EditorMenuBar {
                 basic_widget::<MapOpen>(world, ui, id.with("map_open"));
                 basic_widget::<MapSave>(world, ui, id.with("map_save"));
                 basic_widget::<MapSaveAs>(world, ui, id.with("map_save_as"));
+                let mut options = world.resource_mut::<persistence::SaveOptions>();
+                ui.checkbox(&mut options.compact, "One tile per line")
+                    .on_hover_text("Write RON and JSON maps with each tile on its own line");
+                ui.checkbox(&mut options.embed_tilesets, "Embed tilesets")
+                    .on_hover_text("Store a copy of tilesets that have their own file in the map");
+                ui.separator();
+                basic_widget::<MapExportScene>(world, ui, id.with("map_export_scene"));
+                basic_widget::<MapExportGlb>(world, ui, id.with("map_export_glb"));
+                basic_widget::<MapExportTiled>(world, ui, id.with("map_export_tiled"));
+                ui.separator();
+                basic_widget::<MapCompare>(world, ui, id.with("map_compare"));
+                if ui.button("Relocate missing assets...").clicked() {
+                    world.send_event(EditorUiEvent::FindMissingAssets);
+                    ui.close();
+                }
+                ui.separator();
                 basic_widget::<MapClose>(world, ui, id.with("map_close"));
                 basic_widget::<Quit>(world, ui, id.with("quit"));
             });
@@ -106,6 +122,107 @@ pub struct MapSave;
 #[derive(Default, Clone)]
 pub struct MapSaveAs;
 
+#[derive(Default, Clone)]
+pub struct MapExportScene;
+
+impl BasicWidget for MapExportScene {
+    fn new(_world: &mut World, _ui: &egui::Ui) -> Self {
+        Self::default()
+    }
+
+    fn draw(&mut self, world: &mut World, ui: &mut egui::Ui, _id: egui::Id) {
+        if !ui
+            .button("Export as Bevy Scene...")
+            .clicked()
+        {
+            return;
+        }
+
+        world.spawn(
+            filepicker::Picker::save_dialog(crate::PickerEvent::MapExportScene(None))
+                .add_filter("Bevy Scene", &["scn.ron"])
+                .build()
+        );
+        ui.close();
+    }
+}
+
+#[derive(Default, Clone)]
+pub struct MapExportGlb;
+
+impl BasicWidget for MapExportGlb {
+    fn new(_world: &mut World, _ui: &egui::Ui) -> Self {
+        Self::default()
+    }
+
+    fn draw(&mut self, world: &mut World, ui: &mut egui::Ui, _id: egui::Id) {
+        if !ui
+            .button("Export as glTF Binary...")
+            .clicked()
+        {
+            return;
+        }
+
+        world.spawn(
+            filepicker::Picker::save_dialog(crate::PickerEvent::MapExportGlb(None))
+                .add_filter("glTF Binary", &["glb"])
+                .build()
+        );
+        ui.close();
+    }
+}
+
+#[derive(Default, Clone)]
+pub struct MapExportTiled;
+
+impl BasicWidget for MapExportTiled {
+    fn new(_world: &mut World, _ui: &egui::Ui) -> Self {
+        Self::default()
+    }
+
+    fn draw(&mut self, world: &mut World, ui: &mut egui::Ui, _id: egui::Id) {
+        if !ui
+            .button("Export to Tiled...")
+            .clicked()
+        {
+            return;
+        }
+
+        world.spawn(
+            filepicker::Picker::save_dialog(crate::PickerEvent::MapExportTiled(None))
+                .add_filter("Tiled JSON", &["tmj"])
+                .add_filter("Tiled XML", &["tmx"])
+                .build()
+        );
+        ui.close();
+    }
+}
+
+#[derive(Default, Clone)]
+pub struct MapCompare;
+
+impl BasicWidget for MapCompare {
+    fn new(_world: &mut World, _ui: &egui::Ui) -> Self {
+        Self::default()
+    }
+
+    fn draw(&mut self, world: &mut World, ui: &mut egui::Ui, _id: egui::Id) {
+        if !ui
+            .button("Compare with...")
+            .clicked()
+        {
+            return;
+        }
+
+        world.spawn(
+            filepicker::Picker::new(crate::PickerEvent::MapCompare(None))
+                .add_filter("Map", &["ron", "json", "bin", "zst", "gz", "tmx", "tmj"])
+                .build()
+        );
+        ui.close();
+    }
+}
+
 #[derive(Default, Clone)]
 pub struct MapClose;
 
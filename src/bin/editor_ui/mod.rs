@@ -1,3 +1,13 @@
+pub mod conflicts;
+pub mod diff;
+pub mod export_overwrite;
 pub mod menu;
+pub mod model_parts;
+pub mod notifications;
 pub mod tile_properties;
-pub mod panel;
\ No newline at end of file
+pub mod tileset_changed;
+pub mod panel;
+pub mod problems;
+pub mod recovery;
+pub mod relocate;
+pub mod remap;
\ No newline at end of file
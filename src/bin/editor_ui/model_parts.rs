@@ -0,0 +1,63 @@
+use bevy::prelude::*;
+use bevy_egui::egui;
+
+use world_editor::{ tileset,ui::widget::* };
+
+use crate::{ EditorUiEvent,ModelPartsImport };
+
+/// Picks the scenes and nodes of a glTF file to add as tiles
+#[derive(Default, Clone)]
+pub struct ModelPartsDialog;
+
+impl BasicWidget for ModelPartsDialog {
+    fn new(_world: &mut World, _ui: &egui::Ui) -> Self {
+        Self::default()
+    }
+
+    fn draw(&mut self, world: &mut World, ui: &mut egui::Ui, id: egui::Id) {
+        let Some(mut import) = world.get_resource_mut::<ModelPartsImport>() else { return };
+
+        ui.heading(format!("Add tiles from {}", import.path.to_string_lossy()));
+        ui.horizontal(|ui| {
+            if ui.button("All").clicked() {
+                import.parts.iter_mut().for_each(|(_, selected)| *selected = true);
+            }
+            if ui.button("None").clicked() {
+                import.parts.iter_mut().for_each(|(_, selected)| *selected = false);
+            }
+        });
+
+        egui::ScrollArea::vertical()
+            .max_height(300.0)
+            .id_salt(id.with("parts"))
+            .show(ui, |ui| {
+                for (part, selected) in import.parts.iter_mut() {
+                    let label = match part {
+                        tileset::ModelPart::Scene(name) => format!("scene: {}", name),
+                        tileset::ModelPart::Node(name) => format!("node: {}", name)
+                    };
+                    ui.checkbox(selected, label);
+                }
+            });
+
+        let parts: Vec<tileset::ModelPart> = import
+            .parts
+            .iter()
+            .filter(|(_, selected)| *selected)
+            .map(|(part, _)| part.clone())
+            .collect();
+        let (tileset, path) = (import.tileset, import.path.clone());
+
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(!parts.is_empty(), egui::Button::new(format!("Add {} tiles", parts.len())))
+                .clicked()
+            {
+                world.send_event(EditorUiEvent::AddModelParts { tileset, path, parts });
+            }
+            if ui.button("Cancel").clicked() {
+                world.remove_resource::<ModelPartsImport>();
+            }
+        });
+    }
+}
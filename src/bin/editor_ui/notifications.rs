@@ -0,0 +1,121 @@
+use bevy::prelude::*;
+use bevy_egui::egui;
+use std::time::Duration;
+
+use world_editor::{ persistence::{ IoCompleted,IoError,IoOperation,MapImporter },ui::widget::* };
+
+const TOAST_DURATION: Duration = Duration::from_secs(4);
+
+#[derive(Debug, Clone)]
+pub struct Toast {
+    pub text: String,
+    pub error: bool,
+    pub expires: Duration
+}
+
+#[derive(Resource, Debug, Default)]
+pub struct Notifications {
+    pub toasts: Vec<Toast>,
+    /// Shown in a modal dialog until acknowledged
+    pub error: Option<(String, IoError)>
+}
+
+impl Notifications {
+    pub fn push(&mut self, now: Duration, event: &IoCompleted) {
+        let action = match event.operation {
+            IoOperation::MapSave => "save map",
+            IoOperation::Autosave => "autosave map",
+            IoOperation::MapLoad => "load map",
+            IoOperation::TilesetImport => "import tileset",
+            IoOperation::TilesetExport => "export tileset"
+        };
+        let path = event.path.to_string_lossy();
+        // autosaves run every few minutes; only failures are worth a toast
+        let quiet = event.operation == IoOperation::Autosave;
+
+        match &event.result {
+            Ok(()) if quiet => (),
+            Ok(()) => self.toasts.push(Toast {
+                text: format!("{}: {}", action, path),
+                error: false,
+                expires: now + TOAST_DURATION
+            }),
+            Err(e) => {
+                self.toasts.push(Toast {
+                    text: format!("failed to {}: {}", action, path),
+                    error: true,
+                    expires: now + TOAST_DURATION
+                });
+                if !quiet {
+                    self.error = Some((format!("Failed to {} {}", action, path), e.clone()));
+                }
+            }
+        }
+    }
+}
+
+#[derive(Default, Clone)]
+pub struct NotificationToasts;
+
+impl BasicWidget for NotificationToasts {
+    fn new(_world: &mut World, _ui: &egui::Ui) -> Self {
+        Self::default()
+    }
+
+    fn draw(&mut self, world: &mut World, ui: &mut egui::Ui, id: egui::Id) {
+        let now = world.resource::<Time>().elapsed();
+        let loads: Vec<(String, f32)> = world
+            .query::<&MapImporter>()
+            .iter(world)
+            .map(|importer| (importer.path().to_string_lossy().into_owned(), importer.progress()))
+            .collect();
+        let Some(mut notifications) = world.get_resource_mut::<Notifications>() else { return };
+        notifications.toasts.retain(|t| t.expires > now);
+
+        egui::Area::new(id)
+            .anchor(egui::Align2::RIGHT_BOTTOM, egui::vec2(-8.0, -8.0))
+            .show(ui.ctx(), |ui| {
+                for (path, progress) in &loads {
+                    egui::Frame::popup(ui.style()).show(ui, |ui| {
+                        ui.label(format!("loading {}", path));
+                        ui.add(egui::ProgressBar::new(*progress).show_percentage());
+                    });
+                }
+                for toast in &notifications.toasts {
+                    egui::Frame::popup(ui.style()).show(ui, |ui| {
+                        if toast.error {
+                            ui.colored_label(ui.visuals().error_fg_color, &toast.text);
+                        } else {
+                            ui.label(&toast.text);
+                        }
+                    });
+                }
+            });
+
+        let Some((title, error)) = notifications.error.clone() else { return };
+        let mut open = true;
+        egui::Window::new(title)
+            .id(id.with("error"))
+            .collapsible(false)
+            .resizable(false)
+            .open(&mut open)
+            .show(ui.ctx(), |ui| {
+                for (i, cause) in error.chain.iter().enumerate() {
+                    if i == 0 {
+                        ui.strong(cause);
+                    } else {
+                        ui.label(format!("caused by: {}", cause));
+                    }
+                }
+                if let Some((line, column)) = error.location {
+                    ui.label(format!("at line {}, column {}", line, column));
+                }
+                if ui.button("OK").clicked() {
+                    notifications.error = None;
+                }
+            });
+        if !open {
+            notifications.error = None;
+        }
+    }
+}
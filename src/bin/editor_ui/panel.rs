@@ -63,6 +63,16 @@ impl BasicWidget for TilesetViewer {
     }
 
     fn draw(&mut self, world: &mut World, ui: &mut egui::Ui, id: egui::Id) {
+        basic_widget::<TilePaletteStrip>(world, ui, id.with("palette"));
+        basic_widget::<RotationStrip>(world, ui, id.with("rotations"));
+        ui.horizontal(|ui| {
+            ui.label("🔍");
+            let mut search = world.resource_mut::<crate::TileSearch>();
+            ui.add(egui::TextEdit::singleline(&mut search.0).hint_text("name or tag"));
+            if !search.0.is_empty() && ui.small_button("✖").clicked() {
+                search.0.clear();
+            }
+        });
         egui::ScrollArea::vertical()
             .max_height(self.height)
             .auto_shrink([false, false])
@@ -77,6 +87,111 @@ impl BasicWidget for TilesetViewer {
     }
 }
 
+/// Favourite and recently used tiles; the number shown is the hotkey
+#[derive(Default, Clone)]
+pub struct TilePaletteStrip;
+
+impl BasicWidget for TilePaletteStrip {
+    fn new(_world: &mut World, _ui: &egui::Ui) -> Self {
+        Self::default()
+    }
+
+    fn draw(&mut self, world: &mut World, ui: &mut egui::Ui, _id: egui::Id) {
+        let palette = world.resource::<crate::TilePalette>();
+        let selection = world.resource::<crate::TileSelection>();
+        let slots = palette.slots();
+        if slots.is_empty() {
+            return;
+        }
+
+        let mut clicked = None;
+        ui.horizontal(|ui| {
+            ui.spacing_mut().item_spacing = egui::vec2(2.0, 0.0);
+
+            for (slot, tile_ref) in slots.iter().enumerate() {
+                let Some(tile) = world
+                    .get::<tileset::TileSet>(tile_ref.tileset)
+                    .and_then(|tileset| tileset.tiles.get(&tile_ref.tile))
+                else {
+                    continue;
+                };
+                let Some(texture) = tile.egui_texture_id else { continue };
+
+                let button = egui::ImageButton::new((texture, egui::Vec2::splat(32.0)))
+                    .selected(selection.tiles.contains(tile_ref));
+                let res = ui.add(button).on_hover_text(&tile.name);
+                ui.painter().text(
+                    res.rect.left_top() + egui::vec2(2.0, 1.0),
+                    egui::Align2::LEFT_TOP,
+                    (slot + 1).to_string(),
+                    egui::FontId::monospace(10.0),
+                    ui.visuals().strong_text_color()
+                );
+                if palette.is_favourite(tile_ref) {
+                    ui.painter().text(
+                        res.rect.right_top() + egui::vec2(-2.0, 1.0),
+                        egui::Align2::RIGHT_TOP,
+                        "★",
+                        egui::FontId::proportional(10.0),
+                        egui::Color32::GOLD
+                    );
+                }
+                if res.clicked() {
+                    clicked = Some(*tile_ref);
+                }
+            }
+        });
+
+        if let Some(tile_ref) = clicked {
+            let mut selection = world.resource_mut::<crate::TileSelection>();
+            selection.tiles.clear();
+            selection.tiles.insert(tile_ref);
+            world.resource_mut::<EditorState>().active_tileset = Some(tile_ref.tileset);
+        }
+        ui.separator();
+    }
+}
+
+/// The selected tile in all six orientations; clicking one rotates the cursor
+#[derive(Default, Clone)]
+pub struct RotationStrip;
+
+impl BasicWidget for RotationStrip {
+    fn new(_world: &mut World, _ui: &egui::Ui) -> Self {
+        Self::default()
+    }
+
+    fn draw(&mut self, world: &mut World, ui: &mut egui::Ui, _id: egui::Id) {
+        let Some(tile_ref) = world.resource::<crate::TileSelection>().active_tile().copied() else { return };
+        let mut cursor = world.query_filtered::<&mut tileset::TileTransform, With<crate::MapCursor>>();
+        let Ok(current) = cursor.single(world).map(|t| t.rotation) else { return };
+
+        let mut clicked = None;
+        ui.horizontal(|ui| {
+            ui.spacing_mut().item_spacing = egui::vec2(2.0, 0.0);
+            let mut thumbnails = world.resource_mut::<tileset::RotatedThumbnails>();
+
+            for rotation in tileset::TileRotation::ALL {
+                let size = egui::Vec2::splat(32.0);
+                let res = match thumbnails.get(tile_ref, rotation) {
+                    Some(texture) => ui.add(egui::ImageButton::new((texture, size)).selected(rotation == current)),
+                    None => ui.add_sized(size, egui::Spinner::new())
+                };
+                if res.on_hover_text(format!("{:?}", rotation)).clicked() {
+                    clicked = Some(rotation);
+                }
+            }
+        });
+
+        if let Some(rotation) = clicked {
+            if let Ok(mut transform) = cursor.single_mut(world) {
+                transform.rotation = rotation;
+            }
+        }
+        ui.separator();
+    }
+}
+
 #[derive(Default, Clone)]
 pub struct TilesetPanelFooter;
 
@@ -88,10 +203,141 @@ impl BasicWidget for TilesetPanelFooter {
     fn draw(&mut self, world: &mut World, ui: &mut egui::Ui, id: egui::Id) {
         ui.horizontal(|ui| {
             basic_widget::<TilesetAddTiles>(world, ui, id.with("add_tiles"));
+            basic_widget::<TilesetAddModelParts>(world, ui, id.with("add_model_parts"));
+            basic_widget::<TilesetFromFolder>(world, ui, id.with("from_folder"));
+            basic_widget::<TilesetResync>(world, ui, id.with("resync"));
+            basic_widget::<TilesetPreviewToggle>(world, ui, id.with("previews"));
+            basic_widget::<TilesetRepair>(world, ui, id.with("repair"));
         });
     }
 }
 
+#[derive(Default, Clone)]
+pub struct TilesetFromFolder;
+
+impl BasicWidget for TilesetFromFolder {
+    fn new(_world: &mut World, _ui: &egui::Ui) -> Self {
+        Self::default()
+    }
+
+    fn draw(&mut self, world: &mut World, ui: &mut egui::Ui, _id: egui::Id) {
+        if ui
+            .button("📁")
+            .on_hover_text("New tileset from folder")
+            .clicked()
+        {
+            world.spawn(
+                filepicker::Picker::folder_dialog(crate::PickerEvent::TilesetFromFolder(None))
+                    .build()
+            );
+        }
+    }
+}
+
+#[derive(Default, Clone)]
+pub struct TilesetPreviewToggle;
+
+impl BasicWidget for TilesetPreviewToggle {
+    fn new(_world: &mut World, _ui: &egui::Ui) -> Self {
+        Self::default()
+    }
+
+    fn draw(&mut self, world: &mut World, ui: &mut egui::Ui, _id: egui::Id) {
+        let state = world.resource::<EditorState>();
+        let Some(tileset_id) = state.active_tileset else { return };
+        let Some(mut tileset) = world.get_mut::<tileset::TileSet>(tileset_id) else { return };
+
+        let mut use_previews = tileset.use_previews;
+        if ui
+            .checkbox(&mut use_previews, "Previews")
+            .on_hover_text("Show preview images instead of rendered thumbnails")
+            .changed()
+        {
+            tileset.set_use_previews(use_previews);
+        }
+    }
+}
+
+#[derive(Default, Clone)]
+pub struct TilesetResync;
+
+impl BasicWidget for TilesetResync {
+    fn new(_world: &mut World, _ui: &egui::Ui) -> Self {
+        Self::default()
+    }
+
+    fn draw(&mut self, world: &mut World, ui: &mut egui::Ui, _id: egui::Id) {
+        let state = world.resource::<EditorState>();
+        let tileset_id = state.active_tileset.filter(|id| {
+            world
+                .get::<tileset::TileSet>(*id)
+                .is_some_and(|t| t.source_dir.is_some())
+        });
+
+        if ui
+            .add_enabled(tileset_id.is_some(), egui::Button::new("⟳"))
+            .on_hover_text("Re-sync tileset with its folder")
+            .clicked()
+        {
+            world.send_event(EditorUiEvent::TilesetResync(tileset_id.unwrap()));
+        }
+    }
+}
+
+/// Shown while the active tileset has integrity problems
+#[derive(Default, Clone)]
+pub struct TilesetRepair;
+
+impl BasicWidget for TilesetRepair {
+    fn new(_world: &mut World, _ui: &egui::Ui) -> Self {
+        Self::default()
+    }
+
+    fn draw(&mut self, world: &mut World, ui: &mut egui::Ui, _id: egui::Id) {
+        let state = world.resource::<EditorState>();
+        let Some(tileset_id) = state.active_tileset else { return };
+        let Some(integrity) = world.get::<tileset::TilesetIntegrity>(tileset_id) else { return };
+
+        let problems: Vec<String> = integrity.0.iter().map(|p| p.to_string()).collect();
+        if ui
+            .button(egui::RichText::new("⚠ Repair").color(ui.visuals().warn_fg_color))
+            .on_hover_text(problems.join("\n"))
+            .clicked()
+        {
+            world.send_event(EditorUiEvent::RepairTileset(tileset_id));
+        }
+    }
+}
+
+#[derive(Default, Clone)]
+pub struct TilesetAddModelParts;
+
+impl BasicWidget for TilesetAddModelParts {
+    fn new(_world: &mut World, _ui: &egui::Ui) -> Self {
+        Self::default()
+    }
+
+    fn draw(&mut self, world: &mut World, ui: &mut egui::Ui, _id: egui::Id) {
+        let state = world.resource::<EditorState>();
+        let tileset_id = state.active_tileset;
+
+        if ui
+            .add_enabled(tileset_id.is_some(), egui::Button::new("🧩"))
+            .on_hover_text("Add tiles from the scenes or nodes of a glTF file")
+            .clicked()
+        {
+            world.spawn(
+                filepicker::Picker::new(crate::PickerEvent::AddModelParts {
+                    tileset_id: tileset_id.unwrap(),
+                    file: None
+                })
+                .add_filter("glTF", &["glb", "gltf"])
+                .build()
+            );
+        }
+    }
+}
+
 #[derive(Default, Clone)]
 pub struct TilesetAddTiles;
 
@@ -119,7 +365,7 @@ impl BasicWidget for TilesetAddTiles {
         {
             world.spawn(
                 filepicker::Picker::for_many(crate::PickerEvent::AddTiles { tileset_id, files: None })
-                .add_filter("GLTF", &["glb"])
+                .add_filter("3D models", tileset::MODEL_EXTENSIONS)
                 .build()
             );
         }
@@ -130,6 +376,10 @@ pub struct TilePicker<'w: 'static, 's: 'static> {
     system_state: SystemState<(
         Res<'w, EditorState>,
         ResMut<'w, crate::TileSelection>,
+        ResMut<'w, crate::TilePalette>,
+        Res<'w, crate::TileSearch>,
+        ResMut<'w, tileset::RotatedThumbnails>,
+        Query<'w, 's, &'static tileset::TileTransform, With<crate::MapCursor>>,
         Query<'w, 's, &'static mut tileset::TileSet> 
     )>,
     tileset: Option<Entity>,
@@ -152,7 +402,8 @@ impl<'w, 's> BasicWidget for TilePicker<'w, 's> {
     fn draw(&mut self, world: &mut World, ui: &mut egui::Ui, id: egui::Id) {
         use tileset::TileRef;
 
-        let (state, mut selection, mut tilesets) = self.system_state.get_mut(world);
+        let (state, mut selection, mut palette, search, mut rotated, cursor, mut tilesets) = self.system_state.get_mut(world);
+        let rotation = cursor.single().map(|t| t.rotation).unwrap_or_default();
 
         if self.tileset != state.active_tileset {
             self.tileset = state.active_tileset;
@@ -176,89 +427,146 @@ impl<'w, 's> BasicWidget for TilePicker<'w, 's> {
         };
 
         let tile_size = egui::Vec2::splat(48.0);
-        let layout = egui::Layout::left_to_right(egui::Align::Min).with_main_wrap(true);
         let drag_layer = egui::LayerId::new(egui::Order::Tooltip, id.with("dragging"));
 
-        ui.with_layout(layout, |ui| {
-            let mut spacing = ui.spacing_mut();
-            spacing.item_spacing = egui::vec2(0.0, 0.0);
-            spacing.button_padding = egui::vec2(0.0, 0.0);
-            
-            let mut visuals = ui.visuals_mut();
-            visuals.widgets.inactive.weak_bg_fill = egui::Color32::TRANSPARENT;
+        let mut groups: Vec<(&str, Vec<(usize, tileset::TileId)>)> = Vec::new();
+        for (index, tile_id) in tileset.tile_order.iter().enumerate() {
+            let Some(tile) = tileset.tiles.get(tile_id) else {
+                warn!("unknown tile if in tileset order; \
+                tileset \"{}\" ({:?}), tile id {}", tileset.name, tileset_id, tile_id);
+                continue;
+            };
+            if !tile.matches_search(&search.0) {
+                continue;
+            }
+            match groups.iter_mut().find(|(category, _)| *category == tile.category) {
+                Some((_, tiles)) => tiles.push((index, *tile_id)),
+                None => groups.push((tile.category.as_str(), vec![(index, *tile_id)]))
+            }
+        }
 
-            for (index, tile_id) in tileset.tile_order.iter().enumerate() {
-                let Some(tile) = tileset.tiles.get(tile_id) else {
-                    warn!("unknown tile if in tileset order; \
-                    tileset \"{}\" ({:?}), tile id {}", tileset.name, tileset_id, tile_id);
-                    continue;
-                };
-                let Some(texture) = tile.egui_texture_id else { continue };
-                let tile_ref = TileRef {
-                    tileset: tileset_id,
-                    tile: *tile_id
+        let mut tile_button = |ui: &mut egui::Ui, index: usize, tile_id: tileset::TileId| {
+            let tile = &tileset.tiles[&tile_id];
+            let Some(texture) = tile.egui_texture_id else { return };
+            let tile_ref = TileRef {
+                tileset: tileset_id,
+                tile: tile_id
+            };
+            let selected = selection.tiles.contains(&tile_ref);
+            // selected tiles are shown the way the cursor will place them
+            let texture = match rotation {
+                tileset::TileRotation::None => texture,
+                _ if !selected => texture,
+                _ => rotated.get(tile_ref, rotation).unwrap_or(texture)
+            };
+
+            let button = egui::ImageButton::new((texture, tile_size))
+                .selected(selected)
+                .sense(egui::Sense::click_and_drag());
+
+            if self.drag_start.is_some() {
+                let res = if selected {
+                    ui.scope_builder(UiBuilder::new().layer_id(drag_layer), |ui| ui.add(button)).response
+                } else {
+                    ui.add(button)
                 };
-                let selected = selection.tiles.contains(&tile_ref);
 
-                let button = egui::ImageButton::new((texture, tile_size))
-                    .selected(selected)
-                    .sense(egui::Sense::click_and_drag());
-
-                if self.drag_start.is_some() {
-                    let res = if selected {
-                        ui.scope_builder(UiBuilder::new().layer_id(drag_layer), |ui| ui.add(button)).response
-                    } else {
-                        ui.add(button)
-                    };
+                if res.hovered() && ui.input(|i| i.pointer.any_released()) {
+                    drop_index = Some(index);
+                    self.drag_start = None;
+                }
+                return;
+            }
 
-                    if res.hovered() && ui.input(|i| i.pointer.any_released()) {
-                        drop_index = Some(index);
-                        self.drag_start = None;
-                    }
-                    continue;
+            let res = ui.add(button);
+            let res = if tile.tags.is_empty() {
+                res.on_hover_text(&tile.name)
+            } else {
+                res.on_hover_text(format!("{}\n{}", tile.name, tile.tags.join(", ")))
+            };
+            res.context_menu(|ui| {
+                let label = if palette.is_favourite(&tile_ref) {
+                    "Remove from favourites"
+                } else {
+                    "Add to favourites"
+                };
+                if ui.button(label).clicked() {
+                    palette.toggle_favourite(tile_ref);
+                    ui.close();
                 }
+            });
 
-                let res = ui.add(button);
-                if res.clicked() {
-                    if modifiers.shift_only() {
-                        deselect_range = self.last_range.take();
-                        if let Some(start) = &self.start_range {
-                            let range = if *start < index {
-                                *start..=index
-                            } else {
-                                index..=*start
-                            };
-                            select_range = Some(range);
-                        } else {
-                            selection.tiles.insert(tile_ref);
-                            self.start_range = Some(index);
-                        }
-                    } else if modifiers.command_only() {
-                        if selected {
-                            selection.tiles.remove(&tile_ref);
-                            self.start_range = None;
+            if res.clicked() {
+                if modifiers.shift_only() {
+                    deselect_range = self.last_range.take();
+                    if let Some(start) = &self.start_range {
+                        let range = if *start < index {
+                            *start..=index
                         } else {
-                            selection.tiles.insert(tile_ref);
-                            self.start_range = Some(index);
-                        }
-                        self.last_range = None;
+                            index..=*start
+                        };
+                        select_range = Some(range);
                     } else {
-                        selection.tiles.clear();
                         selection.tiles.insert(tile_ref);
                         self.start_range = Some(index);
-                        self.last_range = None;
                     }
-                } else if res.drag_delta().length() > 4.0 {
-                    if !selected {
-                        selection.tiles.clear();
-                        selection.tiles.insert(tile_ref);
+                } else if modifiers.command_only() {
+                    if selected {
+                        selection.tiles.remove(&tile_ref);
                         self.start_range = None;
-                        self.last_range = None;
+                    } else {
+                        selection.tiles.insert(tile_ref);
+                        self.start_range = Some(index);
                     }
-                    self.drag_start = Some(res.rect.center());
+                    self.last_range = None;
+                } else {
+                    selection.tiles.clear();
+                    selection.tiles.insert(tile_ref);
+                    self.start_range = Some(index);
+                    self.last_range = None;
+                }
+            } else if res.drag_delta().length() > 4.0 {
+                if !selected {
+                    selection.tiles.clear();
+                    selection.tiles.insert(tile_ref);
+                    self.start_range = None;
+                    self.last_range = None;
                 }
+                self.drag_start = Some(res.rect.center());
             }
-        });
+        };
+
+        let mut tile_grid = |ui: &mut egui::Ui, tiles: &[(usize, tileset::TileId)]| {
+            let layout = egui::Layout::left_to_right(egui::Align::Min).with_main_wrap(true);
+            ui.with_layout(layout, |ui| {
+                let mut spacing = ui.spacing_mut();
+                spacing.item_spacing = egui::vec2(0.0, 0.0);
+                spacing.button_padding = egui::vec2(0.0, 0.0);
+
+                let mut visuals = ui.visuals_mut();
+                visuals.widgets.inactive.weak_bg_fill = egui::Color32::TRANSPARENT;
+
+                for (index, tile_id) in tiles {
+                    tile_button(ui, *index, *tile_id);
+                }
+            });
+        };
+
+        match groups.as_slice() {
+            [] if !search.0.is_empty() => {
+                ui.label("no matching tiles");
+            }
+            [(_, tiles)] => tile_grid(ui, tiles),
+            groups => {
+                for (category, tiles) in groups {
+                    let title = if category.is_empty() { "other" } else { category };
+                    egui::CollapsingHeader::new(format!("{} ({})", title, tiles.len()))
+                        .id_salt(id.with(("category", *category)))
+                        .default_open(true)
+                        .show(ui, |ui| tile_grid(ui, tiles));
+                }
+            }
+        }
 
         if let Some(range) = deselect_range {
             for tile_ref in range {
@@ -270,6 +578,9 @@ impl<'w, 's> BasicWidget for TilePicker<'w, 's> {
             let mut added = Vec::new();
             for index in range {
                 let tile_id = tileset.tile_order.get(index).unwrap();
+                if !tileset.tiles.get(tile_id).is_some_and(|t| t.matches_search(&search.0)) {
+                    continue;
+                }
                 let tile_ref = TileRef {
                     tileset: tileset_id,
                     tile: *tile_id
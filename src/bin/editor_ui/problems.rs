@@ -0,0 +1,52 @@
+use bevy::prelude::*;
+use bevy_egui::egui;
+
+use world_editor::{ ui::widget::*,validate };
+
+use crate::{ EditorUiEvent,Problems };
+
+#[derive(Default, Clone)]
+pub struct ProblemsPanel;
+
+impl BasicWidget for ProblemsPanel {
+    fn new(_world: &mut World, _ui: &egui::Ui) -> Self {
+        Self::default()
+    }
+
+    fn draw(&mut self, world: &mut World, ui: &mut egui::Ui, _id: egui::Id) {
+        let mut event = None;
+
+        ui.horizontal(|ui| {
+            ui.heading("Problems");
+            if ui.button("Validate").clicked() {
+                event = Some(EditorUiEvent::RunValidation);
+            }
+        });
+
+        if let Some(problems) = world.get_resource::<Problems>() {
+            if problems.0.is_empty() {
+                ui.label("no problems found");
+            }
+
+            egui::ScrollArea::vertical().max_height(240.0).show(ui, |ui| {
+                for problem in &problems.0 {
+                    let color = match problem.severity {
+                        validate::Severity::Error => ui.visuals().error_fg_color,
+                        validate::Severity::Warning => ui.visuals().warn_fg_color
+                    };
+                    let label = ui.selectable_label(false, egui::RichText::new(problem.to_string()).color(color));
+
+                    if let Some(location) = problem.location {
+                        if label.clicked() {
+                            event = Some(EditorUiEvent::FocusLocation(location));
+                        }
+                    }
+                }
+            });
+        }
+
+        if let Some(event) = event {
+            world.send_event(event);
+        }
+    }
+}
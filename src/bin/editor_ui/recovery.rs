@@ -0,0 +1,51 @@
+use bevy::prelude::*;
+use bevy_egui::egui;
+
+use world_editor::{ recovery,ui::widget::* };
+
+use crate::EditorUiEvent;
+
+#[derive(Default, Clone)]
+pub struct RecoveryPrompt;
+
+impl BasicWidget for RecoveryPrompt {
+    fn new(_world: &mut World, _ui: &egui::Ui) -> Self {
+        Self::default()
+    }
+
+    fn draw(&mut self, world: &mut World, ui: &mut egui::Ui, _id: egui::Id) {
+        let Some(sessions) = world.get_resource::<recovery::RecoverableSessions>() else { return };
+        if sessions.0.is_empty() {
+            return;
+        }
+
+        let mut event = None;
+        ui.label("The editor did not shut down cleanly. Recover unsaved work?");
+        ui.separator();
+
+        egui::Grid::new("recovery_sessions").striped(true).show(ui, |ui| {
+            for session in &sessions.0 {
+                ui.label(session.started.format("%Y-%m-%d %H:%M:%S").to_string());
+                ui.label(match session.autosaved {
+                    Some(t) => format!("autosaved {}", t.format("%H:%M:%S")),
+                    None => "not autosaved".into()
+                });
+                ui.label(match &session.map_path {
+                    Some(path) => path.to_string_lossy().into_owned(),
+                    None => "untitled".into()
+                });
+                if ui.button("Recover").clicked() {
+                    event = Some(EditorUiEvent::RecoverSession(session.dir.clone()));
+                }
+                if ui.button("Discard").clicked() {
+                    event = Some(EditorUiEvent::DiscardSession(session.dir.clone()));
+                }
+                ui.end_row();
+            }
+        });
+
+        if let Some(event) = event {
+            world.send_event(event);
+        }
+    }
+}
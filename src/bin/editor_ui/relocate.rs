@@ -0,0 +1,89 @@
+use bevy::prelude::*;
+use bevy_egui::egui;
+
+use world_editor::{ filepicker,ui::widget::* };
+
+use crate::{ EditorUiEvent,PickerEvent,RelocateAssets };
+
+/// Finds tile models that moved by searching a folder for their file names
+#[derive(Default, Clone)]
+pub struct RelocateDialog;
+
+impl BasicWidget for RelocateDialog {
+    fn new(_world: &mut World, _ui: &egui::Ui) -> Self {
+        Self::default()
+    }
+
+    fn draw(&mut self, world: &mut World, ui: &mut egui::Ui, id: egui::Id) {
+        let Some(mut relocate) = world.get_resource_mut::<RelocateAssets>() else { return };
+        let searching = relocate.poll();
+
+        ui.heading("Relocate missing assets");
+        if relocate.missing.is_empty() {
+            ui.label("All tile models were found.");
+            if ui.button("Close").clicked() {
+                world.remove_resource::<RelocateAssets>();
+            }
+            return;
+        }
+
+        let mut search = false;
+        ui.horizontal(|ui| {
+            search = ui.add_enabled(!searching, egui::Button::new("Search folder...")).clicked();
+            match (&relocate.search_dir, searching) {
+                (Some(dir), true) => ui.label(format!("searching {}...", dir.to_string_lossy())),
+                (Some(dir), false) => ui.label(dir.to_string_lossy()),
+                (None, _) => ui.label("")
+            };
+        });
+
+        let relocate = &mut *relocate;
+        egui::ScrollArea::vertical()
+            .max_height(300.0)
+            .id_salt(id.with("missing"))
+            .show(ui, |ui| {
+                egui::Grid::new(id.with("grid")).striped(true).show(ui, |ui| {
+                    for path in &relocate.missing {
+                        ui.label(path.to_string_lossy());
+                        match relocate.candidates.get_mut(path) {
+                            Some((files, chosen)) if files.len() > 1 => {
+                                egui::ComboBox::from_id_salt(id.with(path))
+                                    .selected_text(files[*chosen].to_string_lossy())
+                                    .show_ui(ui, |ui| {
+                                        for (index, file) in files.iter().enumerate() {
+                                            ui.selectable_value(chosen, index, file.to_string_lossy());
+                                        }
+                                    });
+                            }
+                            Some((files, _)) => {
+                                ui.label(files[0].to_string_lossy());
+                            }
+                            None => {
+                                ui.weak("not found");
+                            }
+                        }
+                        ui.end_row();
+                    }
+                });
+            });
+
+        let relocations = relocate.relocations();
+        let mut close = false;
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(!relocations.is_empty(), egui::Button::new(format!("Relocate {} files", relocations.len())))
+                .clicked()
+            {
+                world.send_event(EditorUiEvent::RelocateAssets(relocations));
+            }
+            close = ui.button("Cancel").clicked();
+        });
+
+        if search {
+            world.spawn(filepicker::Picker::folder_dialog(PickerEvent::RelocateSearch(None)).build());
+        }
+        if close {
+            world.remove_resource::<RelocateAssets>();
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use bevy::prelude::*;
+use bevy_egui::egui;
+use std::collections::{ BTreeMap,HashMap };
+
+use world_editor::{ tileset,ui::widget::* };
+
+use crate::EditorUiEvent;
+
+/// Maps tile references that no longer resolve onto existing tiles
+#[derive(Default, Clone)]
+pub struct RemapDialog {
+    targets: HashMap<tileset::TileRef, tileset::TileRef>
+}
+
+impl BasicWidget for RemapDialog {
+    fn new(_world: &mut World, _ui: &egui::Ui) -> Self {
+        Self::default()
+    }
+
+    fn draw(&mut self, world: &mut World, ui: &mut egui::Ui, _id: egui::Id) {
+        let mut missing_query = world.query_filtered::<&tileset::TileRef, With<tileset::MissingTile>>();
+        let mut missing: BTreeMap<(String, tileset::TileId), (tileset::TileRef, usize)> = BTreeMap::new();
+        let mut tilesets = world.query::<(Entity, &tileset::TileSet)>();
+
+        for tile_ref in missing_query.iter(world) {
+            let name = tilesets
+                .get(world, tile_ref.tileset)
+                .map(|(_, t)| t.name.clone())
+                .unwrap_or_else(|_| format!("{:?}", tile_ref.tileset));
+            missing.entry((name, tile_ref.tile)).or_insert((*tile_ref, 0)).1 += 1;
+        }
+        if missing.is_empty() {
+            return;
+        }
+
+        let choices: Vec<(tileset::TileRef, String)> = tilesets
+            .iter(world)
+            .flat_map(|(entity, tileset)| {
+                // ordered ids without a tile are left for the tileset repair
+                tileset.tile_order.iter().filter_map(move |id| {
+                    let tile = tileset.tiles.get(id)?;
+                    Some((
+                        tileset::TileRef { tileset: entity, tile: *id },
+                        format!("{}: {}", tileset.name, tile.name)
+                    ))
+                })
+            })
+            .collect();
+
+        ui.heading("Missing tiles");
+        egui::Grid::new("remap_missing_tiles").striped(true).show(ui, |ui| {
+            for ((tileset_name, tile_id), (tile_ref, count)) in &missing {
+                ui.label(format!("{} #{} ({} placed)", tileset_name, tile_id, count));
+
+                let selected = self
+                    .targets
+                    .get(tile_ref)
+                    .and_then(|target| choices.iter().find(|(r, _)| r == target))
+                    .map(|(_, name)| name.as_str())
+                    .unwrap_or("keep");
+                egui::ComboBox::from_id_salt(("remap", tileset_name, tile_id))
+                    .selected_text(selected)
+                    .show_ui(ui, |ui| {
+                        if ui.selectable_label(!self.targets.contains_key(tile_ref), "keep").clicked() {
+                            self.targets.remove(tile_ref);
+                        }
+                        for (target, name) in &choices {
+                            let is_selected = self.targets.get(tile_ref) == Some(target);
+                            if ui.selectable_label(is_selected, name).clicked() {
+                                self.targets.insert(*tile_ref, *target);
+                            }
+                        }
+                    });
+                ui.end_row();
+            }
+        });
+
+        if ui
+            .add_enabled(!self.targets.is_empty(), egui::Button::new("Remap"))
+            .clicked()
+        {
+            let remap = self.targets.drain().collect();
+            world.send_event(EditorUiEvent::RemapTiles(remap));
+        }
+    }
+}
@@ -72,14 +72,19 @@ pub struct TileProperties<'w: 'static, 's: 'static> {
         Query<'w, 's, &'static mut tileset::TileSet>,
         EventWriter<'w, EditorUiEvent>
     )>,
-    transform: TileTransform
+    transform: TileTransform,
+    category: String,
+    /// Comma separated
+    tags: String
 }
 
 impl <'w, 's> BasicWidget for TileProperties<'w, 's> {
     fn new(world: &mut World, ui: &egui::Ui) -> Self {
         Self {
             system_state: SystemState::new(world),
-            transform: TileTransform::default()
+            transform: TileTransform::default(),
+            category: String::new(),
+            tags: String::new()
         }
     }
 
@@ -102,6 +107,38 @@ impl <'w, 's> BasicWidget for TileProperties<'w, 's> {
                 return;
             };
             self.transform = tile.transform.into();
+            self.category = tile.category.clone();
+            self.tags = tile.tags.join(", ");
+        }
+
+        let labels_changed = egui::Grid::new(id.with("labels"))
+            .num_columns(2)
+            .show(ui, |ui| {
+                ui.label("category");
+                let mut changed = ui.text_edit_singleline(&mut self.category).lost_focus();
+                ui.end_row();
+
+                ui.label("tags");
+                changed |= ui.text_edit_singleline(&mut self.tags).lost_focus();
+                ui.end_row();
+
+                changed
+            })
+            .inner;
+
+        if labels_changed {
+            let tags: Vec<String> = self.tags
+                .split(',')
+                .map(|tag| tag.trim().to_string())
+                .filter(|tag| !tag.is_empty())
+                .collect();
+
+            for tile_ref in &selection.tiles {
+                let Ok(mut tileset) = tilesets.get_mut(tile_ref.tileset) else { continue };
+                let Some(tile) = tileset.tiles.get_mut(&tile_ref.tile) else { continue };
+                tile.category = self.category.trim().to_string();
+                tile.tags = tags.clone();
+            }
         }
 
         let mut full = false;
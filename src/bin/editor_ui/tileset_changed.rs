@@ -0,0 +1,54 @@
+use bevy::prelude::*;
+use bevy_egui::egui;
+
+use world_editor::{ persistence,tileset,ui::widget::* };
+
+use crate::EditorUiEvent;
+
+/// Lists tileset files that changed on disk since the map was last saved or
+/// the tileset was loaded
+#[derive(Default, Clone)]
+pub struct TilesetChangedPrompt;
+
+impl BasicWidget for TilesetChangedPrompt {
+    fn new(_world: &mut World, _ui: &egui::Ui) -> Self {
+        Self::default()
+    }
+
+    fn draw(&mut self, world: &mut World, ui: &mut egui::Ui, _id: egui::Id) {
+        let mut query = world.query_filtered::<
+            (Entity, &tileset::TileSet, &persistence::TilesetFile),
+            With<persistence::TilesetFileChanged>
+        >();
+        let changed: Vec<(Entity, String, String)> = query
+            .iter(world)
+            .map(|(entity, tileset, file)| {
+                (entity, tileset.name.clone(), file.path.to_string_lossy().into_owned())
+            })
+            .collect();
+        if changed.is_empty() {
+            return;
+        }
+
+        ui.label("These tilesets changed on disk:");
+        for (_, name, path) in &changed {
+            ui.label(format!("{} ({})", name, path));
+        }
+
+        ui.horizontal(|ui| {
+            if ui.button("Reload").clicked() {
+                for (entity, _, _) in &changed {
+                    world.send_event(EditorUiEvent::ReloadTileset(*entity));
+                }
+            }
+            if ui.button("Check map").clicked() {
+                world.send_event(EditorUiEvent::RunValidation);
+            }
+            if ui.button("OK").clicked() {
+                for (entity, _, _) in &changed {
+                    world.entity_mut(*entity).remove::<persistence::TilesetFileChanged>();
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,192 @@
+//! Three-way merge of map files.
+//!
+//! Usable as a git merge driver:
+//!
+//! ```text
+//! # .gitattributes
+//! *.map.ron merge=world-editor
+//!
+//! # .git/config
+//! [merge "world-editor"]
+//!     name = world editor map merge
+//!     driver = merge %O %A %B --output %A --name %P --compact
+//! ```
+//!
+//! Exits with status 1 when conflicts remain. Conflicting hexes keep ours in
+//! their layer and theirs in a `conflict: <layer>` layer; a report is written
+//! to `<name>.conflicts.ron`.
+
+use anyhow::{ Context,Result };
+use std::path::PathBuf;
+
+use world_editor::{ merge,persistence };
+
+struct Args {
+    base: PathBuf,
+    ours: PathBuf,
+    theirs: PathBuf,
+    output: PathBuf,
+    name: PathBuf,
+    compact: bool
+}
+
+const USAGE: &str = "usage: merge <base> <ours> <theirs> [--output <file>] [--name <path>] [--compact]";
+
+fn parse_args() -> Result<Args> {
+    let mut positional = Vec::new();
+    let mut output = None;
+    let mut name = None;
+    let mut compact = false;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--output" => output = Some(PathBuf::from(args.next().context(USAGE)?)),
+            "--name" => name = Some(PathBuf::from(args.next().context(USAGE)?)),
+            "--compact" => compact = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                std::process::exit(0);
+            }
+            _ => positional.push(PathBuf::from(arg))
+        }
+    }
+
+    let [base, ours, theirs] = <[PathBuf; 3]>::try_from(positional)
+        .ok()
+        .context(USAGE)?;
+    let output = output.unwrap_or_else(|| ours.clone());
+    let name = name.unwrap_or_else(|| output.clone());
+
+    Ok(Args { base, ours, theirs, output, name, compact })
+}
+
+fn run() -> Result<bool> {
+    merge_files(&parse_args()?)
+}
+
+fn merge_files(args: &Args) -> Result<bool> {
+    let load = |path: &PathBuf| {
+        persistence::MapFormat::load_as(path, &args.name)
+            .context(format!("failed to load {:?}", path))
+    };
+    let base = load(&args.base)?;
+    let ours = load(&args.ours)?;
+    let theirs = load(&args.theirs)?;
+
+    let (merged, report) = merge::merge(&base, &ours, &theirs)?;
+
+    let options = persistence::SaveOptions {
+        compact: args.compact,
+        backups: 0,
+        embed_tilesets: false,
+        // the merge result only goes to the output file
+        write_tilesets: false
+    };
+    merged.write_as(&args.output, &args.name, options)?;
+
+    report.save(&args.name)?;
+    if !report.has_conflicts() {
+        return Ok(true);
+    }
+
+    for conflict in &report.hexes {
+        eprintln!(
+            "conflict: layer {} at ({}, {})",
+            conflict.layer, conflict.location.x, conflict.location.y
+        );
+    }
+    for (tileset, tile) in &report.tiles {
+        eprintln!("conflict: tile {} of tileset {} changed on both sides", tile, tileset);
+    }
+
+    Ok(false)
+}
+
+fn main() {
+    match run() {
+        Ok(true) => (),
+        Ok(false) => std::process::exit(1),
+        Err(e) => {
+            eprintln!("{:?}", e);
+            std::process::exit(2);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use world_editor::tileset;
+
+    fn map_with_tiles(locations: &[(i32, i32)]) -> persistence::MapFormat {
+        let mut map = persistence::MapFormat::new(hexx::HexLayout::default());
+        map.tileset_files.insert(
+            0.into(),
+            persistence::TilesetFile::new(PathBuf::from("../tilesets/forest.tileset.ron"))
+        );
+        map.layers.push(persistence::Layer {
+            name: "ground".into(),
+            tiles: locations
+                .iter()
+                .map(|location| persistence::Tile {
+                    location: (*location).into(),
+                    tileset: 0.into(),
+                    tile_id: 0,
+                    rotation: tileset::TileRotation::default()
+                })
+                .collect()
+        });
+        map
+    }
+
+    fn write(path: &Path, map: &persistence::MapFormat) {
+        let f = std::fs::File::create(path).unwrap();
+        persistence::FileFormat::Ron.to_writer(f, map).unwrap();
+    }
+
+    /// git passes temporary files from another directory and the real path as `--name`
+    #[test]
+    fn resolves_tilesets_relative_to_name() {
+        let root = std::env::temp_dir().join(format!("world-editor-merge-{}", std::process::id()));
+        let repo = root.join("repo");
+        let tmp = root.join("tmp");
+        for dir in [repo.join("maps"), repo.join("tilesets"), tmp.clone()] {
+            std::fs::create_dir_all(dir).unwrap();
+        }
+
+        let mut forest = tileset::TileSet::new("forest");
+        forest.add_title(PathBuf::from("forest/tree.glb"));
+        persistence::write_tileset(&repo.join("tilesets/forest.tileset.ron"), &forest, 0).unwrap();
+
+        let args = Args {
+            base: tmp.join(".merge_file_base"),
+            ours: tmp.join(".merge_file_ours"),
+            theirs: tmp.join(".merge_file_theirs"),
+            output: tmp.join(".merge_file_ours"),
+            name: repo.join("maps/world.map.ron"),
+            compact: true
+        };
+        write(&args.base, &map_with_tiles(&[(0, 0)]));
+        write(&args.ours, &map_with_tiles(&[(0, 0), (1, 0)]));
+        write(&args.theirs, &map_with_tiles(&[(0, 0), (0, 1)]));
+
+        let clean = merge_files(&args).unwrap();
+
+        let merged = persistence::MapFormat::load_as(&args.output, &args.name).unwrap();
+        let written: persistence::MapFormat = persistence::FileFormat::Ron
+            .from_str(&std::fs::read_to_string(&args.output).unwrap())
+            .unwrap();
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert!(clean);
+        assert_eq!(merged.layers[0].tiles.len(), 3);
+        assert_eq!(merged.tilesets[&persistence::SaveId::from(0)].tiles.len(), 1);
+        assert!(written.tilesets.is_empty());
+        assert_eq!(
+            written.tileset_files[&persistence::SaveId::from(0)].path,
+            PathBuf::from("../tilesets/forest.tileset.ron")
+        );
+    }
+}
@@ -0,0 +1,424 @@
+use anyhow::{ bail,Context,Result };
+use hexx::HexLayout;
+use serde::{ Deserialize,Serialize };
+use std::{
+    collections::{ BTreeMap,HashMap },
+    io::{ BufReader,BufWriter,Read,Write },
+    path::Path
+};
+
+use crate::{
+    map,
+    persistence::{ self,SaveId },
+    tileset
+};
+
+const MAGIC: &[u8; 4] = b"WEMB";
+const BINARY_FORMAT_VERSION: u16 = 1;
+/// Longest run of tiles read from a file; runs are limited by the width of a row
+const MAX_RUN_LENGTH: u32 = 1 << 16;
+/// Lengths in a file are not trusted to preallocate more than this
+const MAX_PREALLOCATE: usize = 4096;
+/// Most tiles read from a file; a run takes a few bytes, so a small corrupt
+/// file could otherwise expand into an enormous map
+const MAX_TILES: usize = 1 << 23;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+    Zstd
+}
+
+impl Compression {
+    /// `None` if the path does not name a binary map file
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("bin") => Some(Compression::None),
+            Some("gz") => Some(Compression::Gzip),
+            Some("zst") => Some(Compression::Zstd),
+            _ => None
+        }
+    }
+
+    fn tag(self) -> u8 {
+        match self {
+            Compression::None => 0,
+            Compression::Gzip => 1,
+            Compression::Zstd => 2
+        }
+    }
+
+    fn from_tag(tag: u8) -> Result<Self> {
+        match tag {
+            0 => Ok(Compression::None),
+            1 => Ok(Compression::Gzip),
+            2 => Ok(Compression::Zstd),
+            t => bail!("unknown compression: {}", t)
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Header {
+    version: usize,
+    layout: HexLayout,
+    tilesets: BTreeMap<SaveId, tileset::TileSet>,
+    #[serde(default)]
+    tileset_files: BTreeMap<SaveId, persistence::TilesetFile>
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct PaletteEntry {
+    tileset: SaveId,
+    tile_id: tileset::TileId,
+    rotation: tileset::TileRotation
+}
+
+fn write_u8(w: &mut impl Write, v: u8) -> Result<()> {
+    w.write_all(&[v])?;
+    Ok(())
+}
+
+fn write_u32(w: &mut impl Write, v: u32) -> Result<()> {
+    w.write_all(&v.to_le_bytes())?;
+    Ok(())
+}
+
+fn write_i32(w: &mut impl Write, v: i32) -> Result<()> {
+    w.write_all(&v.to_le_bytes())?;
+    Ok(())
+}
+
+fn write_bytes(w: &mut impl Write, v: &[u8]) -> Result<()> {
+    write_u32(w, v.len() as u32)?;
+    w.write_all(v)?;
+    Ok(())
+}
+
+fn read_u8(r: &mut impl Read) -> Result<u8> {
+    let mut buf = [0; 1];
+    r.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+fn read_u32(r: &mut impl Read) -> Result<u32> {
+    let mut buf = [0; 4];
+    r.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_i32(r: &mut impl Read) -> Result<i32> {
+    let mut buf = [0; 4];
+    r.read_exact(&mut buf)?;
+    Ok(i32::from_le_bytes(buf))
+}
+
+fn read_bytes(r: &mut impl Read) -> Result<Vec<u8>> {
+    let len = read_u32(r)? as usize;
+    // grows with the data actually read, so a corrupt length cannot exhaust memory
+    let mut buf = Vec::new();
+    Read::take(&mut *r, len as u64).read_to_end(&mut buf)?;
+    if buf.len() != len {
+        bail!("unexpected end of file");
+    }
+    Ok(buf)
+}
+
+fn write_layer(w: &mut impl Write, layer: &persistence::Layer) -> Result<()> {
+    write_bytes(w, layer.name.as_bytes())?;
+
+    let mut palette = Vec::new();
+    let mut palette_index = HashMap::new();
+    let mut cells: Vec<(i32, i32, u32)> = Vec::with_capacity(layer.tiles.len());
+
+    for tile in &layer.tiles {
+        let entry = PaletteEntry {
+            tileset: tile.tileset,
+            tile_id: tile.tile_id,
+            rotation: tile.rotation
+        };
+        let index = *palette_index.entry(entry).or_insert_with(|| {
+            palette.push(entry);
+            palette.len() as u32 - 1
+        });
+        cells.push((tile.location.y, tile.location.x, index));
+    }
+
+    write_u32(w, palette.len() as u32)?;
+    for entry in &palette {
+        write_u32(w, usize::from(entry.tileset) as u32)?;
+        write_u32(w, entry.tile_id as u32)?;
+        write_u8(w, entry.rotation.index() as u8)?;
+    }
+
+    // runs of identical tiles along a row: (y, x start, length, palette index)
+    cells.sort_unstable();
+    let mut runs: Vec<(i32, i32, u32, u32)> = Vec::new();
+    for (y, x, index) in cells {
+        if let Some(run) = runs.last_mut() {
+            if run.0 == y && run.1 + run.2 as i32 == x && run.3 == index {
+                run.2 += 1;
+                continue;
+            }
+        }
+        runs.push((y, x, 1, index));
+    }
+
+    write_u32(w, runs.len() as u32)?;
+    for (y, x, len, index) in runs {
+        write_i32(w, y)?;
+        write_i32(w, x)?;
+        write_u32(w, len)?;
+        write_u32(w, index)?;
+    }
+
+    Ok(())
+}
+
+/// `max_tiles` is what is left of the tile limit of the map; tiles read are
+/// taken off it
+fn read_layer(r: &mut impl Read, max_tiles: &mut usize) -> Result<persistence::Layer> {
+    let name = String::from_utf8(read_bytes(r)?).context("invalid layer name")?;
+
+    let palette_len = read_u32(r)?;
+    let mut palette = Vec::with_capacity((palette_len as usize).min(MAX_PREALLOCATE));
+    for _ in 0..palette_len {
+        let tileset = SaveId::from(read_u32(r)? as usize);
+        let tile_id = read_u32(r)? as tileset::TileId;
+        let rotation = tileset::TileRotation::from_index(read_u8(r)? as usize)
+            .context("invalid tile rotation")?;
+        palette.push(PaletteEntry { tileset, tile_id, rotation });
+    }
+
+    let mut layer = persistence::Layer {
+        name,
+        tiles: Vec::new()
+    };
+
+    let runs = read_u32(r)?;
+    for _ in 0..runs {
+        let y = read_i32(r)?;
+        let x = read_i32(r)?;
+        let len = read_u32(r)?;
+        let index = read_u32(r)?;
+        if len > MAX_RUN_LENGTH || x.checked_add(len as i32).is_none() {
+            bail!("invalid run of {} tiles at ({}, {})", len, x, y);
+        }
+        *max_tiles = max_tiles
+            .checked_sub(len as usize)
+            .context(format!("map has more than {} tiles", MAX_TILES))?;
+        let entry = palette
+            .get(index as usize)
+            .context(format!("palette index out of range: {}", index))?;
+
+        for i in 0..len as i32 {
+            layer.tiles.push(persistence::Tile {
+                location: map::Location { x: x + i, y },
+                tileset: entry.tileset,
+                tile_id: entry.tile_id,
+                rotation: entry.rotation
+            });
+        }
+    }
+
+    Ok(layer)
+}
+
+fn write_body(w: &mut impl Write, map: &persistence::MapFormat) -> Result<()> {
+    let header = Header {
+        version: map.version,
+        layout: map.layout.clone(),
+        tilesets: map.tilesets.clone(),
+        tileset_files: map.tileset_files.clone()
+    };
+    write_bytes(w, &serde_json::to_vec(&header)?)?;
+
+    write_u32(w, map.layers.len() as u32)?;
+    for layer in &map.layers {
+        write_layer(w, layer)?;
+    }
+
+    Ok(())
+}
+
+fn read_body(r: &mut impl Read) -> Result<persistence::MapFormat> {
+    let header: Header = serde_json::from_slice(&read_bytes(r)?).context("invalid map header")?;
+
+    let mut map = persistence::MapFormat::new(header.layout);
+    map.version = header.version;
+    map.tilesets = header.tilesets;
+    map.tileset_files = header.tileset_files;
+
+    let mut max_tiles = MAX_TILES;
+    let layers = read_u32(r)?;
+    for _ in 0..layers {
+        map.layers.push(read_layer(r, &mut max_tiles)?);
+    }
+
+    Ok(map)
+}
+
+pub fn write_map(
+    writer: impl Write,
+    map: &persistence::MapFormat,
+    compression: Compression
+) -> Result<()> {
+    let mut writer = BufWriter::new(writer);
+    writer.write_all(MAGIC)?;
+    writer.write_all(&BINARY_FORMAT_VERSION.to_le_bytes())?;
+    write_u8(&mut writer, compression.tag())?;
+
+    match compression {
+        Compression::None => write_body(&mut writer, map)?,
+        Compression::Gzip => {
+            let mut encoder = flate2::write::GzEncoder::new(writer, flate2::Compression::default());
+            write_body(&mut encoder, map)?;
+            encoder.finish()?.flush()?;
+            return Ok(());
+        }
+        Compression::Zstd => {
+            let mut encoder = zstd::Encoder::new(writer, 0)?;
+            write_body(&mut encoder, map)?;
+            encoder.finish()?.flush()?;
+            return Ok(());
+        }
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+pub fn read_map(reader: impl Read) -> Result<persistence::MapFormat> {
+    let mut reader = BufReader::new(reader);
+
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        bail!("not a binary map file");
+    }
+
+    let mut version = [0; 2];
+    reader.read_exact(&mut version)?;
+    let version = u16::from_le_bytes(version);
+    if version != BINARY_FORMAT_VERSION {
+        bail!(
+            "unsupported binary map version: {} != {}",
+            version,
+            BINARY_FORMAT_VERSION
+        );
+    }
+
+    match Compression::from_tag(read_u8(&mut reader)?)? {
+        Compression::None => read_body(&mut reader),
+        Compression::Gzip => read_body(&mut flate2::read::GzDecoder::new(reader)),
+        Compression::Zstd => read_body(&mut zstd::Decoder::with_buffer(reader)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_map() -> persistence::MapFormat {
+        let mut map = persistence::MapFormat::new(HexLayout::default());
+        let mut forest = tileset::TileSet::new("forest");
+        forest.add_title("forest/tree.glb".into());
+        forest.add_title("forest/rock.glb".into());
+        map.tilesets.insert(SaveId::from(0), forest);
+        map.tileset_files.insert(
+            SaveId::from(1),
+            persistence::TilesetFile::new("../tilesets/town.tileset.ron".into())
+        );
+
+        let tile = |x: i32, y: i32, tileset: usize, tile_id: tileset::TileId, rotation: usize| persistence::Tile {
+            location: (x, y).into(),
+            tileset: SaveId::from(tileset),
+            tile_id,
+            rotation: tileset::TileRotation::from_index(rotation).unwrap()
+        };
+        map.layers.push(persistence::Layer {
+            name: "ground".into(),
+            tiles: vec![tile(2, 0, 0, 0, 0), tile(0, 0, 0, 0, 0), tile(1, 0, 0, 0, 0), tile(-3, 5, 1, 7, 4)]
+        });
+        map.layers.push(persistence::Layer {
+            name: "empty".into(),
+            tiles: Vec::new()
+        });
+        map
+    }
+
+    fn tiles(layer: &persistence::Layer) -> Vec<(i32, i32, usize, tileset::TileId, usize)> {
+        let mut tiles: Vec<_> = layer
+            .tiles
+            .iter()
+            .map(|t| (t.location.y, t.location.x, usize::from(t.tileset), t.tile_id, t.rotation.index()))
+            .collect();
+        tiles.sort();
+        tiles
+    }
+
+    #[test]
+    fn maps_round_trip_with_every_compression() {
+        let map = sample_map();
+
+        for compression in [Compression::None, Compression::Gzip, Compression::Zstd] {
+            let mut buf = Vec::new();
+            write_map(&mut buf, &map, compression).unwrap();
+            let read = read_map(buf.as_slice()).unwrap();
+
+            assert_eq!(read.version, map.version);
+            assert_eq!(read.tilesets[&SaveId::from(0)].tiles.len(), 2);
+            assert_eq!(
+                read.tileset_files[&SaveId::from(1)].path,
+                map.tileset_files[&SaveId::from(1)].path
+            );
+            assert_eq!(read.layers.len(), 2);
+            for (read, layer) in read.layers.iter().zip(&map.layers) {
+                assert_eq!(read.name, layer.name);
+                assert_eq!(tiles(read), tiles(layer));
+            }
+        }
+    }
+
+    #[test]
+    fn corrupt_lengths_are_errors() {
+        let mut buf = Vec::new();
+        write_map(&mut buf, &sample_map(), Compression::None).unwrap();
+
+        // the header length claims far more than the file holds
+        let mut corrupt = buf.clone();
+        corrupt[7..11].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(read_map(corrupt.as_slice()).is_err());
+
+        buf.truncate(buf.len() - 3);
+        assert!(read_map(buf.as_slice()).is_err());
+    }
+
+    #[test]
+    fn runs_beyond_the_tile_limit_are_errors() {
+        // one palette entry and three runs of the longest length
+        let mut layer = Vec::new();
+        write_bytes(&mut layer, b"ground").unwrap();
+        write_u32(&mut layer, 1).unwrap();
+        write_u32(&mut layer, 0).unwrap();
+        write_u32(&mut layer, 0).unwrap();
+        write_u8(&mut layer, 0).unwrap();
+        write_u32(&mut layer, 3).unwrap();
+        for y in 0..3 {
+            write_i32(&mut layer, y).unwrap();
+            write_i32(&mut layer, 0).unwrap();
+            write_u32(&mut layer, MAX_RUN_LENGTH).unwrap();
+            write_u32(&mut layer, 0).unwrap();
+        }
+
+        let mut max_tiles = 2 * MAX_RUN_LENGTH as usize;
+        assert!(read_layer(&mut layer.as_slice(), &mut max_tiles).is_err());
+
+        let mut max_tiles = 3 * MAX_RUN_LENGTH as usize;
+        let read = read_layer(&mut layer.as_slice(), &mut max_tiles).unwrap();
+        assert_eq!(read.tiles.len(), 3 * MAX_RUN_LENGTH as usize);
+        assert_eq!(max_tiles, 0);
+    }
+}
@@ -0,0 +1,185 @@
+use anyhow::{ Context,Result };
+use bevy::{
+    prelude::*,
+    tasks::{ IoTaskPool,Task }
+};
+use futures_lite::future;
+use std::{
+    collections::HashSet,
+    path::PathBuf
+};
+
+use crate::{
+    map,
+    merge::{ self,TileKey },
+    persistence
+};
+
+pub struct Plugin;
+
+impl bevy::app::Plugin for Plugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (diff_loaders, draw_diff));
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Changed
+}
+
+impl ChangeKind {
+    pub fn color(&self) -> Color {
+        match self {
+            ChangeKind::Added => Color::srgb(0.2, 0.9, 0.2),
+            ChangeKind::Removed => Color::srgb(0.9, 0.2, 0.2),
+            ChangeKind::Changed => Color::srgb(0.9, 0.8, 0.2)
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TileChange {
+    pub layer: String,
+    pub location: map::Location,
+    pub kind: ChangeKind,
+    pub old: Option<TileKey>,
+    pub new: Option<TileKey>
+}
+
+/// Changes from `old` to `new`, ordered by layer and location
+pub fn diff(
+    old: &persistence::MapFormat,
+    new: &persistence::MapFormat
+) -> Result<Vec<TileChange>> {
+    let old_layers = merge::layer_tiles(old)?;
+    let new_layers = merge::layer_tiles(new)?;
+
+    let mut names: Vec<&String> = new_layers.iter().map(|(name, _)| name).collect();
+    for (name, _) in &old_layers {
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+
+    let mut changes = Vec::new();
+    for name in names {
+        let find = |layers: &Vec<(String, merge::LayerTiles)>| {
+            layers
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, tiles)| tiles.clone())
+                .unwrap_or_default()
+        };
+        let old_tiles = find(&old_layers);
+        let new_tiles = find(&new_layers);
+
+        let mut locations: Vec<&(i32, i32)> = old_tiles.keys().chain(new_tiles.keys()).collect();
+        locations.sort();
+        locations.dedup();
+
+        for location in locations {
+            let (old, new) = (old_tiles.get(location), new_tiles.get(location));
+            let kind = match (old, new) {
+                (None, Some(_)) => ChangeKind::Added,
+                (Some(_), None) => ChangeKind::Removed,
+                (Some(o), Some(n)) if o != n => ChangeKind::Changed,
+                _ => continue
+            };
+            changes.push(TileChange {
+                layer: name.clone(),
+                location: map::Location { x: location.1, y: location.0 },
+                kind,
+                old: old.cloned(),
+                new: new.cloned()
+            });
+        }
+    }
+
+    Ok(changes)
+}
+
+/// Result of comparing the open map against another map file
+#[derive(Resource, Debug)]
+pub struct MapDiff {
+    pub path: PathBuf,
+    pub changes: Vec<TileChange>,
+    pub hidden_layers: HashSet<String>
+}
+
+/// Loads a map without spawning it and diffs the open map against it
+#[derive(Component)]
+pub struct DiffLoader {
+    path: PathBuf,
+    task: Task<Result<persistence::MapFormat>>
+}
+
+impl DiffLoader {
+    pub fn new(path: PathBuf) -> Self {
+        let path_copy = path.clone();
+        let task_pool = IoTaskPool::get();
+        let task = task_pool.spawn(async move { persistence::MapFormat::load(&path) });
+
+        Self {
+            path: path_copy,
+            task
+        }
+    }
+}
+
+fn diff_loaders(world: &mut World) {
+    let mut loaders = world.query::<(Entity, &mut DiffLoader)>();
+    let mut finished = Vec::new();
+    for (entity, mut loader) in loaders.iter_mut(world) {
+        let Some(result) = future::block_on(future::poll_once(&mut loader.task)) else { continue };
+        finished.push((entity, loader.path.clone(), result));
+    }
+
+    for (entity, path, result) in finished {
+        world.despawn(entity);
+
+        let changes = result.and_then(|other| {
+            let root = world
+                .query_filtered::<Entity, With<map::Map>>()
+                .single(world)
+                .context("no map loaded")?;
+            let current = persistence::MapFormat::try_new(world, root)?;
+
+            diff(&other, &current)
+        });
+
+        match changes {
+            Ok(changes) => {
+                info!("{} changes against {}", changes.len(), path.to_string_lossy());
+                world.insert_resource(MapDiff {
+                    path,
+                    changes,
+                    hidden_layers: HashSet::new()
+                });
+            }
+            Err(e) => warn!("failed to compare with {}: {:?}", path.to_string_lossy(), e)
+        }
+    }
+}
+
+fn draw_diff(
+    mut gizmos: Gizmos,
+    diff: Option<Res<MapDiff>>,
+    map: Query<&map::Map>
+) {
+    let Some(diff) = diff else { return };
+    let Ok(map) = map.single() else { return };
+
+    for change in &diff.changes {
+        if diff.hidden_layers.contains(&change.layer) {
+            continue;
+        }
+        let corners = map
+            .layout
+            .hex_corners(change.location.into())
+            .map(|c| Vec3::new(c.x, 0.05, c.y));
+        gizmos.linestrip(corners.into_iter().chain([corners[0]]), change.kind.color());
+    }
+}
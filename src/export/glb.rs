@@ -0,0 +1,655 @@
+use anyhow::{ bail,Context,Result };
+use bevy::{
+    ecs::system::Command,
+    prelude::*,
+    tasks::{ IoTaskPool,Task }
+};
+use futures_lite::future;
+use gltf::json;
+use std::{
+    borrow::Cow,
+    collections::{ HashMap,HashSet },
+    path::{ Path,PathBuf }
+};
+
+use crate::{ obj,persistence,tileset };
+
+pub struct Plugin;
+
+impl bevy::app::Plugin for Plugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, glb_writers);
+    }
+}
+
+struct Model {
+    nodes: Vec<json::Node>,
+    roots: Vec<usize>,
+    /// Root nodes of named scenes
+    scenes: HashMap<String, Vec<usize>>
+}
+
+#[derive(Default)]
+struct GlbBuilder {
+    root: json::Root,
+    bin: Vec<u8>,
+    models: HashMap<PathBuf, Model>,
+    images: HashMap<PathBuf, json::Index<json::Image>>,
+    samplers: HashMap<String, json::Index<json::texture::Sampler>>,
+    textures: HashMap<String, json::Index<json::Texture>>,
+    materials: HashMap<String, json::Index<json::Material>>
+}
+
+impl GlbBuilder {
+    fn push_bytes(&mut self, bytes: &[u8]) -> (u64, u64) {
+        while self.bin.len() % 4 != 0 {
+            self.bin.push(0);
+        }
+        let offset = self.bin.len() as u64;
+        self.bin.extend_from_slice(bytes);
+
+        (offset, bytes.len() as u64)
+    }
+
+    fn push_view(&mut self, bytes: &[u8]) -> json::Index<json::buffer::View> {
+        let (offset, length) = self.push_bytes(bytes);
+
+        self.root.push(json::buffer::View {
+            buffer: json::Index::new(0),
+            byte_length: length.into(),
+            byte_offset: Some(offset.into()),
+            byte_stride: None,
+            name: None,
+            target: None,
+            extensions: None,
+            extras: Default::default()
+        })
+    }
+
+    fn push_image_file(&mut self, image_path: &Path) -> Result<json::Index<json::Image>> {
+        if let Some(index) = self.images.get(image_path) {
+            return Ok(*index);
+        }
+
+        let bytes = std::fs::read(image_path)
+            .context(format!("failed to read image {:?}", image_path))?;
+        let mime_type = match image_path.extension().and_then(|e| e.to_str()) {
+            Some("jpg") | Some("jpeg") => "image/jpeg",
+            _ => "image/png"
+        };
+        let buffer_view = self.push_view(&bytes);
+        let index = self.root.push(json::Image {
+            buffer_view: Some(buffer_view),
+            mime_type: Some(json::image::MimeType(mime_type.into())),
+            name: None,
+            uri: None,
+            extensions: None,
+            extras: Default::default()
+        });
+        self.images.insert(image_path.to_path_buf(), index);
+
+        Ok(index)
+    }
+
+    fn push_accessor(
+        &mut self,
+        bytes: &[u8],
+        count: usize,
+        component_type: json::accessor::ComponentType,
+        type_: json::accessor::Type,
+        bounds: Option<(Vec<f32>, Vec<f32>)>
+    ) -> json::Index<json::Accessor> {
+        let view = self.push_view(bytes);
+        let (min, max) = match bounds {
+            Some((min, max)) => (Some(json::Value::from(min)), Some(json::Value::from(max))),
+            None => (None, None)
+        };
+
+        self.root.push(json::Accessor {
+            buffer_view: Some(view),
+            byte_offset: None,
+            count: (count as u64).into(),
+            component_type: json::validation::Checked::Valid(json::accessor::GenericComponentType(component_type)),
+            type_: json::validation::Checked::Valid(type_),
+            min,
+            max,
+            name: None,
+            normalized: false,
+            sparse: None,
+            extensions: None,
+            extras: Default::default()
+        })
+    }
+
+    fn push_floats(&mut self, values: &[f32], type_: json::accessor::Type, bounds: bool) -> json::Index<json::Accessor> {
+        let width = type_.multiplicity();
+        let bytes: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+        let bounds = bounds.then(|| {
+            let mut min = vec![f32::MAX; width];
+            let mut max = vec![f32::MIN; width];
+            for chunk in values.chunks_exact(width) {
+                for (i, v) in chunk.iter().enumerate() {
+                    min[i] = min[i].min(*v);
+                    max[i] = max[i].max(*v);
+                }
+            }
+            (min, max)
+        });
+
+        self.push_accessor(&bytes, values.len() / width, json::accessor::ComponentType::F32, type_, bounds)
+    }
+
+    /// Converts an OBJ model into glTF meshes, one node per OBJ object
+    fn load_obj_model(&mut self, path: &Path) -> Result<()> {
+        let dir = path.parent().unwrap_or(Path::new("."));
+        let (models, materials) = tobj::load_obj(path, &tobj::GPU_LOAD_OPTIONS)
+            .context(format!("failed to open model {:?}", path))?;
+        let materials = materials.unwrap_or_else(|e| {
+            warn!("failed to load materials of {:?}: {}", path, e);
+            Vec::new()
+        });
+
+        let mut material_indices = Vec::new();
+        for material in &materials {
+            let texture = match &material.diffuse_texture {
+                Some(texture) => {
+                    let source = self.push_image_file(&dir.join(texture))?;
+                    Some(self.root.push(json::Texture {
+                        name: None,
+                        sampler: None,
+                        source,
+                        extensions: None,
+                        extras: Default::default()
+                    }))
+                }
+                None => None
+            };
+
+            let alpha_mode = if material.dissolve.unwrap_or(1.0) < 1.0 {
+                json::material::AlphaMode::Blend
+            } else {
+                json::material::AlphaMode::Opaque
+            };
+            let material = json::Material {
+                name: Some(material.name.clone()),
+                alpha_mode: json::validation::Checked::Valid(alpha_mode),
+                pbr_metallic_roughness: json::material::PbrMetallicRoughness {
+                    base_color_factor: json::material::PbrBaseColorFactor(
+                        obj::base_color(material).to_linear().to_f32_array()
+                    ),
+                    base_color_texture: texture.map(|index| json::texture::Info {
+                        index,
+                        tex_coord: 0,
+                        extensions: None,
+                        extras: Default::default()
+                    }),
+                    metallic_factor: json::material::StrengthFactor(0.0),
+                    roughness_factor: json::material::StrengthFactor(obj::roughness(material)),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+
+            let key = serde_json::to_string(&material)?;
+            let index = match self.materials.get(&key) {
+                Some(index) => *index,
+                None => {
+                    let index = self.root.push(material);
+                    self.materials.insert(key, index);
+                    index
+                }
+            };
+            material_indices.push(index);
+        }
+
+        let mut nodes = Vec::new();
+        for model in &models {
+            use json::mesh::Semantic;
+            let mesh = &model.mesh;
+            let mut attributes = std::collections::BTreeMap::new();
+
+            let positions = self.push_floats(&mesh.positions, json::accessor::Type::Vec3, true);
+            attributes.insert(json::validation::Checked::Valid(Semantic::Positions), positions);
+            if !mesh.normals.is_empty() {
+                let normals = self.push_floats(&mesh.normals, json::accessor::Type::Vec3, false);
+                attributes.insert(json::validation::Checked::Valid(Semantic::Normals), normals);
+            }
+            if !mesh.texcoords.is_empty() {
+                let uvs: Vec<f32> = mesh.texcoords
+                    .chunks_exact(2)
+                    .flat_map(|t| [t[0], 1.0 - t[1]])
+                    .collect();
+                let uvs = self.push_floats(&uvs, json::accessor::Type::Vec2, false);
+                attributes.insert(json::validation::Checked::Valid(Semantic::TexCoords(0)), uvs);
+            }
+
+            let indices: Vec<u8> = mesh.indices.iter().flat_map(|i| i.to_le_bytes()).collect();
+            let indices = self.push_accessor(
+                &indices,
+                mesh.indices.len(),
+                json::accessor::ComponentType::U32,
+                json::accessor::Type::Scalar,
+                None
+            );
+
+            let mesh_index = self.root.push(json::Mesh {
+                name: Some(model.name.clone()),
+                primitives: vec![json::mesh::Primitive {
+                    attributes,
+                    indices: Some(indices),
+                    material: mesh.material_id.and_then(|id| material_indices.get(id).copied()),
+                    mode: json::validation::Checked::Valid(json::mesh::Mode::Triangles),
+                    targets: None,
+                    extensions: None,
+                    extras: Default::default()
+                }],
+                weights: None,
+                extensions: None,
+                extras: Default::default()
+            });
+
+            nodes.push(json::Node {
+                name: Some(model.name.clone()),
+                mesh: Some(mesh_index),
+                ..default()
+            });
+        }
+
+        let roots = (0..nodes.len()).collect();
+        self.models.insert(path.to_path_buf(), Model { nodes, roots, scenes: HashMap::new() });
+
+        Ok(())
+    }
+
+    fn load_model(&mut self, path: &Path) -> Result<()> {
+        if self.models.contains_key(path) {
+            return Ok(());
+        }
+        if path.extension().is_some_and(|e| e.eq_ignore_ascii_case("obj")) {
+            return self.load_obj_model(path);
+        }
+        let dir = path.parent().unwrap_or(Path::new("."));
+
+        let gltf::Gltf { document, blob } = gltf::Gltf::open(path)
+            .context(format!("failed to open model {:?}", path))?;
+        let source = document.into_json();
+
+        let mut buffer_offsets = Vec::new();
+        for (index, buffer) in source.buffers.iter().enumerate() {
+            let bytes = match &buffer.uri {
+                None if index == 0 => blob.clone().context(format!("missing binary chunk in {:?}", path))?,
+                None => bail!("buffer {} in {:?} has no data", index, path),
+                Some(uri) if uri.starts_with("data:") => bail!("embedded buffers are not supported: {:?}", path),
+                Some(uri) => std::fs::read(dir.join(uri)).context(format!("failed to read buffer {:?}", uri))?
+            };
+            buffer_offsets.push(self.push_bytes(&bytes).0);
+        }
+
+        let mut views = Vec::new();
+        for view in &source.buffer_views {
+            let mut view = view.clone();
+            let offset = view.byte_offset.map(|o| o.0).unwrap_or(0);
+            view.byte_offset = Some((buffer_offsets[view.buffer.value()] + offset).into());
+            view.buffer = json::Index::new(0);
+            views.push(self.root.push(view));
+        }
+
+        let mut accessors = Vec::new();
+        for accessor in &source.accessors {
+            let mut accessor = accessor.clone();
+            accessor.buffer_view = accessor.buffer_view.map(|v| views[v.value()]);
+            if let Some(sparse) = accessor.sparse.as_mut() {
+                sparse.indices.buffer_view = views[sparse.indices.buffer_view.value()];
+                sparse.values.buffer_view = views[sparse.values.buffer_view.value()];
+            }
+            accessors.push(self.root.push(accessor));
+        }
+
+        let mut images = Vec::new();
+        for image in &source.images {
+            let index = match (&image.uri, image.buffer_view) {
+                (Some(uri), _) if !uri.starts_with("data:") => self.push_image_file(&dir.join(uri))?,
+                (_, Some(view)) => {
+                    let mut image = image.clone();
+                    image.buffer_view = Some(views[view.value()]);
+                    self.root.push(image)
+                }
+                _ => bail!("unsupported image in {:?}", path)
+            };
+            images.push(index);
+        }
+
+        let mut samplers = Vec::new();
+        for sampler in &source.samplers {
+            let key = serde_json::to_string(sampler)?;
+            let index = match self.samplers.get(&key) {
+                Some(index) => *index,
+                None => {
+                    let index = self.root.push(sampler.clone());
+                    self.samplers.insert(key, index);
+                    index
+                }
+            };
+            samplers.push(index);
+        }
+
+        let mut textures = Vec::new();
+        for texture in &source.textures {
+            let mut texture = texture.clone();
+            texture.source = images[texture.source.value()];
+            texture.sampler = texture.sampler.map(|s| samplers[s.value()]);
+
+            let key = serde_json::to_string(&texture)?;
+            let index = match self.textures.get(&key) {
+                Some(index) => *index,
+                None => {
+                    let index = self.root.push(texture);
+                    self.textures.insert(key, index);
+                    index
+                }
+            };
+            textures.push(index);
+        }
+
+        let remap_info = |info: &mut Option<json::texture::Info>| {
+            if let Some(info) = info {
+                info.index = textures[info.index.value()];
+            }
+        };
+
+        let mut materials = Vec::new();
+        for material in &source.materials {
+            let mut material = material.clone();
+            remap_info(&mut material.pbr_metallic_roughness.base_color_texture);
+            remap_info(&mut material.pbr_metallic_roughness.metallic_roughness_texture);
+            remap_info(&mut material.emissive_texture);
+            if let Some(normal) = material.normal_texture.as_mut() {
+                normal.index = textures[normal.index.value()];
+            }
+            if let Some(occlusion) = material.occlusion_texture.as_mut() {
+                occlusion.index = textures[occlusion.index.value()];
+            }
+
+            let key = serde_json::to_string(&material)?;
+            let index = match self.materials.get(&key) {
+                Some(index) => *index,
+                None => {
+                    let index = self.root.push(material);
+                    self.materials.insert(key, index);
+                    index
+                }
+            };
+            materials.push(index);
+        }
+
+        let mut meshes = Vec::new();
+        for mesh in &source.meshes {
+            let mut mesh = mesh.clone();
+            for primitive in &mut mesh.primitives {
+                for accessor in primitive.attributes.values_mut() {
+                    *accessor = accessors[accessor.value()];
+                }
+                primitive.indices = primitive.indices.map(|a| accessors[a.value()]);
+                primitive.material = primitive.material.map(|m| materials[m.value()]);
+                for target in primitive.targets.iter_mut().flatten() {
+                    target.positions = target.positions.map(|a| accessors[a.value()]);
+                    target.normals = target.normals.map(|a| accessors[a.value()]);
+                    target.tangents = target.tangents.map(|a| accessors[a.value()]);
+                }
+            }
+            meshes.push(self.root.push(mesh));
+        }
+
+        let nodes = source
+            .nodes
+            .iter()
+            .map(|node| {
+                let mut node = node.clone();
+                node.mesh = node.mesh.map(|m| meshes[m.value()]);
+                node.camera = None;
+                node.skin = None;
+                node
+            })
+            .collect();
+
+        let scene = source.scene.map(|s| s.value()).unwrap_or(0);
+        let roots = source
+            .scenes
+            .get(scene)
+            .context(format!("no scene in {:?}", path))?
+            .nodes
+            .iter()
+            .map(|n| n.value())
+            .collect();
+
+        let scenes = source
+            .scenes
+            .iter()
+            .filter_map(|scene| {
+                let roots = scene.nodes.iter().map(|n| n.value()).collect();
+                scene.name.clone().map(|name| (name, roots))
+            })
+            .collect();
+
+        self.models.insert(path.to_path_buf(), Model { nodes, roots, scenes });
+
+        Ok(())
+    }
+
+    fn instantiate_node(&mut self, model: &Path, node: usize) -> json::Index<json::Node> {
+        let mut instance = self.models[model].nodes[node].clone();
+
+        instance.children = instance.children.map(|children| {
+            children
+                .iter()
+                .map(|child| self.instantiate_node(model, child.value()))
+                .collect()
+        });
+
+        self.root.push(instance)
+    }
+
+    fn instantiate(&mut self, model: &Path) -> Vec<json::Index<json::Node>> {
+        let roots = self.models[model].roots.clone();
+
+        roots
+            .into_iter()
+            .map(|node| self.instantiate_node(model, node))
+            .collect()
+    }
+
+    /// Instances of the nodes making up `part` of a model, or its default scene
+    fn instantiate_part(
+        &mut self,
+        model: &Path,
+        part: Option<&tileset::ModelPart>
+    ) -> Result<Vec<json::Index<json::Node>>> {
+        let roots = match part {
+            None => return Ok(self.instantiate(model)),
+            Some(tileset::ModelPart::Scene(name)) => self.models[model]
+                .scenes
+                .get(name)
+                .cloned()
+                .context(format!("no scene named {} in {:?}", name, model))?,
+            Some(tileset::ModelPart::Node(name)) => {
+                let node = self.models[model]
+                    .nodes
+                    .iter()
+                    .position(|n| n.name.as_deref() == Some(name.as_str()))
+                    .context(format!("no node named {} in {:?}", name, model))?;
+                let instance = self.instantiate_node(model, node);
+
+                // the node keeps its orientation but is moved to the tile's origin
+                let node = &mut self.root.nodes[instance.value()];
+                node.translation = None;
+                if let Some(matrix) = node.matrix.as_mut() {
+                    matrix[12..15].fill(0.0);
+                }
+                return Ok(vec![instance]);
+            }
+        };
+
+        Ok(roots
+            .into_iter()
+            .map(|node| self.instantiate_node(model, node))
+            .collect())
+    }
+
+    fn finish(mut self) -> Result<Vec<u8>> {
+        while self.bin.len() % 4 != 0 {
+            self.bin.push(0);
+        }
+
+        self.root.push(json::Buffer {
+            byte_length: (self.bin.len() as u64).into(),
+            name: None,
+            uri: None,
+            extensions: None,
+            extras: Default::default()
+        });
+
+        let json = self.root.to_vec().context("failed to serialize glTF json")?;
+        let glb = gltf::binary::Glb {
+            header: gltf::binary::Header {
+                magic: *b"glTF",
+                version: 2,
+                length: 0
+            },
+            json: Cow::Owned(json),
+            bin: Some(Cow::Owned(self.bin))
+        };
+
+        glb.to_vec().context("failed to write glb")
+    }
+}
+
+pub fn export_glb(
+    map_format: &persistence::MapFormat,
+    asset_root: &Path
+) -> Result<Vec<u8>> {
+    let map = map_format.map();
+    let mut builder = GlbBuilder::default();
+    builder.root.asset.generator = Some("world-editor".into());
+
+    // models that failed to load are only reported once
+    let mut failed_models = HashSet::new();
+    let mut layer_nodes = Vec::new();
+    for layer in &map_format.layers {
+        let mut tile_nodes = Vec::new();
+
+        for tile in &layer.tiles {
+            let Some(tileset) = map_format.tilesets.get(&tile.tileset) else {
+                warn!("tileset SaveId not found: {:?}; not exporting tile at {:?}", tile.tileset, tile.location);
+                continue;
+            };
+            let Some(tileset_tile) = tileset.tiles.get(&tile.tile_id) else {
+                warn!("TileId {} not found in TileSet {}; not exporting tile at {:?}", tile.tile_id, tileset.name, tile.location);
+                continue;
+            };
+
+            let model_path = asset_root.join(&tileset_tile.path);
+            if failed_models.contains(&model_path) {
+                continue;
+            }
+            if let Err(e) = builder.load_model(&model_path) {
+                warn!("failed to load model {:?}: {:#}; not exporting its tiles", model_path, e);
+                failed_models.insert(model_path);
+                continue;
+            }
+            let children = match builder.instantiate_part(&model_path, tileset_tile.part.as_ref()) {
+                Ok(children) => children,
+                Err(e) => {
+                    warn!("failed to export tile {}: {:#}; not exporting it", tileset_tile.name, e);
+                    continue;
+                }
+            };
+
+            let transform = map.tile_transform(
+                tileset_tile,
+                tile.location,
+                &tileset::TileTransform { rotation: tile.rotation }
+            );
+            let extras = serde_json::json!({
+                "location": tile.location,
+                "tileset": tileset.name,
+                "tile": tileset_tile.name
+            });
+
+            tile_nodes.push(builder.root.push(json::Node {
+                name: Some(tileset_tile.name.clone()),
+                translation: Some(transform.translation.to_array()),
+                rotation: Some(json::scene::UnitQuaternion(transform.rotation.to_array())),
+                scale: Some(transform.scale.to_array()),
+                children: Some(children),
+                extras: Some(serde_json::value::to_raw_value(&extras)?),
+                ..default()
+            }));
+        }
+
+        layer_nodes.push(builder.root.push(json::Node {
+            name: Some(layer.name.clone()),
+            children: Some(tile_nodes),
+            ..default()
+        }));
+    }
+
+    let scene = builder.root.push(json::Scene {
+        name: Some("map".into()),
+        nodes: layer_nodes,
+        extensions: None,
+        extras: Default::default()
+    });
+    builder.root.scene = Some(scene);
+
+    builder.finish()
+}
+
+pub struct ExportGlbCommand {
+    path: PathBuf,
+    map: Entity,
+    asset_root: PathBuf
+}
+
+impl ExportGlbCommand {
+    pub fn new(path: PathBuf, map: Entity, asset_root: PathBuf) -> Self {
+        Self { path,map,asset_root }
+    }
+}
+
+impl Command for ExportGlbCommand {
+    fn apply(self, world: &mut World) {
+        let map = match persistence::MapFormat::try_new(world, self.map) {
+            Ok(map) => map,
+            Err(err) => {
+                warn!("failed to export map: {:#?}", err);
+                return;
+            }
+        };
+
+        let task_pool = IoTaskPool::get();
+        let task = task_pool.spawn(async move {
+            let glb = export_glb(&map, &self.asset_root)?;
+            std::fs::write(&self.path, glb).context(format!("writing glb to {:?}", self.path))?;
+
+            Ok::<(), anyhow::Error>(())
+        });
+
+        world.spawn(GlbWriterTask(task));
+    }
+}
+
+#[derive(Component)]
+struct GlbWriterTask(Task<Result<()>>);
+
+fn glb_writers(
+    mut commands: Commands,
+    mut glb_writers: Query<(Entity, &mut GlbWriterTask)>
+) {
+    for (entity, mut writer) in &mut glb_writers {
+        let Some(result) = future::block_on(future::poll_once(&mut writer.0)) else { continue };
+        if let Err(e) = result {
+            warn!("failed to export glb: {:#?}", e);
+        }
+        commands.entity(entity).despawn();
+    }
+}
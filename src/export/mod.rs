@@ -0,0 +1,2 @@
+pub mod glb;
+pub mod scene;
@@ -0,0 +1,177 @@
+use anyhow::{ Context,Result };
+use bevy::{
+    ecs::system::Command,
+    prelude::*,
+    reflect::PartialReflect,
+    scene::{ DynamicScene,DynamicSceneBuilder },
+    tasks::{ IoTaskPool,Task }
+};
+use futures_lite::future;
+use std::{
+    collections::HashMap,
+    path::PathBuf
+};
+
+use crate::{ map,tileset };
+
+pub struct Plugin;
+
+impl bevy::app::Plugin for Plugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<SceneTile>()
+            .add_systems(Update, scene_writers);
+    }
+}
+
+/// Tile information written into exported scenes in place of the editor-only
+/// components. The type path is fixed so a game can declare an identical type
+/// without depending on this crate.
+///
+/// Bevy scenes cannot store asset handles, so tiles carry no `SceneRoot`; a
+/// game adds `SceneRoot(asset_server.load(&tile.scene))` when a `SceneTile`
+/// is spawned.
+#[derive(Component, Default, Debug, Clone, Reflect)]
+#[reflect(Component, Default)]
+#[type_path = "level"]
+pub struct SceneTile {
+    pub layer: String,
+    pub tileset: String,
+    pub name: String,
+    /// Asset path of the tile's scene
+    pub scene: String,
+    /// `scene:<name>` or `node:<name>` when the tile is part of the model
+    /// file in `scene`, otherwise empty
+    pub part: String
+}
+
+/// Every tile of the map as a flat list of entities with only a `Transform`,
+/// relative to the map, and a `SceneTile`
+fn build_scene(world: &mut World, root: Entity) -> Result<DynamicScene> {
+    world
+        .get::<map::Map>(root)
+        .context(format!("failed to get Map component for map root {:?}", root))?;
+
+    let mut extra: HashMap<Entity, Vec<Box<dyn PartialReflect>>> = HashMap::new();
+
+    let mut layers = world.query::<(&map::Layer, &ChildOf, Option<&Transform>, Option<&Children>)>();
+    let mut tiles = world.query_filtered::<(&tileset::TileRef, Option<&Transform>), With<map::Location>>();
+    let mut tilesets = world.query::<&tileset::TileSet>();
+
+    for (layer, child_of, layer_transform, children) in layers.iter(world) {
+        if child_of.parent() != root {
+            continue;
+        }
+        let layer_transform = layer_transform.copied().unwrap_or_default();
+
+        for child in children.into_iter().flatten() {
+            let Ok((tile_ref, transform)) = tiles.get(world, *child) else { continue };
+            let Ok(tileset) = tilesets.get(world, tile_ref.tileset) else {
+                warn!("unknown tileset for tile {:?}: {:?}; not exporting tile", child, tile_ref);
+                continue;
+            };
+            let Some(tile) = tileset.tiles.get(&tile_ref.tile) else {
+                warn!("unknown tile for tile {:?}: {:?}; not exporting tile", child, tile_ref);
+                continue;
+            };
+
+            let scene_tile = match &tile.part {
+                None => SceneTile {
+                    layer: layer.name.clone(),
+                    tileset: tileset.name.clone(),
+                    name: tile.name.clone(),
+                    scene: format!("{}#Scene0", tile.path.to_string_lossy()),
+                    part: String::new()
+                },
+                Some(part) => SceneTile {
+                    layer: layer.name.clone(),
+                    tileset: tileset.name.clone(),
+                    name: tile.name.clone(),
+                    scene: tile.path.to_string_lossy().into(),
+                    part: match part {
+                        tileset::ModelPart::Scene(name) => format!("scene:{}", name),
+                        tileset::ModelPart::Node(name) => format!("node:{}", name)
+                    }
+                }
+            };
+            let transform = layer_transform.mul_transform(transform.copied().unwrap_or_default());
+            extra.insert(*child, vec![
+                Box::new(transform),
+                Box::new(scene_tile)
+            ]);
+        }
+    }
+
+    // everything else on the entities belongs to the editor
+    let mut scene = DynamicSceneBuilder::from_world(world)
+        .deny_all()
+        .extract_entities(extra.keys().cloned())
+        .build();
+
+    for entity in &mut scene.entities {
+        if let Some(components) = extra.remove(&entity.entity) {
+            entity.components.extend(components);
+        }
+    }
+
+    Ok(scene)
+}
+
+pub struct ExportSceneCommand {
+    path: PathBuf,
+    map: Entity
+}
+
+impl ExportSceneCommand {
+    pub fn new(path: PathBuf, map: Entity) -> Self {
+        Self { path,map }
+    }
+}
+
+impl Command for ExportSceneCommand {
+    fn apply(self, world: &mut World) {
+        let scene = match build_scene(world, self.map) {
+            Ok(scene) => scene,
+            Err(err) => {
+                warn!("failed to export scene: {:#?}", err);
+                return;
+            }
+        };
+
+        let serialized = {
+            let type_registry = world.resource::<AppTypeRegistry>().read();
+            scene.serialize(&type_registry)
+        };
+        let serialized = match serialized {
+            Ok(serialized) => serialized,
+            Err(err) => {
+                warn!("failed to serialize scene: {:#?}", err);
+                return;
+            }
+        };
+
+        let task_pool = IoTaskPool::get();
+        let task = task_pool.spawn(async move {
+            std::fs::write(&self.path, serialized).context(format!("writing scene to {:?}", self.path))?;
+
+            Ok::<(), anyhow::Error>(())
+        });
+
+        world.spawn(SceneWriterTask(task));
+    }
+}
+
+#[derive(Component)]
+struct SceneWriterTask(Task<Result<()>>);
+
+fn scene_writers(
+    mut commands: Commands,
+    mut scene_writers: Query<(Entity, &mut SceneWriterTask)>
+) {
+    for (entity, mut writer) in &mut scene_writers {
+        let Some(result) = future::block_on(future::poll_once(&mut writer.0)) else { continue };
+        if let Err(e) = result {
+            warn!("failed to export scene: {:#?}", e);
+        }
+        commands.entity(entity).despawn();
+    }
+}
@@ -0,0 +1,165 @@
+use bevy::{
+    asset::AssetPath,
+    gltf::Gltf,
+    prelude::*,
+    tasks::{ IoTaskPool,Task }
+};
+use futures_lite::future;
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    time::{ Duration,SystemTime }
+};
+
+use crate::{ obj,persistence,tileset };
+
+pub struct Plugin;
+
+impl bevy::app::Plugin for Plugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FileWatcher>()
+            .add_systems(Update, (
+                watch_files,
+                reload_models::<Gltf>,
+                reload_models::<obj::Obj>
+            ).chain());
+    }
+}
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+enum Watched {
+    /// A tile model, by its asset path
+    Model(PathBuf),
+    Tileset(Entity)
+}
+
+/// Polls the modification times of tile models and tileset files
+#[derive(Resource)]
+pub struct FileWatcher {
+    pub enabled: bool,
+    timer: Timer,
+    modified: HashMap<PathBuf, SystemTime>,
+    task: Option<Task<Vec<(PathBuf, Option<SystemTime>)>>>,
+    watched: HashMap<PathBuf, Watched>
+}
+
+impl Default for FileWatcher {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            timer: Timer::new(POLL_INTERVAL, TimerMode::Repeating),
+            modified: HashMap::new(),
+            task: None,
+            watched: HashMap::new()
+        }
+    }
+}
+
+fn watch_files(
+    mut commands: Commands,
+    mut watcher: ResMut<FileWatcher>,
+    time: Res<Time>,
+    asset_server: Res<AssetServer>,
+    asset_root: Res<tileset::AssetRoot>,
+    tilesets: Query<&tileset::TileSet>,
+    tileset_files: Query<(Entity, &persistence::TilesetFile), Without<tileset::TilesetImporter>>
+) {
+    if !watcher.enabled {
+        return;
+    }
+    let watcher = &mut *watcher;
+
+    if let Some(task) = watcher.task.as_mut() {
+        let Some(times) = future::block_on(future::poll_once(task)) else { return };
+        watcher.task = None;
+
+        for (path, time) in times {
+            let Some(time) = time else { continue };
+            let Some(previous) = watcher.modified.insert(path.clone(), time) else { continue };
+            if previous == time {
+                continue;
+            }
+
+            match watcher.watched.get(&path) {
+                Some(Watched::Model(asset_path)) => {
+                    info!("model changed: {}", asset_path.to_string_lossy());
+                    asset_server.reload(AssetPath::from(asset_path.clone()));
+                }
+                Some(Watched::Tileset(entity)) => {
+                    let Ok((_, file)) = tileset_files.get(*entity) else { continue };
+                    // no hash yet while an export is being written
+                    if file.hash == 0 {
+                        continue;
+                    }
+                    let changed = std::fs::read(&path)
+                        .is_ok_and(|buf| persistence::content_hash(&buf) != file.hash);
+                    if changed {
+                        info!("tileset file changed: {}", path.to_string_lossy());
+                        commands.entity(*entity).insert(persistence::TilesetFileChanged);
+                    }
+                }
+                None => ()
+            }
+        }
+    }
+
+    if !watcher.timer.tick(time.delta()).just_finished() {
+        return;
+    }
+
+    watcher.watched.clear();
+    for tileset in &tilesets {
+        for tile in tileset.tiles.values() {
+            let path = asset_root.resolve(&tile.path);
+            watcher.watched.insert(path, Watched::Model(tile.path.clone()));
+        }
+    }
+    for (entity, file) in &tileset_files {
+        watcher.watched.insert(file.path.clone(), Watched::Tileset(entity));
+    }
+    watcher.modified.retain(|path, _| watcher.watched.contains_key(path));
+
+    let paths: Vec<PathBuf> = watcher.watched.keys().cloned().collect();
+    watcher.task = Some(IoTaskPool::get().spawn(async move {
+        paths
+            .into_iter()
+            .map(|path| {
+                let time = std::fs::metadata(&path).and_then(|m| m.modified()).ok();
+                (path, time)
+            })
+            .collect()
+    }));
+}
+
+/// Refreshes tiles whose model file was reloaded: placed tiles get the new
+/// scene and thumbnails are rendered again
+fn reload_models<A: Asset>(
+    mut commands: Commands,
+    mut events: EventReader<AssetEvent<A>>,
+    asset_server: Res<AssetServer>,
+    tilesets: Query<(Entity, &tileset::TileSet)>
+) {
+    for event in events.read() {
+        let AssetEvent::Modified { id } = event else { continue };
+        let Some(asset_path) = asset_server.get_path(*id) else { continue };
+        let path = asset_path.path();
+
+        let reloaded: Vec<tileset::TileRef> = tilesets
+            .iter()
+            .flat_map(|(entity, tileset)| {
+                tileset
+                    .tiles
+                    .values()
+                    .filter(move |tile| tile.path == path)
+                    .map(move |tile| tileset::TileRef { tileset: entity, tile: tile.id })
+            })
+            .collect();
+        if reloaded.is_empty() {
+            continue;
+        }
+
+        info!("reloaded {} tiles using {}", reloaded.len(), path.to_string_lossy());
+        tileset::refresh_tiles(&mut commands, &reloaded);
+    }
+}
@@ -1,10 +1,19 @@
+pub mod binary;
+pub mod diff;
+pub mod export;
 pub mod filepicker;
 pub mod helper;
+pub mod hot_reload;
 pub mod render;
 pub mod map;
+pub mod merge;
+pub mod obj;
 pub mod tileset;
 pub mod persistence;
+pub mod recovery;
+pub mod tiled;
 pub mod ui;
+pub mod validate;
 
 pub mod prelude {
     pub use super::map::{ Map,WorldMapExt };
@@ -0,0 +1,390 @@
+use anyhow::{ bail,Context,Result };
+use bevy::scene::ron;
+use serde::{ Deserialize,Serialize };
+use std::{
+    collections::{ BTreeMap,HashMap },
+    path::{ Path,PathBuf }
+};
+
+use crate::{
+    map,
+    persistence::{ self,SaveId },
+    tileset
+};
+
+/// Prefix of layers holding "theirs" side of conflicting hexes
+pub const CONFLICT_LAYER_PREFIX: &str = "conflict: ";
+
+/// A placed tile identified independently of `SaveId`s, which differ between
+/// the files being merged.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TileKey {
+    pub tileset: String,
+    pub tile_id: tileset::TileId,
+    pub rotation: tileset::TileRotation
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HexConflict {
+    pub layer: String,
+    pub location: map::Location,
+    pub base: Option<TileKey>,
+    pub ours: Option<TileKey>,
+    pub theirs: Option<TileKey>
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct MergeReport {
+    pub hexes: Vec<HexConflict>,
+    /// Tiles defined differently on both sides; ours is kept
+    pub tiles: Vec<(String, tileset::TileId)>
+}
+
+impl MergeReport {
+    pub fn has_conflicts(&self) -> bool {
+        !self.hexes.is_empty() || !self.tiles.is_empty()
+    }
+
+    /// The report written next to a merged map
+    pub fn path_for(map_path: &Path) -> PathBuf {
+        let mut path = map_path.as_os_str().to_owned();
+        path.push(".conflicts.ron");
+        PathBuf::from(path)
+    }
+
+    pub fn load(map_path: &Path) -> Result<Option<Self>> {
+        let path = Self::path_for(map_path);
+        let buf = match std::fs::read_to_string(&path) {
+            Ok(buf) => buf,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).context(format!("failed to read {:?}", path))
+        };
+
+        Ok(Some(ron::from_str(&buf).context(format!("failed to parse {:?}", path))?))
+    }
+
+    /// Writes the report, or removes it once all conflicts are resolved
+    pub fn save(&self, map_path: &Path) -> Result<()> {
+        let path = Self::path_for(map_path);
+
+        if !self.has_conflicts() {
+            return match std::fs::remove_file(&path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e).context(format!("failed to remove {:?}", path)),
+                _ => Ok(())
+            };
+        }
+
+        let buf = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?;
+        std::fs::write(&path, buf).context(format!("failed to write {:?}", path))
+    }
+}
+
+pub(crate) type LayerTiles = BTreeMap<(i32, i32), TileKey>;
+
+pub(crate) fn layer_tiles(map: &persistence::MapFormat) -> Result<Vec<(String, LayerTiles)>> {
+    let mut layers = Vec::new();
+
+    for layer in &map.layers {
+        let mut tiles = BTreeMap::new();
+        for tile in &layer.tiles {
+            let Some(tileset) = map.tilesets.get(&tile.tileset) else {
+                bail!("layer {} references unknown tileset {:?}", layer.name, tile.tileset);
+            };
+            tiles.insert((tile.location.y, tile.location.x), TileKey {
+                tileset: tileset.name.clone(),
+                tile_id: tile.tile_id,
+                rotation: tile.rotation
+            });
+        }
+        layers.push((layer.name.clone(), tiles));
+    }
+
+    Ok(layers)
+}
+
+fn merge_tilesets(
+    ours: &persistence::MapFormat,
+    theirs: &persistence::MapFormat,
+    report: &mut MergeReport
+) -> BTreeMap<SaveId, tileset::TileSet> {
+    let mut tilesets = ours.tilesets.clone();
+    let mut next_id = tilesets.keys().max().map(|id| *id + 1).unwrap_or_default();
+
+    for tileset in theirs.tilesets.values() {
+        let Some(merged) = tilesets.values_mut().find(|t| t.name == tileset.name) else {
+            tilesets.insert(next_id, tileset.clone());
+            next_id += 1;
+            continue;
+        };
+
+        for id in &tileset.tile_order {
+            let Some(tile) = tileset.tiles.get(id) else { continue };
+            match merged.tiles.get(id) {
+                None => merged.insert_tile(tile.clone()),
+                Some(existing) if existing.path != tile.path || existing.transform != tile.transform => {
+                    report.tiles.push((tileset.name.clone(), *id));
+                }
+                Some(_) => ()
+            }
+        }
+    }
+
+    tilesets
+}
+
+fn merge_hex(
+    base: Option<&TileKey>,
+    ours: Option<&TileKey>,
+    theirs: Option<&TileKey>
+) -> std::result::Result<Option<TileKey>, ()> {
+    if ours == theirs || theirs == base {
+        Ok(ours.cloned())
+    } else if ours == base {
+        Ok(theirs.cloned())
+    } else {
+        Err(())
+    }
+}
+
+/// Three-way merge of maps, hex by hex and layer by layer.
+///
+/// Hexes changed differently on both sides keep ours in their layer; theirs
+/// is placed in a layer named `CONFLICT_LAYER_PREFIX` + layer name, and each
+/// conflict is listed in the report.
+pub fn merge(
+    base: &persistence::MapFormat,
+    ours: &persistence::MapFormat,
+    theirs: &persistence::MapFormat
+) -> Result<(persistence::MapFormat, MergeReport)> {
+    let mut report = MergeReport::default();
+    let tilesets = merge_tilesets(ours, theirs, &mut report);
+    let save_ids: HashMap<&str, SaveId> = tilesets
+        .iter()
+        .map(|(id, t)| (t.name.as_str(), *id))
+        .collect();
+
+    let base_layers = layer_tiles(base)?;
+    let ours_layers = layer_tiles(ours)?;
+    let theirs_layers = layer_tiles(theirs)?;
+
+    let mut names: Vec<&String> = ours_layers.iter().map(|(name, _)| name).collect();
+    for (name, _) in &theirs_layers {
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+
+    let find = |layers: &Vec<(String, LayerTiles)>, name: &String| -> Option<LayerTiles> {
+        layers.iter().find(|(n, _)| n == name).map(|(_, tiles)| tiles.clone())
+    };
+
+    let mut merged = persistence::MapFormat::new(ours.layout.clone());
+    merged.tilesets = tilesets;
+    merged.tileset_files = ours.tileset_files.clone();
+    let to_tile = |(y, x): (i32, i32), key: &TileKey| persistence::Tile {
+        location: map::Location { x, y },
+        tileset: save_ids[key.tileset.as_str()],
+        tile_id: key.tile_id,
+        rotation: key.rotation
+    };
+
+    for name in names {
+        let ours_tiles = find(&ours_layers, name);
+        let theirs_tiles = find(&theirs_layers, name);
+        let base_tiles = find(&base_layers, name).unwrap_or_default();
+
+        // a layer deleted on one side and untouched on the other stays deleted
+        match (&ours_tiles, &theirs_tiles) {
+            (None, Some(t)) | (Some(t), None) if base_layers.iter().any(|(n, _)| n == name) && *t == base_tiles => continue,
+            _ => ()
+        }
+        let ours_tiles = ours_tiles.unwrap_or_default();
+        let theirs_tiles = theirs_tiles.unwrap_or_default();
+
+        let mut locations: Vec<&(i32, i32)> = base_tiles
+            .keys()
+            .chain(ours_tiles.keys())
+            .chain(theirs_tiles.keys())
+            .collect();
+        locations.sort();
+        locations.dedup();
+
+        let mut layer = persistence::Layer { name: name.clone(), tiles: Vec::new() };
+        let mut conflicts = persistence::Layer {
+            name: format!("{}{}", CONFLICT_LAYER_PREFIX, name),
+            tiles: Vec::new()
+        };
+
+        for location in locations {
+            let (b, o, t) = (base_tiles.get(location), ours_tiles.get(location), theirs_tiles.get(location));
+            match merge_hex(b, o, t) {
+                Ok(Some(key)) => layer.tiles.push(to_tile(*location, &key)),
+                Ok(None) => (),
+                Err(()) => {
+                    if let Some(key) = o {
+                        layer.tiles.push(to_tile(*location, key));
+                    }
+                    if let Some(key) = t {
+                        conflicts.tiles.push(to_tile(*location, key));
+                    }
+                    report.hexes.push(HexConflict {
+                        layer: name.clone(),
+                        location: map::Location { x: location.1, y: location.0 },
+                        base: b.cloned(),
+                        ours: o.cloned(),
+                        theirs: t.cloned()
+                    });
+                }
+            }
+        }
+
+        merged.layers.push(layer);
+        if !conflicts.tiles.is_empty() {
+            merged.layers.push(conflicts);
+        }
+    }
+
+    Ok((merged, report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn forest() -> tileset::TileSet {
+        let mut forest = tileset::TileSet::new("forest");
+        for name in ["tree", "rock", "bush"] {
+            forest.add_title(PathBuf::from(format!("forest/{}.glb", name)));
+        }
+        forest
+    }
+
+    /// A map using the forest tileset, with tiles given as location and tile id
+    fn map_of(layers: &[(&str, &[((i32, i32), tileset::TileId)])]) -> persistence::MapFormat {
+        let mut map = persistence::MapFormat::new(hexx::HexLayout::default());
+        map.tilesets.insert(SaveId::from(0), forest());
+        for (name, tiles) in layers {
+            map.layers.push(persistence::Layer {
+                name: name.to_string(),
+                tiles: tiles
+                    .iter()
+                    .map(|(location, tile_id)| persistence::Tile {
+                        location: (*location).into(),
+                        tileset: SaveId::from(0),
+                        tile_id: *tile_id,
+                        rotation: tileset::TileRotation::default()
+                    })
+                    .collect()
+            });
+        }
+        map
+    }
+
+    fn tiles(map: &persistence::MapFormat, layer: &str) -> Vec<((i32, i32), tileset::TileId)> {
+        map.layers
+            .iter()
+            .find(|l| l.name == layer)
+            .map(|l| l.tiles.iter().map(|t| ((t.location.x, t.location.y), t.tile_id)).collect())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn changes_to_different_hexes_merge_cleanly() {
+        let base = map_of(&[("ground", &[((0, 0), 0), ((1, 0), 0)])]);
+        let ours = map_of(&[("ground", &[((0, 0), 0), ((1, 0), 0), ((2, 0), 1)])]);
+        let theirs = map_of(&[("ground", &[((0, 0), 2)])]);
+
+        let (merged, report) = merge(&base, &ours, &theirs).unwrap();
+
+        assert!(!report.has_conflicts());
+        assert_eq!(merged.layers.len(), 1);
+        assert_eq!(tiles(&merged, "ground"), vec![((0, 0), 2), ((2, 0), 1)]);
+    }
+
+    #[test]
+    fn conflicting_hexes_keep_ours_and_move_theirs_to_a_layer() {
+        let base = map_of(&[("ground", &[((0, 0), 0)])]);
+        let ours = map_of(&[("ground", &[((0, 0), 1)])]);
+        let theirs = map_of(&[("ground", &[((0, 0), 2)])]);
+
+        let (merged, report) = merge(&base, &ours, &theirs).unwrap();
+
+        assert_eq!(tiles(&merged, "ground"), vec![((0, 0), 1)]);
+        assert_eq!(tiles(&merged, "conflict: ground"), vec![((0, 0), 2)]);
+        assert_eq!(report.hexes.len(), 1);
+        let conflict = &report.hexes[0];
+        assert_eq!(conflict.layer, "ground");
+        assert_eq!(conflict.base.as_ref().map(|t| t.tile_id), Some(0));
+        assert_eq!(conflict.ours.as_ref().map(|t| t.tile_id), Some(1));
+        assert_eq!(conflict.theirs.as_ref().map(|t| t.tile_id), Some(2));
+    }
+
+    #[test]
+    fn layers_deleted_on_one_side_stay_deleted() {
+        let base = map_of(&[("ground", &[((0, 0), 0)]), ("trees", &[((0, 0), 1)])]);
+        let ours = map_of(&[("ground", &[((0, 0), 0)])]);
+        let theirs = map_of(&[("ground", &[((0, 0), 0)]), ("trees", &[((0, 0), 1)]), ("rocks", &[((1, 1), 1)])]);
+
+        let (merged, report) = merge(&base, &ours, &theirs).unwrap();
+
+        assert!(!report.has_conflicts());
+        let names: Vec<&str> = merged.layers.iter().map(|l| l.name.as_str()).collect();
+        assert_eq!(names, vec!["ground", "rocks"]);
+    }
+
+    #[test]
+    fn tilesets_are_merged_by_name() {
+        let base = map_of(&[("ground", &[((0, 0), 0)])]);
+        let mut ours = map_of(&[("ground", &[((0, 0), 0)])]);
+        ours.tilesets.get_mut(&SaveId::from(0)).unwrap().tiles.get_mut(&2).unwrap().path = "forest/fern.glb".into();
+
+        let mut theirs = map_of(&[("ground", &[((0, 0), 0)])]);
+        let mut town = tileset::TileSet::new("town");
+        town.add_title("town/house.glb".into());
+        // the same tileset under another id, with a new tile
+        let mut forest = theirs.tilesets.remove(&SaveId::from(0)).unwrap();
+        forest.add_title("forest/stump.glb".into());
+        theirs.tilesets.insert(SaveId::from(0), town);
+        theirs.tilesets.insert(SaveId::from(1), forest);
+        for tile in &mut theirs.layers[0].tiles {
+            tile.tileset = SaveId::from(1);
+        }
+        theirs.layers[0].tiles.push(persistence::Tile {
+            location: (1, 0).into(),
+            tileset: SaveId::from(0),
+            tile_id: 0,
+            rotation: tileset::TileRotation::default()
+        });
+
+        let (merged, report) = merge(&base, &ours, &theirs).unwrap();
+
+        let forest = &merged.tilesets[&SaveId::from(0)];
+        assert_eq!(forest.name, "forest");
+        assert_eq!(forest.tiles[&3].path, PathBuf::from("forest/stump.glb"));
+        assert_eq!(merged.tilesets[&SaveId::from(1)].name, "town");
+        assert_eq!(report.tiles, vec![("forest".to_string(), 2)]);
+        assert!(report.hexes.is_empty());
+        let ground = &merged.layers[0].tiles;
+        assert_eq!(ground.len(), 2);
+        assert_eq!(ground[1].tileset, SaveId::from(1));
+    }
+
+    #[test]
+    fn resolved_reports_are_removed() {
+        let dir = std::env::temp_dir().join(format!("world-editor-report-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let map_path = dir.join("world.map.ron");
+
+        let mut report = MergeReport::default();
+        report.tiles.push(("forest".into(), 2));
+        report.save(&map_path).unwrap();
+        assert_eq!(MergeReport::load(&map_path).unwrap().unwrap().tiles.len(), 1);
+
+        report.tiles.clear();
+        report.save(&map_path).unwrap();
+        let loaded = MergeReport::load(&map_path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(loaded.is_none());
+    }
+}
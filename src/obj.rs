@@ -0,0 +1,190 @@
+use anyhow::{ Context,Result };
+use bevy::{
+    asset::{ io::Reader,AssetLoader,LoadContext,RenderAssetUsages },
+    prelude::*,
+    render::mesh::{ Indices,PrimitiveTopology }
+};
+use std::{
+    collections::HashMap,
+    path::{ Path,PathBuf }
+};
+
+pub struct Plugin;
+
+impl bevy::app::Plugin for Plugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<Obj>()
+            .register_asset_loader(ObjLoader);
+    }
+}
+
+/// A loaded OBJ file. Like glTF files, the scene is available under the
+/// `Scene0` label, so `"model.obj#Scene0"` works wherever a GLB path does.
+#[derive(Asset, TypePath, Debug)]
+pub struct Obj {
+    pub scenes: Vec<Handle<Scene>>,
+    pub meshes: Vec<Handle<Mesh>>,
+    pub materials: Vec<Handle<StandardMaterial>>
+}
+
+/// Files named by `mtllib` statements, relative to the OBJ file
+pub fn material_libraries(obj: &str) -> Vec<PathBuf> {
+    obj.lines()
+        .filter_map(|line| line.trim().strip_prefix("mtllib "))
+        .map(|name| PathBuf::from(name.trim()))
+        .collect()
+}
+
+/// Parses an OBJ file; `mtl` maps the names returned by `material_libraries`
+/// to their contents. Missing libraries are logged and leave tiles untextured.
+pub fn parse(
+    obj: &[u8],
+    mtl: &HashMap<PathBuf, Vec<u8>>
+) -> Result<(Vec<tobj::Model>, Vec<tobj::Material>)> {
+    let (models, materials) = tobj::load_obj_buf(
+        &mut std::io::BufReader::new(obj),
+        &tobj::GPU_LOAD_OPTIONS,
+        |path| match mtl.get(path) {
+            Some(bytes) => tobj::load_mtl_buf(&mut std::io::BufReader::new(bytes.as_slice())),
+            None => Err(tobj::LoadError::OpenFileFailed)
+        }
+    )
+    .context("failed to parse obj")?;
+
+    let materials = materials.unwrap_or_else(|e| {
+        warn!("failed to load obj materials: {}", e);
+        Vec::new()
+    });
+
+    Ok((models, materials))
+}
+
+pub fn base_color(material: &tobj::Material) -> Color {
+    let [r, g, b] = material.diffuse.unwrap_or([1.0; 3]);
+    Color::srgba(r, g, b, material.dissolve.unwrap_or(1.0))
+}
+
+/// Maps the Phong exponent `Ns` to a PBR roughness
+pub fn roughness(material: &tobj::Material) -> f32 {
+    material
+        .shininess
+        .map_or(0.9, |ns| (2.0 / (ns.max(0.0) + 2.0)).sqrt())
+        .clamp(0.089, 1.0)
+}
+
+fn build_mesh(mesh: &tobj::Mesh) -> Mesh {
+    let positions: Vec<[f32; 3]> = mesh.positions
+        .chunks_exact(3)
+        .map(|p| [p[0], p[1], p[2]])
+        .collect();
+
+    let mut result = Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::default())
+        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+        .with_inserted_indices(Indices::U32(mesh.indices.clone()));
+
+    if !mesh.texcoords.is_empty() {
+        let uvs: Vec<[f32; 2]> = mesh.texcoords
+            .chunks_exact(2)
+            .map(|t| [t[0], 1.0 - t[1]])
+            .collect();
+        result.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    }
+
+    if mesh.normals.is_empty() {
+        result.compute_smooth_normals();
+    } else {
+        let normals: Vec<[f32; 3]> = mesh.normals
+            .chunks_exact(3)
+            .map(|n| [n[0], n[1], n[2]])
+            .collect();
+        result.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    }
+
+    result
+}
+
+#[derive(Default)]
+pub struct ObjLoader;
+
+impl AssetLoader for ObjLoader {
+    type Asset = Obj;
+    type Settings = ();
+    type Error = anyhow::Error;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        load_context: &mut LoadContext<'_>
+    ) -> Result<Obj> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let dir = load_context.path().parent().unwrap_or(Path::new("")).to_path_buf();
+
+        let mut mtl = HashMap::new();
+        for library in material_libraries(&String::from_utf8_lossy(&bytes)) {
+            match load_context.read_asset_bytes(dir.join(&library)).await {
+                Ok(buf) => {
+                    mtl.insert(library, buf);
+                }
+                Err(e) => warn!("failed to read material library {:?}: {}", library, e)
+            }
+        }
+
+        let (models, obj_materials) = parse(&bytes, &mtl)
+            .context(format!("failed to load {:?}", load_context.path()))?;
+
+        let mut materials = Vec::new();
+        for (index, material) in obj_materials.iter().enumerate() {
+            let texture = material
+                .diffuse_texture
+                .as_ref()
+                .map(|texture| load_context.load(dir.join(texture)));
+            let alpha = material.dissolve.unwrap_or(1.0);
+
+            materials.push(load_context.add_labeled_asset(format!("Material{}", index), StandardMaterial {
+                base_color: base_color(material),
+                base_color_texture: texture,
+                perceptual_roughness: roughness(material),
+                alpha_mode: if alpha < 1.0 { AlphaMode::Blend } else { AlphaMode::Opaque },
+                ..default()
+            }));
+        }
+        let default_material = load_context.add_labeled_asset(
+            "DefaultMaterial".to_string(),
+            StandardMaterial::default()
+        );
+
+        let mut meshes = Vec::new();
+        let mut world = World::default();
+        let mut root = world.spawn((Transform::default(), Visibility::default()));
+
+        for (index, model) in models.iter().enumerate() {
+            let mesh = load_context.add_labeled_asset(format!("Mesh{}", index), build_mesh(&model.mesh));
+            let material = model.mesh
+                .material_id
+                .and_then(|id| materials.get(id).cloned())
+                .unwrap_or_else(|| default_material.clone());
+
+            root.with_child((
+                Name::new(model.name.clone()),
+                Mesh3d(mesh.clone()),
+                MeshMaterial3d(material),
+                Transform::default()
+            ));
+            meshes.push(mesh);
+        }
+
+        let scene = load_context.add_labeled_asset("Scene0".to_string(), Scene::new(world));
+
+        Ok(Obj {
+            scenes: vec![scene],
+            meshes,
+            materials
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["obj"]
+    }
+}
@@ -1,7 +1,9 @@
 use std::{
     collections::{ BTreeMap,HashMap },
     fs::File,
-    path::PathBuf
+    io::Write,
+    path::{ Path,PathBuf },
+    sync::{ Arc,Mutex }
 };
 use anyhow::{ bail,Context,Result };
 use bevy::{
@@ -10,22 +12,193 @@ use bevy::{
 use futures_lite::future;
 use hexx::HexLayout;
 use serde:: {
-    de::Visitor,
+    de::{ DeserializeOwned,Visitor },
     Deserialize,
     Serialize
 };
 
-use crate::{ map,tileset };
+use crate::{ binary,map,tileset };
 
 pub struct Plugin;
 
 impl bevy::app::Plugin for Plugin {
     fn build(&self, app: &mut App) {
         app.register_type::<SaveId>()
+            .add_event::<IoCompleted>()
+            .init_resource::<SaveOptions>()
+            .init_resource::<TilesetCache>()
             .add_systems(Update, (map_writers, map_importer));
     }
 }
 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FileFormat {
+    #[default]
+    Ron,
+    Json
+}
+
+impl FileFormat {
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => FileFormat::Json,
+            _ => FileFormat::Ron
+        }
+    }
+
+    pub fn to_writer<W, T>(&self, writer: W, value: &T) -> Result<()>
+    where
+        W: std::io::Write,
+        T: Serialize
+    {
+        match self {
+            FileFormat::Ron => to_writer_pretty(writer, value, PrettyConfig::default())?,
+            FileFormat::Json => serde_json::to_writer_pretty(writer, value)?
+        }
+
+        Ok(())
+    }
+
+    fn to_string<T: Serialize>(&self, value: &T, pretty: bool) -> Result<String> {
+        let buf = match (self, pretty) {
+            (FileFormat::Ron, false) => ron::to_string(value)?,
+            (FileFormat::Ron, true) => ron::ser::to_string_pretty(value, PrettyConfig::default())?,
+            (FileFormat::Json, false) => serde_json::to_string(value)?,
+            (FileFormat::Json, true) => serde_json::to_string_pretty(value)?
+        };
+
+        Ok(buf)
+    }
+
+    pub fn from_str<T: DeserializeOwned>(&self, buf: &str) -> Result<T> {
+        let value = match self {
+            FileFormat::Ron => ron::from_str(buf)?,
+            FileFormat::Json => serde_json::from_str(buf)?
+        };
+
+        Ok(value)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IoOperation {
+    MapSave,
+    /// A copy of the map written for crash recovery
+    Autosave,
+    MapLoad,
+    TilesetImport,
+    TilesetExport
+}
+
+/// Sent when a background load or save has finished
+#[derive(Event, Clone, Debug)]
+pub struct IoCompleted {
+    pub operation: IoOperation,
+    pub path: PathBuf,
+    pub result: std::result::Result<(), IoError>
+}
+
+#[derive(Clone, Debug)]
+pub struct IoError {
+    /// The error followed by its causes
+    pub chain: Vec<String>,
+    /// Line and column of the first parse error in the chain
+    pub location: Option<(usize, usize)>
+}
+
+impl IoCompleted {
+    pub fn new<T>(operation: IoOperation, path: &Path, result: &Result<T>) -> Self {
+        Self {
+            operation,
+            path: path.to_path_buf(),
+            result: match result {
+                Ok(_) => Ok(()),
+                Err(e) => Err(IoError::from(e))
+            }
+        }
+    }
+}
+
+impl From<&anyhow::Error> for IoError {
+    fn from(error: &anyhow::Error) -> Self {
+        let location = error.chain().find_map(|cause| {
+            if let Some(e) = cause.downcast_ref::<ron::error::SpannedError>() {
+                Some((e.position.line, e.position.col))
+            } else {
+                cause
+                    .downcast_ref::<serde_json::Error>()
+                    .map(|e| (e.line(), e.column()))
+            }
+        });
+
+        Self {
+            chain: error.chain().map(|cause| cause.to_string()).collect(),
+            location
+        }
+    }
+}
+
+impl std::fmt::Display for IoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.chain.join(": "))?;
+        if let Some((line, column)) = self.location {
+            write!(f, " (line {}, column {})", line, column)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Number of `.bak` copies kept next to a saved file
+pub const BACKUP_COUNT: usize = 3;
+
+fn backup_path(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}.bak", n));
+    PathBuf::from(name)
+}
+
+/// Writes `path` through a temporary file in the same directory.
+///
+/// The temporary file is passed to `verify` before it replaces `path`, so a
+/// failed or truncated write never touches the existing file. The previous
+/// contents are kept as `<path>.1.bak` .. `<path>.<backups>.bak`.
+pub fn write_atomic(
+    path: &Path,
+    backups: usize,
+    write: impl FnOnce(File) -> Result<()>,
+    verify: impl FnOnce(&Path) -> Result<()>
+) -> Result<()> {
+    let mut tmp_name = path.as_os_str().to_owned();
+    tmp_name.push(".tmp");
+    let tmp = PathBuf::from(tmp_name);
+
+    let result = (|| {
+        let f = File::create(&tmp).context(format!("open {:?}", tmp))?;
+        write(f.try_clone()?)?;
+        f.sync_all().context(format!("sync {:?}", tmp))?;
+        verify(&tmp).context(format!("verifying {:?}", tmp))
+    })();
+    if let Err(e) = result {
+        let _ = std::fs::remove_file(&tmp);
+        return Err(e);
+    }
+
+    if path.exists() && backups > 0 {
+        for n in (1..backups).rev() {
+            let from = backup_path(path, n);
+            if from.exists() {
+                std::fs::rename(&from, backup_path(path, n + 1))
+                    .context(format!("rotating backup {:?}", from))?;
+            }
+        }
+        std::fs::copy(path, backup_path(path, 1))
+            .context(format!("backing up {:?}", path))?;
+    }
+
+    std::fs::rename(&tmp, path).context(format!("replacing {:?}", path))
+}
+
 #[derive(Clone, Component, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd, Reflect)]
 pub struct SaveId(usize);
 
@@ -61,6 +234,18 @@ impl <'de> Deserialize<'de> for SaveId {
     }
 }
 
+impl From<usize> for SaveId {
+    fn from(value: usize) -> Self {
+        Self(value)
+    }
+}
+
+impl From<SaveId> for usize {
+    fn from(value: SaveId) -> Self {
+        value.0
+    }
+}
+
 impl std::ops::Add<usize> for SaveId {
     type Output = Self;
 
@@ -123,18 +308,18 @@ impl WorldSaveIdExt for &mut World {
     }
 }
 
-#[derive(Default, Debug, Serialize, Deserialize)]
-struct Tile {
-    location: map::Location,
-    tileset: SaveId,
-    tile_id: tileset::TileId,
-    rotation: tileset::TileRotation
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+pub struct Tile {
+    pub location: map::Location,
+    pub tileset: SaveId,
+    pub tile_id: tileset::TileId,
+    pub rotation: tileset::TileRotation
 }
 
-#[derive(Default, Debug, Serialize, Deserialize)]
-struct Layer {
-    name: String,
-    tiles: Vec<Tile>
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+pub struct Layer {
+    pub name: String,
+    pub tiles: Vec<Tile>
 }
 
 impl From<&map::Layer> for Layer {
@@ -155,20 +340,401 @@ impl From<&Layer> for map::Layer {
     }
 }
 
-const MAP_FORMAT_VERSION: usize = 1;
+pub const MAP_FORMAT_VERSION: usize = 1;
+
+/// Controls how maps are written
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct SaveOptions {
+    /// Write every tile on its own line so that diffs show one line per changed hex
+    pub compact: bool,
+    /// Number of `.bak` copies to keep
+    pub backups: usize,
+    /// Store tilesets that have their own file in the map as well
+    pub embed_tilesets: bool,
+    /// Write edited tilesets back to their own file; otherwise the map keeps
+    /// a copy of them. Only explicit saves write tileset files.
+    pub write_tilesets: bool
+}
 
-#[derive(Default, Debug, Serialize, Deserialize)]
-struct MapFormat {
-    version: usize,
-    layout: HexLayout,
-    tilesets: BTreeMap<SaveId, tileset::TileSet>,
-    layers: Vec<Layer>,
+impl Default for SaveOptions {
+    fn default() -> Self {
+        Self {
+            compact: false,
+            backups: BACKUP_COUNT,
+            embed_tilesets: false,
+            write_tilesets: true
+        }
+    }
+}
+
+/// FNV-1a; stable across builds, unlike `DefaultHasher`
+pub fn content_hash(buf: &[u8]) -> u64 {
+    buf.iter().fold(0xcbf29ce484222325, |hash, b| (hash ^ *b as u64).wrapping_mul(0x100000001b3))
+}
+
+/// `path` relative to the directory `base`, falling back to `path` itself
+pub fn relative_path(path: &Path, base: &Path) -> PathBuf {
+    let (Ok(path_abs), Ok(base_abs)) = (std::path::absolute(path), std::path::absolute(base)) else {
+        return path.to_path_buf();
+    };
+
+    let mut path_components = path_abs.components().peekable();
+    let mut base_components = base_abs.components().peekable();
+    while let (Some(a), Some(b)) = (path_components.peek(), base_components.peek()) {
+        if a != b {
+            break;
+        }
+        path_components.next();
+        base_components.next();
+    }
+
+    let mut relative = PathBuf::new();
+    for _ in base_components {
+        relative.push("..");
+    }
+    relative.extend(path_components);
+
+    relative
+}
+
+/// Whether two tilesets are written the same way
+fn same_tileset(format: FileFormat, a: &tileset::TileSet, b: &tileset::TileSet) -> bool {
+    match (format.to_string(a, false), format.to_string(b, false)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false
+    }
+}
+
+/// Writes a tileset file atomically; returns the hash of the written contents
+pub fn write_tileset(path: &Path, tileset: &tileset::TileSet, backups: usize) -> Result<u64> {
+    let format = FileFormat::from_path(path);
+    let mut buf = Vec::new();
+    format.to_writer(&mut buf, tileset)?;
+
+    write_atomic(
+        path,
+        backups,
+        |mut f| f.write_all(&buf).map_err(Into::into),
+        |tmp| {
+            let written: tileset::TileSet = format.from_str(&std::fs::read_to_string(tmp)?)?;
+            if written.tiles.len() != tileset.tiles.len() {
+                bail!("written tileset does not match");
+            }
+
+            Ok(())
+        }
+    )
+    .context(format!("writing tileset to {:?}", path))?;
+
+    Ok(content_hash(&buf))
+}
+
+/// A tileset stored in its own file and referenced by maps
+#[derive(Component, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TilesetFile {
+    /// Relative to the map file when stored, as loaded when in memory
+    pub path: PathBuf,
+    /// Hash of the file contents when the map was last saved
+    #[serde(default)]
+    pub hash: u64
+}
+
+impl TilesetFile {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path, hash: 0 }
+    }
+}
+
+/// Set on tileset entities whose file changed since the map was last saved
+#[derive(Component, Debug)]
+pub struct TilesetFileChanged;
+
+/// Parsed tileset files by path and content hash, shared between map loads
+#[derive(Resource, Clone, Default)]
+pub struct TilesetCache(Arc<Mutex<HashMap<PathBuf, (u64, tileset::TileSet)>>>);
+
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+pub struct MapFormat {
+    #[serde(default)]
+    pub version: usize,
+    pub layout: HexLayout,
+    pub tilesets: BTreeMap<SaveId, tileset::TileSet>,
+    /// Tilesets referenced by path. Their contents are loaded into `tilesets`
+    /// and left out of it again when writing, unless the file could not be
+    /// written; then the map keeps a copy, which is used instead of the file.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tileset_files: BTreeMap<SaveId, TilesetFile>,
+    pub layers: Vec<Layer>,
+    /// Referenced tilesets whose file changed since the map was saved
+    #[serde(skip)]
+    pub changed_tilesets: Vec<SaveId>,
     #[serde(skip)]
     entity_map: HashMap<Entity, SaveId>
 }
 
 impl MapFormat {
-    fn try_new(world: &mut World, root: Entity) -> Result<Self> {
+    pub fn new(layout: HexLayout) -> Self {
+        Self {
+            version: MAP_FORMAT_VERSION,
+            layout,
+            ..default()
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::load_as(path, path)
+    }
+
+    /// Loads `path` using the format implied by the extension of `name`, for
+    /// files such as git's temporary merge files that have no extension
+    pub fn load_as(path: &Path, name: &Path) -> Result<Self> {
+        Self::load_cached(path, name, &TilesetCache::default())
+    }
+
+    /// Like `load_as`, reusing tileset files already parsed into `cache`
+    pub fn load_cached(path: &Path, name: &Path, cache: &TilesetCache) -> Result<Self> {
+        let mut map: MapFormat = match name.extension().and_then(|e| e.to_str()) {
+            _ if crate::tiled::is_tiled_path(name) => return crate::tiled::import(path),
+            _ if binary::Compression::from_path(name).is_some() => {
+                let f = File::open(path).context("failed to open file")?;
+                binary::read_map(f).context("failed to parse binary map")?
+            }
+            _ => {
+                let buf = std::fs::read_to_string(path).context("failed to read file")?;
+                FileFormat::from_path(name)
+                    .from_str(&buf)
+                    .context("failed to parse map")?
+            }
+        };
+
+        // `path` may be a temporary copy; references are relative to where the map lives
+        let dir = name.parent().unwrap_or(Path::new(""));
+        map.load_tileset_files(dir, cache);
+
+        Ok(map)
+    }
+
+    /// Missing or broken tileset files are skipped; their tiles load as placeholders
+    fn load_tileset_files(&mut self, dir: &Path, cache: &TilesetCache) {
+        for (id, file) in &mut self.tileset_files {
+            file.path = dir.join(&file.path);
+
+            let result = std::fs::read_to_string(&file.path)
+                .context(format!("failed to read tileset {:?}", file.path))
+                .and_then(|buf| {
+                    let hash = content_hash(buf.as_bytes());
+                    let mut cache = cache.0.lock().unwrap();
+                    if let Some((cached_hash, tileset)) = cache.get(&file.path) {
+                        if *cached_hash == hash {
+                            return Ok((hash, tileset.clone()));
+                        }
+                    }
+
+                    let tileset: tileset::TileSet = FileFormat::from_path(&file.path)
+                        .from_str(&buf)
+                        .context(format!("failed to parse tileset {:?}", file.path))?;
+                    cache.insert(file.path.clone(), (hash, tileset.clone()));
+
+                    Ok((hash, tileset))
+                });
+
+            match result {
+                Ok((hash, tileset)) => {
+                    if file.hash != 0 && file.hash != hash {
+                        self.changed_tilesets.push(*id);
+                    }
+                    file.hash = hash;
+                    // a copy stored in the map has edits the file is missing
+                    self.tilesets.entry(*id).or_insert(tileset);
+                }
+                Err(e) => warn!("{:?}", e)
+            }
+        }
+    }
+
+    /// The map as it is written to `path`: referenced tilesets are replaced by
+    /// their relative path, or everything is embedded if `embed_tilesets` is set.
+    ///
+    /// Also returns the referenced tilesets that were edited since their file
+    /// was loaded if `write_tilesets` is set; they have to be written before
+    /// the map. Other edited tilesets, and those whose file is missing, broken
+    /// or was changed by someone else, are kept in the map instead, so that
+    /// neither version is lost.
+    fn for_storage(&self, path: &Path, options: SaveOptions) -> (MapFormat, Vec<(SaveId, PathBuf, tileset::TileSet)>) {
+        let mut map = self.clone();
+        let mut edited = Vec::new();
+        if options.embed_tilesets {
+            map.tileset_files.clear();
+            return (map, edited);
+        }
+
+        let dir = path.parent().unwrap_or(Path::new(""));
+        for (id, file) in &mut map.tileset_files {
+            let tileset_path = file.path.clone();
+            file.path = relative_path(&tileset_path, dir);
+            let Some(tileset) = map.tilesets.get(id) else { continue };
+
+            let format = FileFormat::from_path(&tileset_path);
+            let on_disk = std::fs::read(&tileset_path).ok().map(|buf| {
+                let parsed = std::str::from_utf8(&buf)
+                    .ok()
+                    .and_then(|buf| format.from_str::<tileset::TileSet>(buf).ok());
+                (content_hash(&buf), parsed)
+            });
+
+            match on_disk {
+                Some((hash, Some(on_disk))) if same_tileset(format, &on_disk, tileset) => {
+                    file.hash = hash;
+                    map.tilesets.remove(id);
+                }
+                Some((hash, Some(_))) if hash == file.hash && options.write_tilesets => {
+                    edited.push((*id, tileset_path, map.tilesets.remove(id).unwrap()));
+                }
+                Some((hash, Some(_))) if hash == file.hash => ()
+                _ => warn!("keeping a copy of tileset {} in the map; {:?} is missing or changed", tileset.name, tileset_path)
+            }
+        }
+
+        (map, edited)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        self.write_with_options(path, SaveOptions::default())
+    }
+
+    pub fn write_with_options(&self, path: &Path, options: SaveOptions) -> Result<()> {
+        self.write_as(path, path, options)
+    }
+
+    /// Writes `path` in the format implied by the extension of `name`
+    pub fn write_as(&self, path: &Path, name: &Path, options: SaveOptions) -> Result<()> {
+        self.write_stored(path, name, options).map(|_| ())
+    }
+
+    /// Like `write_as`, returning the map as written. Edited tilesets with
+    /// their own file are written first, the hashes in `tileset_files` are
+    /// those of the written files.
+    pub fn write_stored(&self, path: &Path, name: &Path, options: SaveOptions) -> Result<MapFormat> {
+        if crate::tiled::is_tiled_path(name) {
+            bail!("{:?} is a Tiled map; export it with `tiled::export` instead", name);
+        }
+        let (mut stored, edited) = self.for_storage(name, options);
+        for (id, tileset_path, tileset) in edited {
+            let hash = write_tileset(&tileset_path, &tileset, options.backups)?;
+            if let Some(file) = stored.tileset_files.get_mut(&id) {
+                file.hash = hash;
+            }
+        }
+
+        write_atomic(
+            path,
+            options.backups,
+            |f| match binary::Compression::from_path(name) {
+                Some(compression) => binary::write_map(f, &stored, compression),
+                None if options.compact => stored.write_compact(f, FileFormat::from_path(name)),
+                None => FileFormat::from_path(name).to_writer(f, &stored)
+            },
+            |tmp| {
+                let written = match binary::Compression::from_path(name) {
+                    Some(_) => binary::read_map(File::open(tmp)?)?,
+                    None => FileFormat::from_path(name).from_str(&std::fs::read_to_string(tmp)?)?
+                };
+                if written.layers.len() != stored.layers.len()
+                    || written.tilesets.len() != stored.tilesets.len()
+                    || written.tileset_files.len() != stored.tileset_files.len()
+                {
+                    bail!("written map does not match");
+                }
+
+                Ok(())
+            }
+        )
+        .context(format!("writing map to {:?}", path))?;
+
+        Ok(stored)
+    }
+
+    /// Pretty printed like `FileFormat::to_writer`, except that each tile is
+    /// written on a single line.
+    fn write_compact(&self, writer: impl std::io::Write, format: FileFormat) -> Result<()> {
+        let mut w = std::io::BufWriter::new(writer);
+        let json = format == FileFormat::Json;
+        let (open, close) = if json { ("{", "}") } else { ("(", ")") };
+        let key = |name: &str| if json { format!("\"{}\": ", name) } else { format!("{}: ", name) };
+        let sep = |i: usize, len: usize| if json && i + 1 == len { "" } else { "," };
+
+        writeln!(w, "{}", open)?;
+        writeln!(w, "    {}{},", key("version"), self.version)?;
+        writeln!(w, "    {}{},", key("layout"), format.to_string(&self.layout, false)?)?;
+
+        writeln!(w, "    {}{{", key("tilesets"))?;
+        for (i, (id, tileset)) in self.tilesets.iter().enumerate() {
+            let tileset = format
+                .to_string(tileset, true)?
+                .replace('\n', "\n        ");
+            writeln!(w, "        {}{}{}", key(&id.0.to_string()), tileset, sep(i, self.tilesets.len()))?;
+        }
+        writeln!(w, "    }},")?;
+
+        if !self.tileset_files.is_empty() {
+            writeln!(w, "    {}{{", key("tileset_files"))?;
+            for (i, (id, file)) in self.tileset_files.iter().enumerate() {
+                writeln!(
+                    w,
+                    "        {}{}{}",
+                    key(&id.0.to_string()),
+                    format.to_string(file, false)?,
+                    sep(i, self.tileset_files.len())
+                )?;
+            }
+            writeln!(w, "    }},")?;
+        }
+
+        writeln!(w, "    {}[", key("layers"))?;
+        for (i, layer) in self.layers.iter().enumerate() {
+            writeln!(w, "        {}", open)?;
+            writeln!(w, "            {}{},", key("name"), format.to_string(&layer.name, false)?)?;
+            writeln!(w, "            {}[", key("tiles"))?;
+            for (j, tile) in layer.tiles.iter().enumerate() {
+                writeln!(w, "                {}{}", format.to_string(tile, false)?, sep(j, layer.tiles.len()))?;
+            }
+            writeln!(w, "            ]{}", if json { "" } else { "," })?;
+            writeln!(w, "        {}{}", close, sep(i, self.layers.len()))?;
+        }
+        writeln!(w, "    ]{}", if json { "" } else { "," })?;
+        writeln!(w, "{}", close)?;
+        w.flush()?;
+
+        Ok(())
+    }
+
+    /// Migrates a map written by an older version of the editor; returns
+    /// whether anything changed
+    pub fn upgrade(&mut self) -> Result<bool> {
+        if self.version > MAP_FORMAT_VERSION {
+            bail!(
+                "map version {} is newer than supported version {}",
+                self.version,
+                MAP_FORMAT_VERSION
+            );
+        }
+        if self.version == MAP_FORMAT_VERSION {
+            return Ok(false);
+        }
+
+        // version 1 is the first versioned format; older files only lack the number
+        self.version = MAP_FORMAT_VERSION;
+
+        Ok(true)
+    }
+
+    pub fn map(&self) -> map::Map {
+        map::Map {
+            layout: self.layout.clone()
+        }
+    }
+
+    pub fn try_new(world: &mut World, root: Entity) -> Result<Self> {
         let mut map = Self {
             version: MAP_FORMAT_VERSION,
             ..default()
@@ -194,27 +760,33 @@ impl MapFormat {
         mut world: &mut World,
         root: Entity 
     ) -> Result<&mut Self> {
-        let mut query = world.query_filtered::<(Entity, &ChildOf), With<tileset::TileSet>>();
-        let tilesets: Vec<Entity> = query
+        let mut query = world.query_filtered::<(Entity, &ChildOf, &tileset::TileSet, Option<&SaveId>), With<tileset::TileSet>>();
+        let mut tilesets: Vec<(Option<SaveId>, String, Entity)> = query
             .iter(world)
-            .filter_map(|(entity, child_of)| {
+            .filter_map(|(entity, child_of, tileset, id)| {
                 if child_of.parent() == root {
-                    Some(entity)
+                    Some((id.copied(), tileset.name.clone(), entity))
                 } else {
                     None 
                 }
             })
             .collect();
+        // tilesets that already have an id keep it; new ones are numbered by name
+        tilesets.sort_by(|a, b| (a.0.is_none(), a.0, &a.1).cmp(&(b.0.is_none(), b.0, &b.1)));
+        let tilesets: Vec<Entity> = tilesets.into_iter().map(|(_, _, entity)| entity).collect();
         self.entity_map = world.assign_save_ids(tilesets.iter().cloned())?;
 
-        let mut query = world.query::<&tileset::TileSet>();
+        let mut query = world.query::<(&tileset::TileSet, Option<&TilesetFile>)>();
         for entity in tilesets {
             let id = self
                 .entity_map
                 .get(&entity)
                 .context(format!("failed to get SaveId for TileSet {:?}", entity))?;
-            let tileset = query.get(world, entity)?;
+            let (tileset, file) = query.get(world, entity)?;
             self.tilesets.insert(*id, tileset.clone());
+            if let Some(file) = file {
+                self.tileset_files.insert(*id, file.clone());
+            }
         }
 
         Ok(self)
@@ -225,13 +797,16 @@ impl MapFormat {
         world: &mut World,
         root: Entity 
     ) -> Result<&mut Self> {
-        let mut query = world.query::<(&map::Layer, &ChildOf, &Children)>();
+        let mut query = world.query::<(&map::Layer, &Children)>();
         let mut tiles = world.query::<(&map::Location, &tileset::TileRef, &tileset::TileTransform)>();
 
-        for (layer, child_of, children) in query.iter(world) {
-            if child_of.parent() != root {
-                continue;
-            }
+        // layers are written in the order of the map's children
+        let root_children: Vec<Entity> = world
+            .get::<Children>(root)
+            .map(|children| children.to_vec())
+            .unwrap_or_default();
+
+        for (layer, children) in query.iter_many(world, &root_children) {
             let mut layer: Layer = layer.into();
 
             for child in children {
@@ -249,6 +824,9 @@ impl MapFormat {
                 };
                 layer.tiles.push(tile);
             }
+            layer
+                .tiles
+                .sort_by_key(|t| (t.location.y, t.location.x, t.tileset, t.tile_id));
             self.layers.push(layer);
         }
 
@@ -256,9 +834,9 @@ impl MapFormat {
     }
 
     pub fn try_spawn(
-        &self,
+        self,
         root: &mut EntityCommands
-    ) -> Result<()> {
+    ) -> Result<MapSpawner> {
         if self.version != MAP_FORMAT_VERSION {
             bail!(
                 "unsupported map version: {} != {}",
@@ -268,20 +846,58 @@ impl MapFormat {
         }
         debug!("loading map into {:?}", root.id());
 
-        let map = map::Map {
-            layout: self.layout.clone()
-        };
+        let map = self.map();
 
-        let mut entity_map = HashMap::new();
+        let mut tilesets = HashMap::new();
         for (id, tileset) in &self.tilesets {
             let entity = root
                 .commands()
-                .spawn((Name::new("tileset"), tileset.clone()))
+                .spawn((Name::new("tileset"), tileset.clone(), *id))
+                .id();
+            root.add_child(entity);
+            tilesets.insert(*id, entity);
+        }
+
+        for (id, file) in &self.tileset_files {
+            let entity = match tilesets.get(id) {
+                Some(entity) => *entity,
+                None => {
+                    // the file could not be loaded; keep the reference for saving
+                    let name = file.path.file_stem().unwrap_or_default().to_string_lossy();
+                    let entity = root
+                        .commands()
+                        .spawn((Name::new("tileset"), tileset::TileSet::new(&name), *id))
+                        .id();
+                    root.add_child(entity);
+                    tilesets.insert(*id, entity);
+                    entity
+                }
+            };
+            root.commands().entity(entity).insert(file.clone());
+            if self.changed_tilesets.contains(id) {
+                root.commands().entity(entity).insert(TilesetFileChanged);
+            }
+        }
+
+        // keep tiles of tilesets missing from the file; they load as placeholders
+        for tile in self.layers.iter().flat_map(|l| &l.tiles) {
+            if tilesets.contains_key(&tile.tileset) {
+                continue;
+            }
+            warn!("map references unknown tileset {:?}", tile.tileset);
+            let entity = root
+                .commands()
+                .spawn((
+                    Name::new("tileset"),
+                    tileset::TileSet::new(&format!("missing tileset {}", tile.tileset.0)),
+                    tile.tileset
+                ))
                 .id();
             root.add_child(entity);
-            entity_map.insert(id, entity);
+            tilesets.insert(tile.tileset, entity);
         }
 
+        let mut layers = Vec::new();
         for layer in &self.layers {
             let layer_component: map::Layer = layer.into();
             let layer_entity = root
@@ -294,17 +910,69 @@ impl MapFormat {
                 ))
                 .id();
             root.add_child(layer_entity);
+            layers.push(layer_entity);
+        }
 
-            let mut tiles = Vec::new();
+        root.insert((Transform::default(), Visibility::default(), map));
+
+        let total = self.layers.iter().map(|l| l.tiles.len()).sum();
+
+        Ok(MapSpawner {
+            map: self,
+            tilesets,
+            layers,
+            layer: 0,
+            tile: 0,
+            spawned: 0,
+            total
+        })
+    }
+}
 
-            for tile in &layer.tiles {
+const SPAWN_BATCH_SIZE: usize = 4096;
+
+/// Spawns the tiles of a loaded map over several frames
+pub struct MapSpawner {
+    map: MapFormat,
+    tilesets: HashMap<SaveId, Entity>,
+    layers: Vec<Entity>,
+    layer: usize,
+    tile: usize,
+    spawned: usize,
+    total: usize
+}
+
+impl MapSpawner {
+    pub fn progress(&self) -> f32 {
+        if self.total == 0 {
+            1.0
+        } else {
+            self.spawned as f32 / self.total as f32
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.layer >= self.map.layers.len()
+    }
+
+    fn spawn_batch(&mut self, commands: &mut Commands, batch_size: usize) -> Result<()> {
+        let mut remaining = batch_size;
+
+        while remaining > 0 && !self.is_done() {
+            let layer = &self.map.layers[self.layer];
+            let end = (self.tile + remaining).min(layer.tiles.len());
+            let mut tiles = Vec::with_capacity(end - self.tile);
+
+            for tile in &layer.tiles[self.tile..end] {
                 let tile_ref = tileset::TileRef {
-                    tileset: *entity_map.get(&tile.tileset).unwrap(),
+                    tileset: *self
+                        .tilesets
+                        .get(&tile.tileset)
+                        .context(format!("tileset SaveId not found: {:?}", tile.tileset))?,
                     tile: tile.tile_id,
                 };
 
-                let tile_entity = root
-                    .commands()
+                let tile_entity = commands
                     .spawn((
                         tile.location,
                         tile_ref,
@@ -318,13 +986,18 @@ impl MapFormat {
                 tiles.push(tile_entity);
             }
 
-            root
-                .commands()
-                .entity(layer_entity)
+            commands
+                .entity(self.layers[self.layer])
                 .add_children(&tiles);
-        }
 
-        root.insert((Transform::default(), Visibility::default(), map));
+            remaining -= end - self.tile;
+            self.spawned += end - self.tile;
+            self.tile = end;
+            if self.tile >= layer.tiles.len() {
+                self.layer += 1;
+                self.tile = 0;
+            }
+        }
 
         Ok(())
     }
@@ -333,16 +1006,27 @@ impl MapFormat {
 pub struct SaveMapCommand {
     path: PathBuf,
     map: Entity,
+    operation: IoOperation
 }
 
 impl SaveMapCommand {
     pub fn new(path: PathBuf, map: Entity) -> Self {
-        Self { path,map }
+        Self { path,map,operation: IoOperation::MapSave }
+    }
+
+    /// Completion is reported as `IoOperation::Autosave`
+    pub fn autosave(path: PathBuf, map: Entity) -> Self {
+        Self { path,map,operation: IoOperation::Autosave }
     }
 }
 
 impl Command for SaveMapCommand {
     fn apply(self, world: &mut World) {
+        let mut options = *world.resource::<SaveOptions>();
+        // autosaves go to the recovery directory and leave the user's files alone
+        if self.operation == IoOperation::Autosave {
+            options.write_tilesets = false;
+        }
         let map = match MapFormat::try_new(world, self.map) {
             Ok(map) => map,
             Err(err) => {
@@ -351,30 +1035,49 @@ impl Command for SaveMapCommand {
             }
         };
 
+        let path = self.path.clone();
         let task_pool = IoTaskPool::get();
         let task = task_pool.spawn(async move {
-            let f = File::create(self.path.clone()).context(format!("open map: {:?}", self.path))?;
-            to_writer_pretty(f, &map, PrettyConfig::default()).context(format!("writing map to {:?}", self.path))?;
+            let stored = map.write_stored(&self.path, &self.path, options)?;
 
-            Ok::<(), anyhow::Error>(())
+            Ok::<BTreeMap<SaveId, TilesetFile>, anyhow::Error>(stored.tileset_files)
         });
 
-        world.spawn(MapWriterTask(task));
+        world.spawn(MapWriterTask { path, map: self.map, operation: self.operation, task });
     }
 }
 
 #[derive(Component)]
-struct MapWriterTask(Task<Result<()>>);
+struct MapWriterTask {
+    path: PathBuf,
+    map: Entity,
+    operation: IoOperation,
+    task: Task<Result<BTreeMap<SaveId, TilesetFile>>>
+}
 
 fn map_writers(
     mut commands: Commands,  
-    mut map_writers: Query<(Entity, &mut MapWriterTask)>
+    mut events: EventWriter<IoCompleted>,
+    mut map_writers: Query<(Entity, &mut MapWriterTask)>,
+    mut tileset_files: Query<(&SaveId, &ChildOf, &mut TilesetFile)>
 ) {
     for (entity, mut writer) in &mut map_writers {
-        let Some(result) = future::block_on(future::poll_once(&mut writer.0)) else { continue };
-        if let Err(e) = result {
-            warn!("{:#?}", e);
+        let Some(result) = future::block_on(future::poll_once(&mut writer.task)) else { continue };
+        match &result {
+            // tileset files written along with the map are not changes on disk
+            Ok(written) => {
+                for (id, child_of, mut file) in &mut tileset_files {
+                    if child_of.parent() != writer.map {
+                        continue;
+                    }
+                    if let Some(written) = written.get(id) {
+                        file.hash = written.hash;
+                    }
+                }
+            }
+            Err(e) => warn!("{:#?}", e)
         }
+        events.write(IoCompleted::new(writer.operation, &writer.path, &result));
         commands.entity(entity).despawn();
     }
 }
@@ -382,60 +1085,216 @@ fn map_writers(
 #[derive(Component)]
 pub struct MapImporter {
     path: PathBuf,
-    task: Task<Result<MapFormat>>
+    task: Option<Task<Result<MapFormat>>>,
+    spawner: Option<MapSpawner>
 }
 
 impl MapImporter {
+    /// Loading starts on the next `map_importer` run
     pub fn new(path: PathBuf) -> Self {
-        let path_copy = path.clone();
-        let task_pool = IoTaskPool::get();
-        let task = task_pool.spawn(async move {
-            let buf = std::fs::read_to_string(path).context("failed to read file")?;
-            let map = ron::from_str(&buf).context("failed to parse map")?;
-
-            Ok(map)
-        });
+        Self {
+            path,
+            task: None,
+            spawner: None
+        }
+    }
 
+    /// Spawns a map that is already loaded; `path` is where it came from
+    pub fn from_map(path: PathBuf, map: MapFormat) -> Self {
         Self {
-            path: path_copy,
-            task
+            path,
+            task: Some(IoTaskPool::get().spawn(async move { Ok(map) })),
+            spawner: None
         }
     }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn progress(&self) -> f32 {
+        self.spawner.as_ref().map(|s| s.progress()).unwrap_or(0.0)
+    }
 }
 
 fn map_importer(
     mut commands: Commands,
+    mut events: EventWriter<IoCompleted>,
+    cache: Res<TilesetCache>,
     mut map_importers: Query<(Entity, &mut MapImporter)>
 ) {
     for (entity, mut importer) in &mut map_importers {
-        let Some(result) = future::block_on(future::poll_once(&mut importer.task)) else { continue };
-        match result {
-            Err(e) => {
-                warn!(
-                    "failed to load map{}: {:?}",
-                    importer.path.to_string_lossy(),
-                    e 
-                );
-                commands.entity(entity).despawn();
-            }
-            Ok(map) => {
-                let name = importer.path.file_stem().unwrap().to_string_lossy();
-                let mut entity_ref = commands.entity(entity);
+        if importer.task.is_none() && importer.spawner.is_none() {
+            let path = importer.path.clone();
+            let cache = cache.clone();
+            let task_pool = IoTaskPool::get();
+            importer.task = Some(task_pool.spawn(async move {
+                MapFormat::load_cached(&path, &path, &cache)
+            }));
+        }
+
+        if let Some(task) = importer.task.as_mut() {
+            let Some(result) = future::block_on(future::poll_once(task)) else { continue };
+            importer.task = None;
 
-                if let Err(e) = map.try_spawn(&mut entity_ref) {
-                    error!(
-                        "failed to spawn map {}: {:?}",
+            // older maps are migrated in memory; they are written in the current format on save
+            let spawned = result.and_then(|mut map| {
+                if map.upgrade()? {
+                    info!("upgraded map {} to version {}", importer.path.to_string_lossy(), map.version);
+                }
+                map.try_spawn(&mut commands.entity(entity))
+            });
+            match spawned {
+                Err(e) => {
+                    warn!(
+                        "failed to load map {}: {:?}",
                         importer.path.to_string_lossy(),
                         e 
                     );
-                    entity_ref.despawn();
+                    events.write(IoCompleted::new(IoOperation::MapLoad, &importer.path, &Err::<(), _>(e)));
+                    commands.entity(entity).despawn();
                     continue;
                 }
-
-                entity_ref
-                    .remove::<MapImporter>()
-                    .insert(Name::new(format!("map: {}", name)));
+                Ok(spawner) => importer.spawner = Some(spawner)
             }
-        };
+        }
+
+        let Some(spawner) = importer.spawner.as_mut() else { continue };
+        if let Err(e) = spawner.spawn_batch(&mut commands, SPAWN_BATCH_SIZE) {
+            error!(
+                "failed to spawn map {}: {:?}",
+                importer.path.to_string_lossy(),
+                e 
+            );
+            events.write(IoCompleted::new(IoOperation::MapLoad, &importer.path, &Err::<(), _>(e)));
+            commands.entity(entity).despawn();
+            continue;
+        }
+        debug!(
+            "loading map {}: {:.0}%",
+            importer.path.to_string_lossy(),
+            spawner.progress() * 100.0
+        );
+
+        if spawner.is_done() {
+            let name = importer.path.file_stem().unwrap().to_string_lossy();
+            commands
+                .entity(entity)
+                .remove::<MapImporter>()
+                .insert(Name::new(format!("map: {}", name)));
+            events.write(IoCompleted::new(IoOperation::MapLoad, &importer.path, &Ok(())));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Spawns tilesets in the order of `names` and the tiles of one layer in
+    /// the order of `tiles`
+    fn spawn_map(world: &mut World, names: &[&str], tiles: &[((i32, i32), &str)]) -> Entity {
+        let root = world.spawn(map::Map::default()).id();
+        let mut tilesets = HashMap::new();
+        for name in names {
+            let mut tileset = tileset::TileSet::new(name);
+            tileset.add_title(PathBuf::from(format!("{}.glb", name)));
+            tilesets.insert(*name, world.spawn((tileset, ChildOf(root))).id());
+        }
+
+        let layer = world.spawn((map::Layer::new("ground".into()), ChildOf(root))).id();
+        for (location, name) in tiles {
+            world.spawn((
+                map::Location::from(*location),
+                tileset::TileRef { tileset: tilesets[name], tile: 0 },
+                tileset::TileTransform::default(),
+                ChildOf(layer)
+            ));
+        }
+        root
+    }
+
+    fn saved(world: &mut World, root: Entity) -> String {
+        let map = MapFormat::try_new(world, root).unwrap();
+        FileFormat::Ron.to_string(&map, true).unwrap()
+    }
+
+    #[test]
+    fn saves_do_not_depend_on_entity_order() {
+        let mut a = World::new();
+        let root_a = spawn_map(&mut a, &["town", "forest"], &[((1, 0), "forest"), ((0, 0), "town"), ((0, 1), "forest")]);
+        let mut b = World::new();
+        let root_b = spawn_map(&mut b, &["forest", "town"], &[((0, 1), "forest"), ((1, 0), "forest"), ((0, 0), "town")]);
+
+        assert_eq!(saved(&mut a, root_a), saved(&mut b, root_b));
+
+        let map = MapFormat::try_new(&mut a, root_a).unwrap();
+        assert_eq!(map.tilesets[&SaveId(0)].name, "forest");
+        assert_eq!(map.tilesets[&SaveId(1)].name, "town");
+        let locations: Vec<(i32, i32)> = map.layers[0].tiles.iter().map(|t| (t.location.x, t.location.y)).collect();
+        assert_eq!(locations, vec![(0, 0), (1, 0), (0, 1)]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn tilesets_keep_their_save_id() {
+        let mut world = World::new();
+        let root = spawn_map(&mut world, &["forest", "town"], &[((0, 0), "town")]);
+        let mut query = world.query::<(Entity, &tileset::TileSet)>();
+        let town = query.iter(&world).find(|(_, t)| t.name == "town").unwrap().0;
+        world.entity_mut(town).insert(SaveId(0));
+
+        let map = MapFormat::try_new(&mut world, root).unwrap();
+        assert_eq!(map.tilesets[&SaveId(0)].name, "town");
+        assert_eq!(map.tilesets[&SaveId(1)].name, "forest");
+        assert_eq!(map.layers[0].tiles[0].tileset, SaveId(0));
+    }
+
+    #[test]
+    fn compact_maps_have_one_tile_per_line() {
+        let mut world = World::new();
+        let root = spawn_map(&mut world, &["forest"], &[((0, 0), "forest"), ((1, 0), "forest"), ((2, 3), "forest")]);
+        let mut map = MapFormat::try_new(&mut world, root).unwrap();
+        map.tileset_files.insert(SaveId(0), TilesetFile::new("forest.tileset.ron".into()));
+
+        for format in [FileFormat::Ron, FileFormat::Json] {
+            let mut buf = Vec::new();
+            map.write_compact(&mut buf, format).unwrap();
+            let text = String::from_utf8(buf).unwrap();
+
+            assert_eq!(text.lines().filter(|line| line.contains("tile_id")).count(), 3);
+            let read: MapFormat = format.from_str(&text).unwrap();
+            assert_eq!(read.layers[0].tiles.len(), 3);
+            assert_eq!(read.tileset_files[&SaveId(0)].path, PathBuf::from("forest.tileset.ron"));
+            assert_eq!(format.to_string(&read, true).unwrap(), format.to_string(&map, true).unwrap());
+        }
+    }
+
+    #[test]
+    fn edited_tilesets_are_written_only_when_asked() {
+        let dir = std::env::temp_dir().join(format!("world-editor-save-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let tileset_path = dir.join("forest.tileset.ron");
+        let mut forest = tileset::TileSet::new("forest");
+        forest.add_title(PathBuf::from("forest/tree.glb"));
+        let hash = write_tileset(&tileset_path, &forest, 0).unwrap();
+
+        let mut map = MapFormat::new(HexLayout::default());
+        forest.add_title(PathBuf::from("forest/rock.glb"));
+        map.tilesets.insert(SaveId(0), forest);
+        map.tileset_files.insert(SaveId(0), TilesetFile { path: tileset_path.clone(), hash });
+
+        let autosave = SaveOptions { write_tilesets: false, backups: 0, ..default() };
+        let kept = map.write_stored(&dir.join("autosave.map.ron"), &dir.join("autosave.map.ron"), autosave).unwrap();
+        let after_autosave = std::fs::read(&tileset_path).unwrap();
+        let saved = map
+            .write_stored(&dir.join("world.map.ron"), &dir.join("world.map.ron"), SaveOptions { backups: 0, ..default() })
+            .unwrap();
+        let after_save: tileset::TileSet = FileFormat::Ron.from_str(&std::fs::read_to_string(&tileset_path).unwrap()).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(content_hash(&after_autosave), hash);
+        assert_eq!(kept.tilesets[&SaveId(0)].tiles.len(), 2);
+        assert!(saved.tilesets.is_empty());
+        assert_eq!(after_save.tiles.len(), 2);
+    }
+}
@@ -0,0 +1,303 @@
+use anyhow::{ Context,Result };
+use bevy::{
+    prelude::*,
+    scene::ron
+};
+use chrono::{ DateTime,Local };
+use serde::{ Deserialize,Serialize };
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    path::{ Path,PathBuf },
+    time::Duration
+};
+
+use crate::{ map,tileset };
+
+pub struct Plugin;
+
+impl bevy::app::Plugin for Plugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RecoverySettings>()
+            .init_resource::<Journal>()
+            .add_systems(PreStartup, start_session)
+            .add_systems(Last, (flush_journal, end_session));
+    }
+}
+
+#[derive(Resource, Debug, Clone)]
+pub struct RecoverySettings {
+    pub dir: PathBuf,
+    pub interval: Duration,
+    pub enabled: bool
+}
+
+impl Default for RecoverySettings {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from(".recovery"),
+            interval: Duration::from_secs(120),
+            enabled: true
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    pub id: String,
+    pub started: DateTime<Local>,
+    pub autosaved: Option<DateTime<Local>>,
+    pub map_path: Option<PathBuf>,
+    #[serde(skip)]
+    pub dir: PathBuf
+}
+
+impl Session {
+    const SESSION_FILE: &'static str = "session.ron";
+    const MAP_FILE: &'static str = "autosave.ron";
+    const JOURNAL_FILE: &'static str = "journal.ron";
+    /// Edits contained in an autosave that has not finished yet
+    const SAVING_JOURNAL_FILE: &'static str = "journal.saving.ron";
+
+    fn new(settings: &RecoverySettings) -> Self {
+        let started = Local::now();
+        let id = format!("{}-{}", started.format("%Y%m%d-%H%M%S"), std::process::id());
+
+        Self {
+            dir: settings.dir.join(&id),
+            id,
+            started,
+            autosaved: None,
+            map_path: None
+        }
+    }
+
+    pub fn load(dir: &Path) -> Result<Self> {
+        let buf = std::fs::read_to_string(dir.join(Self::SESSION_FILE))
+            .context(format!("failed to read session {:?}", dir))?;
+        let mut session: Session = ron::from_str(&buf).context("failed to parse session")?;
+        session.dir = dir.to_path_buf();
+
+        Ok(session)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        std::fs::create_dir_all(&self.dir).context(format!("failed to create {:?}", self.dir))?;
+        let buf = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?;
+        std::fs::write(self.dir.join(Self::SESSION_FILE), buf)
+            .context(format!("failed to write session {:?}", self.dir))?;
+
+        Ok(())
+    }
+
+    pub fn map_file(&self) -> PathBuf {
+        self.dir.join(Self::MAP_FILE)
+    }
+
+    pub fn journal_file(&self) -> PathBuf {
+        self.dir.join(Self::JOURNAL_FILE)
+    }
+
+    fn saving_journal_file(&self) -> PathBuf {
+        self.dir.join(Self::SAVING_JOURNAL_FILE)
+    }
+
+    pub fn has_autosave(&self) -> bool {
+        self.map_file().exists()
+    }
+
+    /// Journaled edits, oldest first. Replaying edits the autosave already
+    /// contains gives the same result, so edits of an unfinished autosave are
+    /// included.
+    pub fn journal(&self) -> Result<Vec<JournalEntry>> {
+        let mut entries = Vec::new();
+        for path in [self.saving_journal_file(), self.journal_file()] {
+            let buf = match std::fs::read_to_string(&path) {
+                Ok(buf) => buf,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e).context("failed to read journal")
+            };
+
+            // a crash can leave a partially written last line; keep everything before it
+            entries.extend(buf.lines().map_while(|line| ron::from_str::<JournalEntry>(line).ok()));
+        }
+
+        Ok(entries)
+    }
+
+    /// Records the map file that is open, so that it can be recovered before
+    /// the first autosave
+    pub fn set_map_path(&mut self, map_path: Option<PathBuf>) -> Result<()> {
+        if self.map_path == map_path {
+            return Ok(());
+        }
+        self.map_path = map_path;
+        self.save()
+    }
+
+    pub fn discard(&self) -> Result<()> {
+        std::fs::remove_dir_all(&self.dir).context(format!("failed to remove session {:?}", self.dir))
+    }
+}
+
+/// Sessions left behind by editor instances that did not shut down cleanly
+pub fn list_sessions(settings: &RecoverySettings) -> Result<Vec<Session>> {
+    let entries = match std::fs::read_dir(&settings.dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).context(format!("failed to read {:?}", settings.dir))
+    };
+
+    let mut sessions = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if !path.is_dir() {
+            continue;
+        }
+        match Session::load(&path) {
+            Ok(session) => sessions.push(session),
+            Err(e) => warn!("ignoring recovery session {:?}: {:?}", path, e)
+        }
+    }
+    sessions.sort_by_key(|s| std::cmp::Reverse(s.started));
+
+    Ok(sessions)
+}
+
+#[derive(Resource, Debug)]
+pub struct CurrentSession(pub Session);
+
+/// Sessions found on startup that can be recovered
+#[derive(Resource, Debug, Default)]
+pub struct RecoverableSessions(pub Vec<Session>);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum JournalEdit {
+    Insert {
+        tileset: String,
+        tile: tileset::TileId,
+        rotation: tileset::TileRotation
+    },
+    Remove
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub time: DateTime<Local>,
+    pub layer: String,
+    pub location: map::Location,
+    pub edit: JournalEdit
+}
+
+/// Edits made since the last autosave
+#[derive(Resource, Debug, Default)]
+pub struct Journal {
+    pending: Vec<JournalEntry>
+}
+
+impl Journal {
+    pub fn record(&mut self, layer: &str, location: map::Location, edit: JournalEdit) {
+        self.pending.push(JournalEntry {
+            time: Local::now(),
+            layer: layer.into(),
+            location,
+            edit
+        });
+    }
+}
+
+fn start_session(
+    mut commands: Commands,
+    settings: Res<RecoverySettings>
+) {
+    if !settings.enabled {
+        return;
+    }
+
+    match list_sessions(&settings) {
+        Ok(sessions) => commands.insert_resource(RecoverableSessions(sessions)),
+        Err(e) => warn!("failed to list recovery sessions: {:?}", e)
+    }
+
+    let session = Session::new(&settings);
+    if let Err(e) = session.save() {
+        warn!("failed to start recovery session: {:?}", e);
+        return;
+    }
+    debug!("recovery session {:?}", session.dir);
+    commands.insert_resource(CurrentSession(session));
+}
+
+fn flush_journal(
+    mut journal: ResMut<Journal>,
+    session: Option<Res<CurrentSession>>
+) {
+    if journal.pending.is_empty() {
+        return;
+    }
+    let Some(session) = session else {
+        journal.pending.clear();
+        return;
+    };
+
+    let result = (|| {
+        let mut f = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(session.0.journal_file())?;
+        for entry in journal.pending.drain(..) {
+            writeln!(f, "{}", ron::to_string(&entry)?)?;
+        }
+
+        Ok::<(), anyhow::Error>(())
+    })();
+
+    if let Err(e) = result {
+        warn!("failed to write recovery journal: {:?}", e);
+    }
+}
+
+/// Call when an autosave is queued: edits journaled so far are contained in
+/// it and set aside until `autosaved`, later edits go to a new journal
+pub fn autosave_started(session: &Session) -> Result<()> {
+    let journal = match std::fs::read(session.journal_file()) {
+        Ok(journal) => journal,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e).context("failed to read journal")
+    };
+
+    // the edits of an earlier autosave that failed are kept in front
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(session.saving_journal_file())
+        .and_then(|mut f| f.write_all(&journal))
+        .context("failed to set journal aside")?;
+    std::fs::remove_file(session.journal_file()).context("failed to truncate journal")
+}
+
+/// Records a completed autosave; edits set aside by `autosave_started` are
+/// contained in the autosaved map.
+pub fn autosaved(session: &mut Session) -> Result<()> {
+    session.autosaved = Some(Local::now());
+    session.save()?;
+
+    match std::fs::remove_file(session.saving_journal_file()) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e).context("failed to truncate journal"),
+        _ => Ok(())
+    }
+}
+
+fn end_session(
+    mut exit: EventReader<AppExit>,
+    session: Option<Res<CurrentSession>>
+) {
+    if exit.read().last().is_none() {
+        return;
+    }
+    let Some(session) = session else { return };
+
+    if let Err(e) = session.0.discard() {
+        warn!("{:?}", e);
+    }
+}
@@ -1,34 +1,148 @@
 use bevy::{
+    asset::RenderAssetUsages,
     core_pipeline::tonemapping::Tonemapping,
+    image::{ CompressedImageFormats,ImageSampler,ImageType },
     prelude::*,
-    render::{camera::RenderTarget, view::RenderLayers},
-    scene::SceneInstance
+    render::{
+        camera::{ RenderTarget,ScalingMode },
+        gpu_readback::{ Readback,ReadbackComplete },
+        primitives::Aabb,
+        render_resource::TextureFormat,
+        view::RenderLayers
+    },
+    scene::SceneInstance,
+    tasks::{ IoTaskPool,Task }
 };
-use std::collections::VecDeque;
+use futures_lite::future;
+use std::{
+    collections::{ HashSet,VecDeque },
+    path::{ Path,PathBuf }
+};
+
+use crate::persistence;
 
 pub struct Plugin;
 
 impl bevy::app::Plugin for Plugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, (setup, render_thumbnails));
+        app.init_resource::<RenderQueue>()
+            .init_resource::<ThumbnailSettings>()
+            .add_systems(Startup, setup)
+            .add_systems(Update, (update_light_rig, load_cached_thumbnails, render_thumbnails).chain());
     }
 }
 
-fn setup(mut commands: Commands) {
-    commands.insert_resource(RenderQueue::default());
+/// The layer thumbnail scenes, camera and lights are rendered on
+const THUMBNAIL_LAYER: usize = 1;
 
-    commands.spawn((
-        Name::new("thumbnail_render::camera"),
-        ThumbnailCamera,
-        bevy::render::view::RenderLayers::layer(1),
-        Camera3d::default(),
-    ));
+/// Frames to wait for mesh bounds before framing a scene without them
+const BOUNDS_TIMEOUT: u32 = 10;
+
+/// Frames to wait for a scene to load before giving up on its thumbnail
+const LOAD_TIMEOUT: u32 = 600;
+
+/// BGRA color of thumbnails whose scene failed to load
+const FAILED_COLOR: [u8; 4] = [40, 40, 160, 255];
+
+#[derive(Clone, Debug)]
+pub struct ThumbnailLight {
+    /// Direction the light points in
+    pub direction: Vec3,
+    pub color: Color,
+    pub illuminance: f32
+}
+
+#[derive(Resource, Clone, Debug)]
+pub struct ThumbnailSettings {
+    /// Width and height of thumbnails in pixels; applies to thumbnails created
+    /// after a change
+    pub size: u32,
+    pub yaw_degrees: f32,
+    pub pitch_degrees: f32,
+    /// Space around the framed model, relative to its size
+    pub margin: f32,
+    pub lights: Vec<ThumbnailLight>,
+    pub ambient_brightness: f32,
+    /// Where preview images named after tiles are looked up, relative to
+    /// the asset root
+    pub previews_dir: PathBuf,
+    /// Rendered thumbnails are stored here; caching is off if `None`
+    pub cache_dir: Option<PathBuf>
+}
+
+impl Default for ThumbnailSettings {
+    fn default() -> Self {
+        Self {
+            size: 96,
+            yaw_degrees: 45.0,
+            pitch_degrees: -30.0,
+            margin: 0.05,
+            lights: vec![
+                ThumbnailLight {
+                    direction: Vec3::new(-1.0, -2.0, -1.5),
+                    color: Color::WHITE,
+                    illuminance: 4000.0
+                },
+                ThumbnailLight {
+                    direction: Vec3::new(1.5, -1.0, 1.0),
+                    color: Color::srgb(0.8, 0.85, 1.0),
+                    illuminance: 1200.0
+                }
+            ],
+            ambient_brightness: 400.0,
+            previews_dir: PathBuf::from("Previews"),
+            cache_dir: Some(PathBuf::from(".cache/thumbnails"))
+        }
+    }
+}
+
+impl ThumbnailSettings {
+    fn view_direction(&self) -> Vec3 {
+        Quat::from_euler(
+            EulerRot::YXZ,
+            self.yaw_degrees.to_radians(),
+            self.pitch_degrees.to_radians(),
+            0.0
+        ) * Vec3::NEG_Z
+    }
+
+    /// Everything but the paths affects how a thumbnail looks
+    fn cache_key(&self) -> String {
+        format!(
+            "{} {} {} {} {:?} {}",
+            self.size, self.yaw_degrees, self.pitch_degrees, self.margin, self.lights, self.ambient_brightness
+        )
+    }
+}
+
+struct Thumbnail {
+    image: Handle<Image>,
+    scene: Handle<Scene>,
+    transform: Transform,
+    cache: Option<PathBuf>
+}
+
+enum Stage {
+    /// Waiting for the scene, for at most `LOAD_TIMEOUT` frames
+    Spawned(u32),
+    /// Waiting for `Aabb`s, for at most `BOUNDS_TIMEOUT` frames
+    Bounds(u32),
+    Rendered
+}
+
+struct Current {
+    entity: Entity,
+    thumbnail: Thumbnail,
+    stage: Stage
 }
 
-#[derive(Resource, Default, Debug)]
+#[derive(Resource, Default)]
 pub struct RenderQueue {
-    queue: VecDeque<(Handle<Image>, Handle<Scene>)>,
-    scene: Option<Entity>
+    queue: VecDeque<Thumbnail>,
+    cached: Vec<(Thumbnail, Task<(Option<PathBuf>, Option<Vec<u8>>)>)>,
+    current: Option<Current>,
+    /// Scenes that did not load; their thumbnails are not rendered again
+    failed: HashSet<AssetId<Scene>>
 }
 
 impl RenderQueue {
@@ -37,66 +151,403 @@ impl RenderQueue {
         image: Handle<Image>,
         scene: Handle<Scene>
     ) {
-        self.queue.push_back((image, scene));
+        self.push_transformed(image, scene, Transform::IDENTITY);
+    }
+
+    /// Renders the scene with `transform` applied, e.g. to show a rotated tile
+    pub fn push_transformed(
+        &mut self,
+        image: Handle<Image>,
+        scene: Handle<Scene>,
+        transform: Transform
+    ) {
+        self.queue.push_back(Thumbnail { image, scene, transform, cache: None });
+    }
+
+    /// Uses a thumbnail cached for the contents of the model file `path` and
+    /// the files it loads, rendering and caching it if there is none. `variant` tells apart
+    /// thumbnails of different parts of the same file.
+    pub fn push_cached(
+        &mut self,
+        image: Handle<Image>,
+        scene: Handle<Scene>,
+        path: PathBuf,
+        variant: String,
+        settings: &ThumbnailSettings
+    ) {
+        let thumbnail = Thumbnail { image, scene, transform: Transform::IDENTITY, cache: None };
+        let Some(cache_dir) = settings.cache_dir.clone() else {
+            self.queue.push_back(thumbnail);
+            return;
+        };
+
+        let key = settings.cache_key();
+        let task = IoTaskPool::get().spawn(async move {
+            let Ok(mut model) = std::fs::read(&path) else { return (None, None) };
+            for file in model_dependencies(&path, &model) {
+                model.extend(file.to_string_lossy().as_bytes());
+                model.extend(std::fs::read(&file).unwrap_or_default());
+            }
+            let hash = persistence::content_hash(&[&model, variant.as_bytes(), key.as_bytes()].concat());
+            let cache = cache_dir.join(format!("{:016x}.png", hash));
+
+            let png = std::fs::read(&cache).ok();
+            (Some(cache), png)
+        });
+
+        self.cached.push((thumbnail, task));
     }
 }
 
+/// Files besides `path` that change how the model looks: glTF buffers and
+/// images, OBJ material libraries and their textures
+fn model_dependencies(path: &Path, model: &[u8]) -> Vec<PathBuf> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+
+    if path.extension().is_some_and(|e| e.eq_ignore_ascii_case("obj")) {
+        let mut files = Vec::new();
+        for line in String::from_utf8_lossy(model).lines() {
+            let Some(name) = line.trim().strip_prefix("mtllib ") else { continue };
+            let mtl = dir.join(name.trim());
+            let mtl_dir = mtl.parent().unwrap_or(dir).to_path_buf();
+            if let Ok((materials, _)) = tobj::load_mtl(&mtl) {
+                for material in materials {
+                    files.extend(
+                        [
+                            material.ambient_texture,
+                            material.diffuse_texture,
+                            material.specular_texture,
+                            material.normal_texture,
+                            material.shininess_texture,
+                            material.dissolve_texture
+                        ]
+                        .into_iter()
+                        .flatten()
+                        .map(|texture| mtl_dir.join(texture))
+                    );
+                }
+            }
+            files.push(mtl);
+        }
+        return files;
+    }
+
+    let Ok(gltf) = gltf::Gltf::from_slice(model) else { return Vec::new() };
+    let root = gltf.document.into_json();
+    root.buffers
+        .iter()
+        .filter_map(|buffer| buffer.uri.as_ref())
+        .chain(root.images.iter().filter_map(|image| image.uri.as_ref()))
+        .filter(|uri| !uri.starts_with("data:"))
+        .map(|uri| dir.join(uri))
+        .collect()
+}
+
 #[derive(Component)]
 struct ThumbnailCamera;
 
+#[derive(Component)]
+struct ThumbnailLightRig;
+
 #[derive(Clone, Copy, Component)]
 struct ThumbnailScene;
 
+fn setup(mut commands: Commands) {
+    commands.spawn((
+        Name::new("thumbnail_render::camera"),
+        ThumbnailCamera,
+        RenderLayers::layer(THUMBNAIL_LAYER),
+        Camera3d::default(),
+        Camera {
+            is_active: false,
+            clear_color: ClearColorConfig::Custom(Color::NONE),
+            ..default()
+        },
+        Projection::Orthographic(OrthographicProjection::default_3d()),
+        Tonemapping::None
+    ));
+}
+
+fn update_light_rig(
+    mut commands: Commands,
+    settings: Res<ThumbnailSettings>,
+    lights: Query<Entity, With<ThumbnailLightRig>>,
+    camera: Query<Entity, With<ThumbnailCamera>>
+) {
+    if !settings.is_changed() {
+        return;
+    }
+
+    for entity in &lights {
+        commands.entity(entity).despawn();
+    }
+    for (index, light) in settings.lights.iter().enumerate() {
+        commands.spawn((
+            Name::new(format!("thumbnail_render::light {}", index)),
+            ThumbnailLightRig,
+            DirectionalLight {
+                color: light.color,
+                illuminance: light.illuminance,
+                shadows_enabled: false,
+                ..default()
+            },
+            Transform::default().looking_to(light.direction, Vec3::Y),
+            RenderLayers::layer(THUMBNAIL_LAYER)
+        ));
+    }
+
+    if let Ok(camera) = camera.single() {
+        commands.entity(camera).insert(AmbientLight {
+            brightness: settings.ambient_brightness,
+            ..default()
+        });
+    }
+}
+
+/// Converts between the RGBA of PNG files and the BGRA of thumbnails
+fn swap_red_blue(data: &mut [u8]) {
+    for pixel in data.chunks_exact_mut(4) {
+        pixel.swap(0, 2);
+    }
+}
+
+fn load_cached_thumbnails(
+    mut render_queue: ResMut<RenderQueue>,
+    mut images: ResMut<Assets<Image>>
+) {
+    let render_queue = &mut *render_queue;
+    let mut index = 0;
+
+    while index < render_queue.cached.len() {
+        let Some((cache, png)) = future::block_on(future::poll_once(&mut render_queue.cached[index].1)) else {
+            index += 1;
+            continue;
+        };
+        let (mut thumbnail, _) = render_queue.cached.swap_remove(index);
+
+        let cached = png.and_then(|png| {
+            Image::from_buffer(
+                &png,
+                ImageType::Extension("png"),
+                CompressedImageFormats::NONE,
+                true,
+                ImageSampler::Default,
+                RenderAssetUsages::default()
+            )
+            .map_err(|e| warn!("invalid cached thumbnail {:?}: {}", cache, e))
+            .ok()
+        });
+
+        match (cached, images.get_mut(&thumbnail.image)) {
+            (Some(cached), Some(image)) if cached.size() == image.size() => {
+                let mut data = cached.data.unwrap_or_default();
+                swap_red_blue(&mut data);
+                image.data = Some(data);
+            }
+            _ => {
+                thumbnail.cache = cache;
+                render_queue.queue.push_back(thumbnail);
+            }
+        }
+    }
+}
+
+/// Writes a rendered thumbnail to the cache; `data` are BGRA rows, possibly
+/// padded to 256 bytes
+fn save_thumbnail(path: PathBuf, size: UVec2, data: Vec<u8>) {
+    let row = size.x as usize * 4;
+    let padded_row = if data.len() > row * size.y as usize { row.div_ceil(256) * 256 } else { row };
+    let mut pixels: Vec<u8> = data
+        .chunks(padded_row)
+        .take(size.y as usize)
+        .flat_map(|r| &r[..row])
+        .copied()
+        .collect();
+    swap_red_blue(&mut pixels);
+
+    IoTaskPool::get()
+        .spawn(async move {
+            let image = Image::new(
+                bevy::render::render_resource::Extent3d {
+                    width: size.x,
+                    height: size.y,
+                    depth_or_array_layers: 1
+                },
+                bevy::render::render_resource::TextureDimension::D2,
+                pixels,
+                TextureFormat::Rgba8UnormSrgb,
+                RenderAssetUsages::default()
+            );
+            let result = std::fs::create_dir_all(path.parent().unwrap_or(&path))
+                .map_err(|e| e.to_string())
+                .and_then(|_| image.try_into_dynamic().map_err(|e| e.to_string()))
+                .and_then(|image| image.to_rgba8().save(&path).map_err(|e| e.to_string()));
+            if let Err(e) = result {
+                warn!("failed to cache thumbnail {:?}: {}", path, e);
+            }
+        })
+        .detach();
+}
+
+/// Places the camera so the box spanned by `points` fills the view
+fn frame_camera(
+    points: &[Vec3],
+    settings: &ThumbnailSettings,
+    transform: &mut Transform,
+    projection: &mut OrthographicProjection
+) {
+    let direction = settings.view_direction();
+    let look = Transform::default().looking_to(direction, Vec3::Y);
+    let (min, max) = points
+        .iter()
+        .fold((Vec3::MAX, Vec3::MIN), |(min, max), p| (min.min(*p), max.max(*p)));
+    let center = (min + max) / 2.0;
+    let radius = (max - min).length().max(0.01) / 2.0;
+
+    // extent of the box as seen from the camera
+    let inverse = look.rotation.inverse();
+    let (view_min, view_max) = points
+        .iter()
+        .map(|p| inverse * (*p - center))
+        .fold((Vec3::MAX, Vec3::MIN), |(min, max), p| (min.min(p), max.max(p)));
+    let extent = (view_max - view_min).truncate();
+    let view_center = look.rotation * ((view_min + view_max) / 2.0).with_z(0.0);
+    let size = extent.max_element().max(0.01) * (1.0 + settings.margin * 2.0);
+
+    *transform = look.with_translation(center + view_center - direction * radius * 2.0);
+    projection.scaling_mode = ScalingMode::Fixed { width: size, height: size };
+    projection.near = 0.0;
+    projection.far = radius * 4.0;
+}
+
+/// Fills the thumbnail with `FAILED_COLOR`
+fn mark_failed(image: &Handle<Image>, images: &mut Assets<Image>) {
+    let Some(image) = images.get_mut(image) else { return };
+    let pixels = (image.width() * image.height()) as usize;
+    image.data = Some(FAILED_COLOR.repeat(pixels));
+}
+
 fn render_thumbnails(
-    mut commands: Commands,  
+    mut commands: Commands,
     mut render_queue: ResMut<RenderQueue>,
-    mut camera: Query<(&mut Camera, &RenderLayers), With<ThumbnailCamera>>,
+    asset_server: Res<AssetServer>,
+    mut camera: Query<(&mut Camera, &mut Transform, &mut Projection, &RenderLayers), With<ThumbnailCamera>>,
     scene_instances: Query<&SceneInstance, With<ThumbnailScene>>,
-    scene_manager: Res<SceneSpawner>
+    bounds: Query<(&Aabb, &GlobalTransform)>,
+    scene_manager: Res<SceneSpawner>,
+    settings: Res<ThumbnailSettings>,
+    mut images: ResMut<Assets<Image>>
 ) {
-    let (mut camera, render_layers) = camera 
+    let (mut camera, mut camera_transform, mut projection, render_layers) = camera
         .single_mut()
         .expect("a single Thumbnail Camera to exist");
 
-    if let Some(scene) = render_queue.scene {
-        if let Ok(instance) = scene_instances.get(scene) {
-            if !scene_manager.instance_is_ready(**instance) {
-                debug!("scene not loaded {:?}", scene);
+    let render_queue = &mut *render_queue;
+    if let Some(current) = render_queue.current.as_mut() {
+        let instance = scene_instances.get(current.entity).ok().map(|i| **i);
+        let load_failed = asset_server.load_state(&current.thumbnail.scene).is_failed()
+            || matches!(current.stage, Stage::Spawned(frames) if frames >= LOAD_TIMEOUT);
+
+        match (&mut current.stage, instance) {
+            (Stage::Spawned(_), _) if load_failed => {
+                warn!("no thumbnail for scene {:?}; it did not load", current.thumbnail.scene.path());
+                render_queue.failed.insert(current.thumbnail.scene.id());
+                mark_failed(&current.thumbnail.image, &mut images);
+            }
+            (Stage::Spawned(frames), Some(instance)) => {
+                if !scene_manager.instance_is_ready(instance) {
+                    debug!("scene not loaded {:?}", current.entity);
+                    *frames += 1;
+                    return;
+                }
+                for entity in scene_manager.iter_instance_entities(instance) {
+                    commands.entity(entity).insert(render_layers.clone());
+                }
+                current.stage = Stage::Bounds(0);
+                return;
+            }
+            (Stage::Spawned(frames), None) => {
+                *frames += 1;
                 return;
             }
+            (Stage::Bounds(frames), Some(instance)) => {
+                let points: Vec<Vec3> = scene_manager
+                    .iter_instance_entities(instance)
+                    .filter_map(|entity| bounds.get(entity).ok())
+                    .flat_map(|(aabb, transform)| {
+                        let (center, half) = (Vec3::from(aabb.center), Vec3::from(aabb.half_extents));
+                        [-1.0, 1.0].into_iter().flat_map(move |x| {
+                            [-1.0, 1.0].into_iter().flat_map(move |y| {
+                                [-1.0, 1.0].map(|z| transform.transform_point(center + half * Vec3::new(x, y, z)))
+                            })
+                        })
+                    })
+                    .collect();
+
+                if points.is_empty() && *frames < BOUNDS_TIMEOUT {
+                    *frames += 1;
+                    return;
+                }
+                let points = if points.is_empty() {
+                    vec![Vec3::splat(-0.5), Vec3::splat(0.5)]
+                } else {
+                    points
+                };
 
-            for entity in scene_manager.iter_instance_entities(**instance) {
-                commands.entity(entity).insert(render_layers.clone());
+                let Projection::Orthographic(ref mut projection) = *projection else {
+                    panic!("thumbnail camera is not orthographic")
+                };
+                frame_camera(&points, &settings, &mut camera_transform, projection);
+
+                debug!("render thumbnail {:?}", current.entity);
+                camera.target = RenderTarget::Image(current.thumbnail.image.clone().into());
+                camera.is_active = true;
+                commands
+                    .entity(current.entity)
+                    .remove::<ThumbnailScene>()
+                    .insert(Visibility::Visible);
+                current.stage = Stage::Rendered;
+                return;
             }
+            _ => ()
+        }
 
-            debug!("render thumbnail {:?}", scene);
-            camera.is_active = true;
-            commands
-                .entity(scene)
-                .remove::<ThumbnailScene>()
-                .insert(Visibility::Visible);
+        debug!("despawn thumbnail {:?}", current.entity);
+        camera.is_active = false;
+        commands.entity(current.entity).despawn();
 
-            return;
-        } else {
-            debug!("despawn thumbnail {:?}", scene);
-            camera.is_active = false;
-            commands.entity(scene).despawn();
-            render_queue.scene = None;
+        if let (Stage::Rendered, Some(cache)) = (&current.stage, current.thumbnail.cache.take()) {
+            let size = images.get(&current.thumbnail.image).map(|i| i.size()).unwrap_or_default();
+            commands
+                .spawn(Readback::texture(current.thumbnail.image.clone()))
+                .observe(move |trigger: Trigger<ReadbackComplete>, mut commands: Commands| {
+                    save_thumbnail(cache.clone(), size, trigger.event().0.clone());
+                    commands.entity(trigger.target()).despawn();
+                });
         }
+        render_queue.current = None;
     }
 
-    let Some((image, scene)) = render_queue.queue.pop_front() else { return };
-
-    camera.target = RenderTarget::Image(image.into());
+    let Some(thumbnail) = render_queue.queue.pop_front() else { return };
+    if render_queue.failed.contains(&thumbnail.scene.id()) {
+        mark_failed(&thumbnail.image, &mut images);
+        return;
+    }
 
     let entity = commands
         .spawn((
             ThumbnailScene,
-            SceneRoot(scene),
+            SceneRoot(thumbnail.scene.clone()),
+            thumbnail.transform,
+            Visibility::Hidden,
             render_layers.clone()
         ))
         .id();
 
-    render_queue.scene = Some(entity);
+    render_queue.current = Some(Current {
+        entity,
+        thumbnail,
+        stage: Stage::Spawned(0)
+    });
     debug!("spawn thumbnail {:?}", entity);
-}
\ No newline at end of file
+}
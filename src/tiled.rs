@@ -0,0 +1,924 @@
+use anyhow::{ bail,Context,Result };
+use bevy::{
+    ecs::system::Command,
+    prelude::*,
+    scene::ron,
+    tasks::{ IoTaskPool,Task }
+};
+use futures_lite::future;
+use hexx::{ HexLayout,HexOrientation };
+use quick_xml::events::{ BytesDecl,BytesEnd,BytesStart,BytesText,Event };
+use serde::{ Deserialize,Serialize };
+use std::{
+    collections::HashMap,
+    path::{ Path,PathBuf }
+};
+
+use crate::{ map,persistence,tileset };
+
+pub struct Plugin;
+
+impl bevy::app::Plugin for Plugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, tiled_writers);
+    }
+}
+
+const TILED_VERSION: &str = "1.10";
+const PIXELS_PER_UNIT: f32 = 32.0;
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Property {
+    pub name: String,
+    #[serde(rename = "type", default = "default_property_type")]
+    pub property_type: String,
+    pub value: serde_json::Value
+}
+
+fn default_property_type() -> String {
+    "string".into()
+}
+
+fn default_opacity() -> f32 {
+    1.0
+}
+
+fn default_visible() -> bool {
+    true
+}
+
+impl Property {
+    fn new(name: &str, value: impl ToString) -> Self {
+        Self {
+            name: name.into(),
+            property_type: default_property_type(),
+            value: serde_json::Value::String(value.to_string())
+        }
+    }
+
+    fn value_string(&self) -> String {
+        match &self.value {
+            serde_json::Value::String(s) => s.clone(),
+            v => v.to_string()
+        }
+    }
+}
+
+fn property<'a>(properties: &'a [Property], name: &str) -> Option<&'a Property> {
+    properties.iter().find(|p| p.name == name)
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct TiledTile {
+    pub id: u32,
+    pub image: String,
+    pub imagewidth: u32,
+    pub imageheight: u32,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub properties: Vec<Property>
+}
+
+/// Only the fields of tilesets stored in the map; an external tileset has
+/// just `firstgid` and `source`
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct TiledTileset {
+    pub firstgid: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub tilewidth: u32,
+    #[serde(default)]
+    pub tileheight: u32,
+    #[serde(default)]
+    pub tilecount: u32,
+    #[serde(default)]
+    pub columns: u32,
+    #[serde(default)]
+    pub tiles: Vec<TiledTile>
+}
+
+/// Object and group layers have none of the tile layer fields
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct TiledLayer {
+    pub id: u32,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub layer_type: String,
+    #[serde(default)]
+    pub width: u32,
+    #[serde(default)]
+    pub height: u32,
+    #[serde(default)]
+    pub x: i32,
+    #[serde(default)]
+    pub y: i32,
+    #[serde(default = "default_opacity")]
+    pub opacity: f32,
+    #[serde(default = "default_visible")]
+    pub visible: bool,
+    #[serde(default)]
+    pub data: Vec<u32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub properties: Vec<Property>
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct TiledMap {
+    #[serde(rename = "type")]
+    pub map_type: String,
+    pub version: String,
+    pub orientation: String,
+    pub renderorder: String,
+    pub width: u32,
+    pub height: u32,
+    pub tilewidth: u32,
+    pub tileheight: u32,
+    /// Only written for hexagonal and staggered maps
+    #[serde(default)]
+    pub hexsidelength: u32,
+    #[serde(default)]
+    pub staggeraxis: String,
+    #[serde(default)]
+    pub staggerindex: String,
+    pub infinite: bool,
+    pub nextlayerid: u32,
+    pub nextobjectid: u32,
+    #[serde(default)]
+    pub layers: Vec<TiledLayer>,
+    #[serde(default)]
+    pub tilesets: Vec<TiledTileset>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub properties: Vec<Property>
+}
+
+const GID_FLAGS: u32 = 0xf0000000;
+
+fn axial_to_offset(location: map::Location, orientation: HexOrientation) -> (i32, i32) {
+    let (q, r) = (location.x, location.y);
+
+    match orientation {
+        HexOrientation::Pointy => (q + (r - (r & 1)) / 2, r),
+        HexOrientation::Flat => (q, r + (q - (q & 1)) / 2)
+    }
+}
+
+fn offset_to_axial(col: i32, row: i32, orientation: HexOrientation) -> map::Location {
+    match orientation {
+        HexOrientation::Pointy => (col - (row - (row & 1)) / 2, row).into(),
+        HexOrientation::Flat => (col, row - (col - (col & 1)) / 2).into()
+    }
+}
+
+fn png_size(path: &Path) -> Option<(u32, u32)> {
+    let bytes = std::fs::read(path).ok()?;
+    if bytes.len() < 24 || &bytes[1..4] != b"PNG" {
+        return None;
+    }
+    let width = u32::from_be_bytes(bytes[16..20].try_into().ok()?);
+    let height = u32::from_be_bytes(bytes[20..24].try_into().ok()?);
+
+    Some((width, height))
+}
+
+/// Whether `path` names a Tiled map, which is imported and exported rather than saved
+pub fn is_tiled_path(path: &Path) -> bool {
+    matches!(path.extension().and_then(|e| e.to_str()), Some("tmx") | Some("tmj"))
+}
+
+/// Tile images are the tile previews, see `tileset::tile_preview`. `dir` is
+/// where the Tiled map is written; Tiled resolves image paths relative to it.
+pub fn to_tiled(
+    map: &persistence::MapFormat,
+    asset_root: &tileset::AssetRoot,
+    previews_dir: &Path,
+    dir: &Path
+) -> Result<TiledMap> {
+    let orientation = map.layout.orientation;
+    let side = (map.layout.scale.x.abs() * PIXELS_PER_UNIT).round().max(1.0) as u32;
+    let narrow = (side as f32 * 3f32.sqrt()).round() as u32;
+    let (tilewidth, tileheight, staggeraxis) = match orientation {
+        HexOrientation::Pointy => (narrow, side * 2, "y"),
+        HexOrientation::Flat => (side * 2, narrow, "x")
+    };
+
+    let mut tiled = TiledMap {
+        map_type: "map".into(),
+        version: TILED_VERSION.into(),
+        orientation: "hexagonal".into(),
+        renderorder: "right-down".into(),
+        tilewidth,
+        tileheight,
+        hexsidelength: side,
+        staggeraxis: staggeraxis.into(),
+        staggerindex: "odd".into(),
+        nextobjectid: 1,
+        ..default()
+    };
+
+    let mut firstgids = HashMap::new();
+    let mut firstgid = 1;
+    for (save_id, tileset) in &map.tilesets {
+        let mut tiled_tileset = TiledTileset {
+            firstgid,
+            name: tileset.name.clone(),
+            tilewidth,
+            tileheight,
+            tilecount: tileset.tiles.len() as u32,
+            columns: 0,
+            ..default()
+        };
+
+        for tile_id in &tileset.tile_order {
+            let Some(tile) = tileset.tiles.get(tile_id) else { continue };
+            let preview = tileset::tile_preview(tile, previews_dir, asset_root)
+                .unwrap_or_else(|| tileset::default_preview(tile, previews_dir));
+            let image = asset_root.resolve(&preview);
+            let (imagewidth, imageheight) = png_size(&image).unwrap_or((tilewidth, tileheight));
+            let image = persistence::relative_path(&image, dir);
+
+            tiled_tileset.tiles.push(TiledTile {
+                id: *tile_id as u32,
+                image: image.to_string_lossy().replace('\\', "/"),
+                imagewidth,
+                imageheight,
+                properties: vec![
+                    Property::new("name", &tile.name),
+                    Property::new("path", tile.path.to_string_lossy()),
+                    Property::new("transform", ron::to_string(&tile.transform)?)
+                ]
+            });
+        }
+
+        let max_id = tileset.tiles.keys().max().copied().unwrap_or(0) as u32;
+        firstgids.insert(*save_id, firstgid);
+        firstgid += max_id + 1;
+        tiled.tilesets.push(tiled_tileset);
+    }
+
+    let offsets: Vec<(i32, i32)> = map
+        .layers
+        .iter()
+        .flat_map(|layer| layer.tiles.iter())
+        .map(|tile| axial_to_offset(tile.location, orientation))
+        .collect();
+    let (mut min_col, mut min_row) = offsets
+        .iter()
+        .fold((i32::MAX, i32::MAX), |(c, r), (col, row)| (c.min(*col), r.min(*row)));
+    let (max_col, max_row) = offsets
+        .iter()
+        .fold((i32::MIN, i32::MIN), |(c, r), (col, row)| (c.max(*col), r.max(*row)));
+    if offsets.is_empty() {
+        (min_col, min_row) = (0, 0);
+    }
+
+    // shifting along the stagger axis by an odd amount would flip the stagger index
+    match orientation {
+        HexOrientation::Pointy => min_row = min_row.div_euclid(2) * 2,
+        HexOrientation::Flat => min_col = min_col.div_euclid(2) * 2
+    }
+    tiled.width = if offsets.is_empty() { 1 } else { (max_col - min_col + 1) as u32 };
+    tiled.height = if offsets.is_empty() { 1 } else { (max_row - min_row + 1) as u32 };
+    tiled.properties = vec![
+        Property::new("offset_col", min_col),
+        Property::new("offset_row", min_row),
+        Property::new("scale", ron::to_string(&map.layout.scale)?)
+    ];
+
+    for (index, layer) in map.layers.iter().enumerate() {
+        let cells = (tiled.width * tiled.height) as usize;
+        let mut data = vec![0; cells];
+        let mut rotations = vec![0; cells];
+
+        for tile in &layer.tiles {
+            let (col, row) = axial_to_offset(tile.location, orientation);
+            let cell = ((row - min_row) as u32 * tiled.width + (col - min_col) as u32) as usize;
+            let firstgid = firstgids
+                .get(&tile.tileset)
+                .context(format!("tileset SaveId not found: {:?}", tile.tileset))?;
+
+            data[cell] = firstgid + tile.tile_id as u32;
+            rotations[cell] = tile.rotation.index();
+        }
+
+        let rotations: Vec<String> = rotations.iter().map(|r| r.to_string()).collect();
+        tiled.layers.push(TiledLayer {
+            id: index as u32 + 1,
+            name: layer.name.clone(),
+            layer_type: "tilelayer".into(),
+            width: tiled.width,
+            height: tiled.height,
+            opacity: 1.0,
+            visible: true,
+            data,
+            properties: vec![Property::new("rotations", rotations.join(","))],
+            ..default()
+        });
+    }
+    tiled.nextlayerid = tiled.layers.len() as u32 + 1;
+
+    Ok(tiled)
+}
+
+pub fn from_tiled(tiled: &TiledMap) -> Result<persistence::MapFormat> {
+    if tiled.orientation != "hexagonal" {
+        bail!("unsupported Tiled map orientation: {}", tiled.orientation);
+    }
+    if tiled.infinite {
+        bail!("infinite Tiled maps are not supported");
+    }
+
+    let orientation = match tiled.staggeraxis.as_str() {
+        "y" => HexOrientation::Pointy,
+        "x" => HexOrientation::Flat,
+        axis => bail!("unsupported stagger axis: {}", axis)
+    };
+    let odd = match tiled.staggerindex.as_str() {
+        "odd" => true,
+        "even" => false,
+        index => bail!("unsupported stagger index: {}", index)
+    };
+
+    let scale = match property(&tiled.properties, "scale") {
+        Some(p) => ron::from_str(&p.value_string()).context("invalid scale property")?,
+        None => Vec2::splat(tiled.hexsidelength as f32 / PIXELS_PER_UNIT)
+    };
+    let mut map = persistence::MapFormat::new(HexLayout {
+        orientation,
+        scale,
+        ..default()
+    });
+
+    let offset = |name: &str| -> Result<i32> {
+        match property(&tiled.properties, name) {
+            Some(p) => p.value_string().parse().context(format!("invalid {} property", name)),
+            None => Ok(0)
+        }
+    };
+    let (offset_col, offset_row) = (offset("offset_col")?, offset("offset_row")?);
+
+    let mut tilesets = Vec::new();
+    for (index, tiled_tileset) in tiled.tilesets.iter().enumerate() {
+        if let Some(source) = &tiled_tileset.source {
+            bail!("external Tiled tilesets are not supported: {}", source);
+        }
+        let mut tileset = tileset::TileSet::new(&tiled_tileset.name);
+
+        for tiled_tile in &tiled_tileset.tiles {
+            let image = PathBuf::from(&tiled_tile.image);
+            let name = match property(&tiled_tile.properties, "name") {
+                Some(p) => p.value_string(),
+                None => image.file_stem().unwrap_or_default().to_string_lossy().into()
+            };
+            let path = match property(&tiled_tile.properties, "path") {
+                Some(p) => PathBuf::from(p.value_string()),
+                None => image.with_extension("glb")
+            };
+            let transform = match property(&tiled_tile.properties, "transform") {
+                Some(p) => ron::from_str(&p.value_string()).context("invalid transform property")?,
+                None => Transform::IDENTITY
+            };
+
+            tileset.insert_tile(tileset::Tile {
+                id: tiled_tile.id as tileset::TileId,
+                name,
+                path,
+                transform,
+                ..default()
+            });
+        }
+
+        let save_id = persistence::SaveId::from(index);
+        tilesets.push((tiled_tileset.firstgid, save_id));
+        map.tilesets.insert(save_id, tileset);
+    }
+    tilesets.sort_by_key(|(firstgid, _)| std::cmp::Reverse(*firstgid));
+
+    for tiled_layer in &tiled.layers {
+        if tiled_layer.layer_type != "tilelayer" {
+            warn!("skipping Tiled layer {} of type {}", tiled_layer.name, tiled_layer.layer_type);
+            continue;
+        }
+        if tiled_layer.width == 0 && !tiled_layer.data.is_empty() {
+            bail!("Tiled layer {} has data but no width", tiled_layer.name);
+        }
+
+        let rotations: Vec<usize> = match property(&tiled_layer.properties, "rotations") {
+            Some(p) => p
+                .value_string()
+                .split(',')
+                .map(|r| r.trim().parse().unwrap_or(0))
+                .collect(),
+            None => Vec::new()
+        };
+
+        let mut layer = persistence::Layer {
+            name: tiled_layer.name.clone(),
+            tiles: Vec::new()
+        };
+
+        for (cell, gid) in tiled_layer.data.iter().enumerate() {
+            let gid = gid & !GID_FLAGS;
+            if gid == 0 {
+                continue;
+            }
+            let Some((firstgid, save_id)) = tilesets.iter().find(|(firstgid, _)| *firstgid <= gid) else {
+                bail!("no tileset for gid {} in layer {}", gid, tiled_layer.name);
+            };
+
+            let mut col = (cell as u32 % tiled_layer.width) as i32 + tiled_layer.x + offset_col;
+            let mut row = (cell as u32 / tiled_layer.width) as i32 + tiled_layer.y + offset_row;
+            if !odd {
+                match orientation {
+                    HexOrientation::Pointy => col -= row & 1,
+                    HexOrientation::Flat => row -= col & 1
+                }
+            }
+
+            layer.tiles.push(persistence::Tile {
+                location: offset_to_axial(col, row, orientation),
+                tileset: *save_id,
+                tile_id: (gid - firstgid) as tileset::TileId,
+                rotation: rotations
+                    .get(cell)
+                    .and_then(|r| tileset::TileRotation::from_index(*r))
+                    .unwrap_or_default()
+            });
+        }
+
+        map.layers.push(layer);
+    }
+
+    Ok(map)
+}
+
+fn write_properties<W: std::io::Write>(
+    writer: &mut quick_xml::Writer<W>,
+    properties: &[Property]
+) -> Result<()> {
+    if properties.is_empty() {
+        return Ok(());
+    }
+
+    writer.write_event(Event::Start(BytesStart::new("properties")))?;
+    for p in properties {
+        let value = p.value_string();
+        let mut attributes = vec![("name", p.name.as_str()), ("value", value.as_str())];
+        if p.property_type != "string" {
+            attributes.push(("type", p.property_type.as_str()));
+        }
+        writer.write_event(Event::Empty(BytesStart::new("property").with_attributes(attributes)))?;
+    }
+    writer.write_event(Event::End(BytesEnd::new("properties")))?;
+
+    Ok(())
+}
+
+pub fn to_tmx(tiled: &TiledMap) -> Result<String> {
+    let mut writer = quick_xml::Writer::new_with_indent(Vec::new(), b' ', 1);
+    writer.write_event(Event::Decl(BytesDecl::new("1.0", Some("UTF-8"), None)))?;
+
+    let map_attributes = [
+        ("version", tiled.version.clone()),
+        ("orientation", tiled.orientation.clone()),
+        ("renderorder", tiled.renderorder.clone()),
+        ("width", tiled.width.to_string()),
+        ("height", tiled.height.to_string()),
+        ("tilewidth", tiled.tilewidth.to_string()),
+        ("tileheight", tiled.tileheight.to_string()),
+        ("infinite", (tiled.infinite as u8).to_string()),
+        ("hexsidelength", tiled.hexsidelength.to_string()),
+        ("staggeraxis", tiled.staggeraxis.clone()),
+        ("staggerindex", tiled.staggerindex.clone()),
+        ("nextlayerid", tiled.nextlayerid.to_string()),
+        ("nextobjectid", tiled.nextobjectid.to_string())
+    ];
+    writer.write_event(Event::Start(BytesStart::new("map").with_attributes(
+        map_attributes.iter().map(|(k, v)| (*k, v.as_str()))
+    )))?;
+    write_properties(&mut writer, &tiled.properties)?;
+
+    for tileset in &tiled.tilesets {
+        let attributes = [
+            ("firstgid", tileset.firstgid.to_string()),
+            ("name", tileset.name.clone()),
+            ("tilewidth", tileset.tilewidth.to_string()),
+            ("tileheight", tileset.tileheight.to_string()),
+            ("tilecount", tileset.tilecount.to_string()),
+            ("columns", tileset.columns.to_string())
+        ];
+        writer.write_event(Event::Start(BytesStart::new("tileset").with_attributes(
+            attributes.iter().map(|(k, v)| (*k, v.as_str()))
+        )))?;
+
+        for tile in &tileset.tiles {
+            let id = tile.id.to_string();
+            writer.write_event(Event::Start(BytesStart::new("tile").with_attributes([("id", id.as_str())])))?;
+            write_properties(&mut writer, &tile.properties)?;
+            let (width, height) = (tile.imagewidth.to_string(), tile.imageheight.to_string());
+            writer.write_event(Event::Empty(BytesStart::new("image").with_attributes([
+                ("width", width.as_str()),
+                ("height", height.as_str()),
+                ("source", tile.image.as_str())
+            ])))?;
+            writer.write_event(Event::End(BytesEnd::new("tile")))?;
+        }
+
+        writer.write_event(Event::End(BytesEnd::new("tileset")))?;
+    }
+
+    for layer in &tiled.layers {
+        let attributes = [
+            ("id", layer.id.to_string()),
+            ("name", layer.name.clone()),
+            ("width", layer.width.to_string()),
+            ("height", layer.height.to_string())
+        ];
+        writer.write_event(Event::Start(BytesStart::new("layer").with_attributes(
+            attributes.iter().map(|(k, v)| (*k, v.as_str()))
+        )))?;
+        write_properties(&mut writer, &layer.properties)?;
+
+        let rows: Vec<String> = layer
+            .data
+            .chunks(layer.width.max(1) as usize)
+            .map(|row| row.iter().map(|gid| gid.to_string()).collect::<Vec<_>>().join(","))
+            .collect();
+        writer.write_event(Event::Start(BytesStart::new("data").with_attributes([("encoding", "csv")])))?;
+        writer.write_event(Event::Text(BytesText::new(&format!("\n{}\n", rows.join(",\n")))))?;
+        writer.write_event(Event::End(BytesEnd::new("data")))?;
+
+        writer.write_event(Event::End(BytesEnd::new("layer")))?;
+    }
+
+    writer.write_event(Event::End(BytesEnd::new("map")))?;
+
+    String::from_utf8(writer.into_inner()).context("invalid utf-8 in tmx")
+}
+
+pub fn from_tmx(xml: &str) -> Result<TiledMap> {
+    let mut reader = quick_xml::Reader::from_str(xml);
+    reader.config_mut().trim_text(true);
+
+    let mut tiled = TiledMap {
+        map_type: "map".into(),
+        ..default()
+    };
+    let mut stack: Vec<String> = Vec::new();
+    let mut in_data = false;
+
+    loop {
+        let event = reader.read_event().context(format!("xml error at {}", reader.buffer_position()))?;
+        let (element, empty) = match &event {
+            Event::Start(e) => (e, false),
+            Event::Empty(e) => (e, true),
+            Event::Text(text) if in_data => {
+                let layer = tiled.layers.last_mut().context("data outside of layer")?;
+                for gid in text.decode()?.split(',') {
+                    let gid = gid.trim();
+                    if !gid.is_empty() {
+                        layer.data.push(gid.parse().context(format!("invalid gid {}", gid))?);
+                    }
+                }
+                continue;
+            }
+            Event::End(_) => {
+                if stack.pop().as_deref() == Some("data") {
+                    in_data = false;
+                }
+                continue;
+            }
+            Event::Eof => break,
+            _ => continue
+        };
+
+        let name = String::from_utf8_lossy(element.name().as_ref()).to_string();
+        let mut attributes = HashMap::new();
+        for attribute in element.attributes() {
+            let attribute = attribute?;
+            attributes.insert(
+                String::from_utf8_lossy(attribute.key.as_ref()).to_string(),
+                attribute.unescape_value()?.to_string()
+            );
+        }
+        let attr = |key: &str| attributes.get(key).cloned().unwrap_or_default();
+        let number = |key: &str| attributes.get(key).and_then(|v| v.parse::<u32>().ok()).unwrap_or(0);
+
+        match (stack.last().map(String::as_str), name.as_str()) {
+            (None, "map") => {
+                tiled.version = attr("version");
+                tiled.orientation = attr("orientation");
+                tiled.renderorder = attr("renderorder");
+                tiled.width = number("width");
+                tiled.height = number("height");
+                tiled.tilewidth = number("tilewidth");
+                tiled.tileheight = number("tileheight");
+                tiled.hexsidelength = number("hexsidelength");
+                tiled.staggeraxis = attr("staggeraxis");
+                tiled.staggerindex = attr("staggerindex");
+                tiled.infinite = number("infinite") != 0;
+                tiled.nextlayerid = number("nextlayerid");
+                tiled.nextobjectid = number("nextobjectid");
+            }
+            (Some("map"), "tileset") => {
+                if attributes.contains_key("source") {
+                    bail!("external Tiled tilesets are not supported: {}", attr("source"));
+                }
+                tiled.tilesets.push(TiledTileset {
+                    firstgid: number("firstgid"),
+                    name: attr("name"),
+                    tilewidth: number("tilewidth"),
+                    tileheight: number("tileheight"),
+                    tilecount: number("tilecount"),
+                    columns: number("columns"),
+                    ..default()
+                });
+            }
+            (Some("tileset"), "tile") => {
+                let tileset = tiled.tilesets.last_mut().context("tile outside of tileset")?;
+                tileset.tiles.push(TiledTile {
+                    id: number("id"),
+                    ..default()
+                });
+            }
+            (Some("tile"), "image") => {
+                let tile = tiled
+                    .tilesets
+                    .last_mut()
+                    .and_then(|t| t.tiles.last_mut())
+                    .context("image outside of tile")?;
+                tile.image = attr("source");
+                tile.imagewidth = number("width");
+                tile.imageheight = number("height");
+            }
+            (Some("map"), "layer") => {
+                tiled.layers.push(TiledLayer {
+                    id: number("id"),
+                    name: attr("name"),
+                    layer_type: "tilelayer".into(),
+                    width: number("width"),
+                    height: number("height"),
+                    opacity: 1.0,
+                    visible: attr("visible") != "0",
+                    ..default()
+                });
+            }
+            (Some("layer"), "data") => {
+                if attr("encoding") != "csv" {
+                    bail!("unsupported layer data encoding: {:?}", attr("encoding"));
+                }
+                in_data = !empty;
+            }
+            (Some("properties"), "property") => {
+                let property = Property {
+                    name: attr("name"),
+                    property_type: attributes.get("type").cloned().unwrap_or_else(default_property_type),
+                    value: serde_json::Value::String(attr("value"))
+                };
+                let parent = stack.get(stack.len().wrapping_sub(2)).map(String::as_str);
+                let properties = match parent {
+                    Some("map") => &mut tiled.properties,
+                    Some("layer") => &mut tiled.layers.last_mut().context("properties outside of layer")?.properties,
+                    Some("tile") => &mut tiled
+                        .tilesets
+                        .last_mut()
+                        .and_then(|t| t.tiles.last_mut())
+                        .context("properties outside of tile")?
+                        .properties,
+                    _ => continue
+                };
+                properties.push(property);
+            }
+            _ => ()
+        }
+
+        if !empty {
+            stack.push(name);
+        }
+    }
+
+    Ok(tiled)
+}
+
+pub fn import(path: &Path) -> Result<persistence::MapFormat> {
+    let buf = std::fs::read_to_string(path).context("failed to read file")?;
+    let tiled = match path.extension().and_then(|e| e.to_str()) {
+        Some("tmx") => from_tmx(&buf).context("failed to parse tmx")?,
+        _ => serde_json::from_str(&buf).context("failed to parse tmj")?
+    };
+
+    from_tiled(&tiled)
+}
+
+pub fn export(
+    map: &persistence::MapFormat,
+    asset_root: &tileset::AssetRoot,
+    previews_dir: &Path,
+    path: &Path
+) -> Result<()> {
+    let tiled = to_tiled(map, asset_root, previews_dir, path.parent().unwrap_or(Path::new("")))?;
+    let buf = match path.extension().and_then(|e| e.to_str()) {
+        Some("tmx") => to_tmx(&tiled)?,
+        _ => serde_json::to_string_pretty(&tiled)?
+    };
+    std::fs::write(path, buf).context(format!("writing Tiled map to {:?}", path))?;
+
+    Ok(())
+}
+
+pub struct ExportTiledCommand {
+    path: PathBuf,
+    map: Entity
+}
+
+impl ExportTiledCommand {
+    pub fn new(path: PathBuf, map: Entity) -> Self {
+        Self { path,map }
+    }
+}
+
+impl Command for ExportTiledCommand {
+    fn apply(self, world: &mut World) {
+        let asset_root = world.get_resource::<tileset::AssetRoot>().cloned().unwrap_or_default();
+        let previews_dir = world
+            .get_resource::<crate::render::ThumbnailSettings>()
+            .map(|settings| settings.previews_dir.clone())
+            .unwrap_or_else(|| crate::render::ThumbnailSettings::default().previews_dir);
+        let map = match persistence::MapFormat::try_new(world, self.map) {
+            Ok(map) => map,
+            Err(err) => {
+                warn!("failed to export map: {:#?}", err);
+                return;
+            }
+        };
+
+        let task_pool = IoTaskPool::get();
+        let task = task_pool.spawn(async move { export(&map, &asset_root, &previews_dir, &self.path) });
+
+        world.spawn(TiledWriterTask(task));
+    }
+}
+
+#[derive(Component)]
+struct TiledWriterTask(Task<Result<()>>);
+
+fn tiled_writers(
+    mut commands: Commands,
+    mut tiled_writers: Query<(Entity, &mut TiledWriterTask)>
+) {
+    for (entity, mut writer) in &mut tiled_writers {
+        let Some(result) = future::block_on(future::poll_once(&mut writer.0)) else { continue };
+        if let Err(e) = result {
+            warn!("failed to export Tiled map: {:#?}", e);
+        }
+        commands.entity(entity).despawn();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Written by Tiled, with an object layer and an empty group layer
+    const TMJ: &str = r#"{
+        "type": "map",
+        "version": "1.10",
+        "tiledversion": "1.10.2",
+        "orientation": "hexagonal",
+        "renderorder": "right-down",
+        "width": 2,
+        "height": 2,
+        "tilewidth": 55,
+        "tileheight": 64,
+        "hexsidelength": 32,
+        "staggeraxis": "y",
+        "staggerindex": "odd",
+        "infinite": false,
+        "compressionlevel": -1,
+        "nextlayerid": 4,
+        "nextobjectid": 2,
+        "layers": [
+            {
+                "id": 1,
+                "name": "ground",
+                "type": "tilelayer",
+                "width": 2,
+                "height": 2,
+                "x": 0,
+                "y": 0,
+                "opacity": 1,
+                "visible": true,
+                "data": [1, 0, 0, 2]
+            },
+            {
+                "id": 2,
+                "name": "spawns",
+                "type": "objectgroup",
+                "draworder": "topdown",
+                "objects": [
+                    { "id": 1, "name": "start", "x": 10, "y": 20, "width": 0, "height": 0, "rotation": 0, "visible": true, "point": true }
+                ]
+            },
+            {
+                "id": 3,
+                "name": "decorations",
+                "type": "group",
+                "layers": []
+            }
+        ],
+        "tilesets": [
+            {
+                "firstgid": 1,
+                "name": "forest",
+                "tilewidth": 55,
+                "tileheight": 64,
+                "tilecount": 2,
+                "columns": 0,
+                "tiles": [
+                    { "id": 0, "image": "Previews/tree.png", "imagewidth": 55, "imageheight": 64 },
+                    { "id": 1, "image": "Previews/rock.png", "imagewidth": 55, "imageheight": 64 }
+                ]
+            }
+        ]
+    }"#;
+
+    #[test]
+    fn tmj_with_object_layers_imports_the_tile_layers() {
+        let tiled: TiledMap = serde_json::from_str(TMJ).unwrap();
+        let map = from_tiled(&tiled).unwrap();
+
+        assert_eq!(map.layers.len(), 1);
+        let ground = &map.layers[0];
+        assert_eq!(ground.name, "ground");
+        let tiles: Vec<((i32, i32), tileset::TileId)> = ground
+            .tiles
+            .iter()
+            .map(|t| ((t.location.x, t.location.y), t.tile_id))
+            .collect();
+        // the second row is shifted right, so the cell below and right of (0, 0) is (1, 1)
+        assert_eq!(tiles, vec![((0, 0), 0), ((1, 1), 1)]);
+
+        let forest = &map.tilesets[&persistence::SaveId::from(0)];
+        assert_eq!(forest.tiles[&1].name, "rock");
+        assert_eq!(forest.tiles[&1].path, PathBuf::from("Previews/rock.glb"));
+    }
+
+    #[test]
+    fn external_tilesets_are_refused() {
+        let mut tmj: serde_json::Value = serde_json::from_str(TMJ).unwrap();
+        tmj["tilesets"][0] = serde_json::json!({ "firstgid": 1, "source": "forest.tsj" });
+        let tiled: TiledMap = serde_json::from_value(tmj).unwrap();
+        let error = from_tiled(&tiled).unwrap_err();
+        assert_eq!(error.to_string(), "external Tiled tilesets are not supported: forest.tsj");
+
+        let tmx = r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.10" orientation="hexagonal" width="1" height="1" tilewidth="55" tileheight="64" hexsidelength="32" staggeraxis="y" staggerindex="odd">
+ <tileset firstgid="1" source="forest.tsx"/>
+</map>"#;
+        let error = from_tmx(tmx).unwrap_err();
+        assert_eq!(error.to_string(), "external Tiled tilesets are not supported: forest.tsx");
+    }
+
+    #[test]
+    fn exported_maps_import_again() {
+        let mut map = persistence::MapFormat::new(HexLayout::default());
+        let mut forest = tileset::TileSet::new("forest");
+        forest.add_title(PathBuf::from("forest/tree.glb"));
+        forest.add_title(PathBuf::from("forest/rock.glb"));
+        map.tilesets.insert(persistence::SaveId::from(0), forest);
+        map.layers.push(persistence::Layer {
+            name: "ground".into(),
+            tiles: [(-1, 0, 0), (0, 0, 1), (3, -2, 1)]
+                .into_iter()
+                .map(|(x, y, tile_id)| persistence::Tile {
+                    location: (x, y).into(),
+                    tileset: persistence::SaveId::from(0),
+                    tile_id,
+                    rotation: tileset::TileRotation::Clockwise60
+                })
+                .collect()
+        });
+
+        let asset_root = tileset::AssetRoot(PathBuf::from("assets"));
+        let tiled = to_tiled(&map, &asset_root, Path::new("Previews"), Path::new("")).unwrap();
+        let from_json: TiledMap = serde_json::from_str(&serde_json::to_string(&tiled).unwrap()).unwrap();
+        let from_xml = from_tmx(&to_tmx(&tiled).unwrap()).unwrap();
+
+        for tiled in [from_json, from_xml] {
+            let imported = from_tiled(&tiled).unwrap();
+            let mut tiles: Vec<(i32, i32, tileset::TileId, tileset::TileRotation)> = imported.layers[0]
+                .tiles
+                .iter()
+                .map(|t| (t.location.x, t.location.y, t.tile_id, t.rotation))
+                .collect();
+            tiles.sort_by_key(|t| (t.0, t.1));
+            assert_eq!(tiles, vec![
+                (-1, 0, 0, tileset::TileRotation::Clockwise60),
+                (0, 0, 1, tileset::TileRotation::Clockwise60),
+                (3, -2, 1, tileset::TileRotation::Clockwise60)
+            ]);
+            assert_eq!(imported.tilesets[&persistence::SaveId::from(0)].tiles[&1].path, PathBuf::from("forest/rock.glb"));
+        }
+    }
+}
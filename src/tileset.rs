@@ -1,12 +1,14 @@
 use anyhow::{ Context,Result };
 use bevy::{
+    asset::io::file::FileAssetReader,
+    gltf::{ Gltf,GltfMesh,GltfNode },
     prelude::*, render::render_resource::{
         Extent3d,
         TextureDescriptor,
         TextureDimension,
         TextureFormat,
         TextureUsages
-    }, scene::ron, tasks::{ IoTaskPool,Task }
+    }, tasks::{ IoTaskPool,Task }
 };
 use bevy_egui::{ egui,EguiUserTextures };
 use serde::{
@@ -20,24 +22,49 @@ use serde::{
     Serialize
 };
 use std::{
-    collections::HashMap,
-    path::PathBuf
+    collections::{ HashMap,HashSet },
+    ffi::OsString,
+    path::{ Path,PathBuf }
 };
 
-use crate::map;
+use crate::{ map,persistence::{ self,FileFormat,IoCompleted,IoOperation } };
 
 pub struct Plugin;
 
 impl bevy::app::Plugin for Plugin {
     fn build(&self, app: &mut App) {
+        // the folder the asset server loads from, unless set before
+        if !app.world().contains_resource::<AssetRoot>() {
+            let asset_root = match app.get_added_plugins::<AssetPlugin>().first() {
+                Some(plugin) => AssetRoot(FileAssetReader::get_base_path().join(&plugin.file_path)),
+                None => AssetRoot::default()
+            };
+            app.insert_resource(asset_root);
+        }
+
         app.register_type::<TileSet>()
             .register_type::<TileRef>()
             .register_type::<TileRotation>()
             .register_type::<Tile>()
+            .register_type::<ModelPart>()
+            .init_resource::<PendingModelParts>()
+            .init_resource::<RotatedThumbnails>()
             .register_type::<TileId>()
             .register_type::<Vec<TileId>>()
-            .add_systems(Update, (update_tile_scene, update_tile_transform))
-            .add_systems(Startup, (load_tiles, tileset_exporter, tileset_importer));
+            .add_systems(Update, (
+                normalize_tile_paths,
+                check_tileset_integrity,
+                load_tiles,
+                render_rotated_thumbnails,
+                tile_ref_changed,
+                recheck_missing_tiles,
+                update_tile_scene,
+                update_tile_transform,
+                draw_missing_tiles,
+                tileset_exporter,
+                tileset_importer,
+                build_model_parts
+            ).chain());
     }
 }
 
@@ -49,8 +76,35 @@ pub type TileId = usize;
 pub struct Tile {
     pub id: TileId,
     pub name: String,
+    /// The model file. Paths are resolved like this:
+    ///
+    /// 1. A relative path is relative to the asset root (`AssetRoot`). This is
+    ///    how paths are written.
+    /// 2. A relative path that does not exist under the asset root, in a tileset
+    ///    loaded from its own file, is tried relative to that file's directory.
+    /// 3. An absolute path is used as is. Tilesets written before paths were
+    ///    relative have these.
+    ///
+    /// Paths found by rules 2 and 3 are rewritten relative to the asset root
+    /// when the tileset is loaded, unless the file is outside of it.
     pub path: PathBuf,
     pub transform: Transform,
+    /// Derived from the file name prefix when imported from a folder
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub category: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Image shown in the tile picker instead of a rendered thumbnail when the
+    /// tileset uses previews, relative to the asset root. Without one, an image
+    /// found by `tile_preview` is shown.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preview: Option<PathBuf>,
+    /// Scene or node of `path` used as the model, instead of its first scene
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub part: Option<ModelPart>,
+    /// The file has disappeared from the tileset's source folder
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub removed: bool,
     #[reflect(ignore)]
     #[serde(skip)]
     pub scene: Option<Handle<Scene>>,
@@ -59,6 +113,129 @@ pub struct Tile {
     pub egui_texture_id: Option<egui::TextureId>
 }
 
+/// A named scene or node within a glTF file holding several models
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, Reflect)]
+pub enum ModelPart {
+    Scene(String),
+    Node(String)
+}
+
+impl ModelPart {
+    pub fn name(&self) -> &str {
+        match self {
+            ModelPart::Scene(name) | ModelPart::Node(name) => name
+        }
+    }
+}
+
+/// Named scenes and named nodes with a mesh or children in a glTF file
+pub fn model_parts(path: &Path) -> Result<Vec<ModelPart>> {
+    let gltf = gltf::Gltf::open(path).context(format!("failed to open model {:?}", path))?;
+
+    let scenes = gltf
+        .scenes()
+        .filter_map(|scene| scene.name().map(|name| ModelPart::Scene(name.into())));
+    let nodes = gltf
+        .nodes()
+        .filter(|node| node.mesh().is_some() || node.children().len() > 0)
+        .filter_map(|node| node.name().map(|name| ModelPart::Node(name.into())));
+
+    Ok(scenes.chain(nodes).collect())
+}
+
+/// Files in `dir` and its subdirectories whose name is one of `names`, by name
+pub fn locate_files(dir: &Path, names: &HashSet<OsString>) -> HashMap<OsString, Vec<PathBuf>> {
+    let mut found: HashMap<OsString, Vec<PathBuf>> = HashMap::new();
+    let mut dirs = vec![dir.to_path_buf()];
+
+    while let Some(dir) = dirs.pop() {
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                warn!("failed to read {:?}: {}", dir, e);
+                continue;
+            }
+        };
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let Ok(file_type) = entry.file_type() else { continue };
+            if file_type.is_dir() {
+                dirs.push(entry.path());
+            } else if names.contains(&entry.file_name()) {
+                found.entry(entry.file_name()).or_default().push(entry.path());
+            }
+        }
+    }
+
+    found.values_mut().for_each(|paths| paths.sort());
+    found
+}
+
+impl Tile {
+    /// Case-insensitive match of `query` against the name, category and tags;
+    /// an empty query matches every tile
+    pub fn matches_search(&self, query: &str) -> bool {
+        let query = query.trim().to_lowercase();
+        query.is_empty()
+            || self.name.to_lowercase().contains(&query)
+            || self.category.to_lowercase().contains(&query)
+            || self.tags.iter().any(|tag| tag.to_lowercase().contains(&query))
+    }
+}
+
+/// Directory tile models are loaded from; see `Tile::path`
+#[derive(Resource, Clone, Debug)]
+pub struct AssetRoot(pub PathBuf);
+
+/// The default `assets` folder of the asset server, which is next to the
+/// executable rather than in the working directory
+impl Default for AssetRoot {
+    fn default() -> Self {
+        Self(FileAssetReader::get_base_path().join("assets"))
+    }
+}
+
+impl AssetRoot {
+    /// The file a tile path refers to
+    pub fn resolve(&self, path: &Path) -> PathBuf {
+        self.0.join(path)
+    }
+
+    /// A file on disk, e.g. from a file dialog, as it is stored in a tile:
+    /// relative if it is inside the asset root, otherwise absolute
+    pub fn tile_path(&self, file: &Path) -> PathBuf {
+        let (Ok(file), Ok(root)) = (std::path::absolute(file), std::path::absolute(&self.0)) else {
+            return file.to_path_buf();
+        };
+
+        match file.strip_prefix(&root) {
+            Ok(relative) => relative.to_path_buf(),
+            Err(_) => file
+        }
+    }
+
+    /// Applies the resolution rules of `Tile::path` to a stored path, returning
+    /// the path relative to the asset root if it changes
+    fn normalize(&self, path: &Path, tileset_dir: Option<&Path>) -> Option<PathBuf> {
+        let file = if path.is_absolute() {
+            path.to_path_buf()
+        } else if self.resolve(path).exists() {
+            return None;
+        } else {
+            tileset_dir.map(|dir| dir.join(path)).filter(|file| file.exists())?
+        };
+
+        let relative = self.tile_path(&file);
+        if relative.is_absolute() && path.is_relative() {
+            warn!(
+                "{} is outside the asset root {}; storing its absolute path",
+                relative.to_string_lossy(),
+                self.0.to_string_lossy()
+            );
+        }
+        (relative != path).then_some(relative)
+    }
+}
+
 pub type TileSetId = usize;
 
 #[derive(Component, Default, Reflect, Debug, Clone)]
@@ -67,48 +244,295 @@ pub struct TileSet {
     pub name: String,
     pub tiles: HashMap<TileId, Tile>,
     pub tile_order: Vec<TileId>,
+    /// Folder the tiles were imported from, relative to the asset root
+    pub source_dir: Option<PathBuf>,
+    /// Show preview images of tiles that have one instead of rendering them
+    pub use_previews: bool,
+    /// Next id to hand out. Saved so that ids of deleted tiles, which maps may
+    /// still refer to, are not reused.
     tile_id_max: TileId
 }
 
+/// A violation of the invariants `TileSet` relies on, as found by
+/// `TileSet::check_integrity`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IntegrityProblem {
+    /// A tile has the id of another tile in the file; it was loaded under `key`
+    DuplicateId { id: TileId, key: TileId, name: String },
+    /// `tile_order` lists a tile that does not exist
+    OrderWithoutTile(TileId),
+    /// `tile_order` lists a tile more than once
+    DuplicateOrder(TileId),
+    /// A tile is missing from `tile_order`
+    TileNotOrdered(TileId)
+}
+
+impl std::fmt::Display for IntegrityProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::DuplicateId { id, key, name } => {
+                write!(f, "tile {} reuses id {}; it gets id {}", name, id, key)
+            }
+            Self::OrderWithoutTile(id) => write!(f, "tile order lists missing tile {}", id),
+            Self::DuplicateOrder(id) => write!(f, "tile order lists tile {} more than once", id),
+            Self::TileNotOrdered(id) => write!(f, "tile {} is missing from the tile order", id)
+        }
+    }
+}
+
 impl TileSet {
+    /// Switches between preview images and rendered thumbnails
+    pub fn set_use_previews(&mut self, use_previews: bool) {
+        self.use_previews = use_previews;
+        for tile in self.tiles.values_mut() {
+            tile.egui_texture_id = None;
+        }
+    }
+
     pub fn new(name: &str) -> Self {
         Self {
             name: name.into(),
             tiles: HashMap::new(),
             tile_order: Vec::new(),
+            source_dir: None,
+            use_previews: false,
             tile_id_max: 0
         }
     }
 
+    /// A tileset with a tile for every model in `dir`, which must be inside `asset_root`
+    pub fn from_dir(dir: &Path, asset_root: &Path) -> Result<Self> {
+        let name = dir.file_name().unwrap_or_default().to_string_lossy();
+        let mut tileset = Self::new(&name);
+        tileset.source_dir = Some(persistence::relative_path(dir, asset_root));
+        tileset.sync_dir(asset_root)?;
+
+        Ok(tileset)
+    }
+
+    /// Adds tiles for new files in `source_dir` and flags tiles whose file is
+    /// gone. Ids and transforms of existing tiles are kept. Returns the number
+    /// of added and removed tiles.
+    pub fn sync_dir(&mut self, asset_root: &Path) -> Result<(usize, usize)> {
+        let source_dir = self.source_dir.clone().context("tileset has no source folder")?;
+        let dir = asset_root.join(&source_dir);
+
+        let mut paths: Vec<PathBuf> = std::fs::read_dir(&dir)
+            .context(format!("failed to read {:?}", dir))?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| {
+                path.extension()
+                    .and_then(|e| e.to_str())
+                    .is_some_and(|e| MODEL_EXTENSIONS.contains(&e.to_lowercase().as_str()))
+            })
+            .map(|path| source_dir.join(path.file_name().unwrap()))
+            .collect();
+        paths.sort();
+
+        let mut removed = 0;
+        for tile in self.tiles.values_mut() {
+            let present = paths.contains(&tile.path);
+            if tile.path.starts_with(&source_dir) && tile.removed == present {
+                tile.removed = !present;
+                if !present {
+                    removed += 1;
+                }
+            }
+        }
+
+        let mut added = 0;
+        for path in paths {
+            if self.tiles.values().any(|t| t.path == path) {
+                continue;
+            }
+            let name = path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+            let id = self.next_id();
+            self.insert_tile(Tile {
+                id,
+                category: category_from_name(&name),
+                name,
+                path,
+                transform: Transform::IDENTITY,
+                ..default()
+            });
+            added += 1;
+        }
+
+        self.sort_by_category();
+
+        Ok((added, removed))
+    }
+
+    /// Orders tiles by category, then name
+    pub fn sort_by_category(&mut self) {
+        let tiles = &self.tiles;
+        // ids without a tile sort last, to be dropped by `repair`
+        let key = |id: &TileId| {
+            let tile = tiles.get(id);
+            (tile.is_none(), tile.map(|tile| (&tile.category, &tile.name)))
+        };
+        self.tile_order.sort_by(|a, b| key(a).cmp(&key(b)));
+    }
+
     pub fn add_title(&mut self, path: PathBuf) {
-        let tile = Tile {
-            id: self.tile_id_max,
+        let id = self.next_id();
+        self.insert_tile(Tile {
+            id,
             name: path.file_stem().unwrap().to_string_lossy().into(),
             path,
             transform: Transform::IDENTITY,
-            scene: None,
-            egui_texture_id: None
-        };
-        self.tile_order.push(tile.id);
+            ..default()
+        });
+    }
+
+    /// Adds a tile showing `part` of the glTF file at `path`
+    pub fn add_part(&mut self, path: PathBuf, part: ModelPart) {
+        let name = part.name().to_string();
+        let id = self.next_id();
+        self.insert_tile(Tile {
+            id,
+            category: category_from_name(&name),
+            name,
+            path,
+            transform: Transform::IDENTITY,
+            part: Some(part),
+            ..default()
+        });
+    }
+
+    /// Rewrites tile and preview paths relative to the asset root, see
+    /// `Tile::path`; returns the number of changed paths
+    pub fn normalize_paths(&mut self, asset_root: &AssetRoot, tileset_dir: Option<&Path>) -> usize {
+        let mut changed = 0;
+        for tile in self.tiles.values_mut() {
+            if let Some(path) = asset_root.normalize(&tile.path, tileset_dir) {
+                tile.path = path;
+                changed += 1;
+            }
+            let preview = tile.preview.as_ref().and_then(|p| asset_root.normalize(p, tileset_dir));
+            if preview.is_some() {
+                tile.preview = preview;
+                changed += 1;
+            }
+        }
+
+        changed
+    }
+
+    /// An id no tile has or had, as far as this tileset knows
+    pub fn next_id(&self) -> TileId {
+        self.tiles
+            .iter()
+            .map(|(key, tile)| key.max(&tile.id) + 1)
+            .max()
+            .unwrap_or(0)
+            .max(self.tile_id_max)
+    }
+
+    /// Duplicate ids and mismatches between `tiles` and `tile_order`
+    pub fn check_integrity(&self) -> Vec<IntegrityProblem> {
+        let mut problems = Vec::new();
+
+        let mut keys: Vec<&TileId> = self.tiles.keys().collect();
+        keys.sort();
+        for key in keys {
+            let tile = &self.tiles[key];
+            if tile.id != *key {
+                problems.push(IntegrityProblem::DuplicateId { id: tile.id, key: *key, name: tile.name.clone() });
+            }
+        }
+
+        let mut ordered = HashSet::new();
+        for id in &self.tile_order {
+            if !self.tiles.contains_key(id) {
+                problems.push(IntegrityProblem::OrderWithoutTile(*id));
+            } else if !ordered.insert(*id) {
+                problems.push(IntegrityProblem::DuplicateOrder(*id));
+            }
+        }
+
+        let mut unordered: Vec<TileId> = self.tiles.keys().filter(|id| !ordered.contains(id)).copied().collect();
+        unordered.sort();
+        problems.extend(unordered.into_iter().map(IntegrityProblem::TileNotOrdered));
+
+        problems
+    }
+
+    /// Fixes everything `check_integrity` finds and returns what was fixed.
+    /// Tiles with a duplicate id keep the id they were loaded under, tiles
+    /// missing from the order are appended to it.
+    pub fn repair(&mut self) -> Vec<IntegrityProblem> {
+        let problems = self.check_integrity();
+
+        for problem in &problems {
+            match problem {
+                IntegrityProblem::DuplicateId { key, .. } => {
+                    self.tiles.get_mut(key).unwrap().id = *key;
+                }
+                IntegrityProblem::TileNotOrdered(id) => self.tile_order.push(*id),
+                IntegrityProblem::OrderWithoutTile(_) | IntegrityProblem::DuplicateOrder(_) => ()
+            }
+        }
+
+        let tiles = &self.tiles;
+        let mut ordered = HashSet::new();
+        self.tile_order.retain(|id| tiles.contains_key(id) && ordered.insert(*id));
+        self.tile_id_max = self.next_id();
+
+        problems
+    }
+
+    pub fn insert_tile(&mut self, tile: Tile) {
+        self.tile_id_max = self.tile_id_max.max(tile.id + 1);
+        if !self.tile_order.contains(&tile.id) {
+            self.tile_order.push(tile.id);
+        }
         self.tiles.insert(tile.id, tile);
-        self.tile_id_max += 1;
     }
 }
 
 pub const TILESET_VERSION: usize = 1;
 
+pub const MODEL_EXTENSIONS: &[&str] = &["glb", "gltf", "obj"];
+
+/// `building` for `building-house`; empty if the name has no prefix
+pub fn category_from_name(name: &str) -> String {
+    name.split_once(['-', '_'])
+        .map(|(category, _)| category.to_lowercase())
+        .unwrap_or_default()
+}
+
 impl Serialize for TileSet {
     fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
         where
             S: serde::Serializer {
-        let mut map = serializer.serialize_map(Some(3))?;
+        // only written when tiles were deleted from the end; otherwise it follows from the ids
+        let next_id = (self.tile_id_max > self.tiles.keys().max().map_or(0, |max| max + 1))
+            .then_some(self.tile_id_max);
+        let len = 3 + self.source_dir.is_some() as usize + self.use_previews as usize + next_id.is_some() as usize;
+        let mut map = serializer.serialize_map(Some(len))?;
         map.serialize_entry("version", &TILESET_VERSION)?;
         map.serialize_entry("name", &self.name)?;
+        if let Some(source_dir) = &self.source_dir {
+            map.serialize_entry("source", source_dir)?;
+        }
+        if self.use_previews {
+            map.serialize_entry("use_previews", &true)?;
+        }
+        if let Some(next_id) = next_id {
+            map.serialize_entry("next_tile_id", &next_id)?;
+        }
 
+        // tiles missing from the order are written after it, so none are lost
+        let mut written = HashSet::new();
+        let mut unordered: Vec<&TileId> = self.tiles.keys().filter(|id| !self.tile_order.contains(id)).collect();
+        unordered.sort();
         let tiles: Vec<Tile> = self
                 .tile_order
                 .iter()
-                .map(|i| self.tiles[i].clone())
+                .chain(unordered)
+                .filter(|i| written.insert(**i))
+                .filter_map(|i| self.tiles.get(i).cloned())
                 .collect();
         map.serialize_entry("tiles", &tiles)?;
         
@@ -128,33 +552,57 @@ impl <'de> Visitor<'de> for TileSetVisitor {
     fn visit_map<A>(self, mut map: A) -> std::result::Result<Self::Value, A::Error>
         where
             A: MapAccess<'de>, {
-        let mut tileset = TileSet::default();
+        const FIELDS: &[&str] = &["version", "name", "source", "use_previews", "next_tile_id", "tiles"];
 
-        if map.next_key::<&str>()? != Some("version") {
-            return Err(de::Error::custom("expected \"version\" key"));
-        };
+        let mut version = None;
+        let mut name = None;
+        let mut source_dir = None;
+        let mut use_previews = false;
+        let mut next_id = 0;
+        let mut tiles = None;
 
-        match map.next_value::<usize>()? {
-            TILESET_VERSION => (),
-            v => {
-                return Err(de::Error::custom(format!("unsupported tileset version: {}", v)));
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "version" => version = Some(map.next_value::<usize>()?),
+                "name" => name = Some(map.next_value::<String>()?),
+                "source" => source_dir = Some(map.next_value::<PathBuf>()?),
+                "use_previews" => use_previews = map.next_value::<bool>()?,
+                "next_tile_id" => next_id = map.next_value::<TileId>()?,
+                "tiles" => tiles = Some(map.next_value::<Vec<Tile>>()?),
+                key => return Err(de::Error::unknown_field(key, FIELDS))
             }
         }
 
-        if map.next_key::<&str>()? != Some("name") {
-            return Err(de::Error::custom("expected \"name\" key"));
-        };
-        tileset.name = map.next_value::<String>()?;
+        match version {
+            Some(TILESET_VERSION) => (),
+            Some(v) => {
+                return Err(de::Error::custom(format!("unsupported tileset version: {}", v)));
+            }
+            None => return Err(de::Error::missing_field("version"))
+        }
 
-        if map.next_key::<&str>()? != Some("tiles") {
-            return Err(de::Error::custom("expected \"tiles\" key"));
+        let mut tileset = TileSet {
+            name: name.ok_or_else(|| de::Error::missing_field("name"))?,
+            source_dir,
+            use_previews,
+            ..default()
         };
-        let tiles = map.next_value::<Vec<Tile>>()?;
+        let tiles = tiles.ok_or_else(|| de::Error::missing_field("tiles"))?;
 
+        // a tile reusing an id is kept under a fresh one and shows up in
+        // `check_integrity` until it is repaired
+        let mut next_id = tiles.iter().map(|tile| tile.id + 1).max().unwrap_or(0).max(next_id);
         for tile in tiles {
-            tileset.tile_order.push(tile.id);
-            tileset.tiles.insert(tile.id, tile);
+            let key = if tileset.tiles.contains_key(&tile.id) {
+                next_id += 1;
+                next_id - 1
+            } else {
+                tile.id
+            };
+            tileset.tile_order.push(key);
+            tileset.tiles.insert(key, tile);
         }
+        tileset.tile_id_max = next_id;
 
         Ok(tileset)
     }
@@ -174,7 +622,7 @@ pub struct TileRef {
     pub tile: TileId
 }
 
-#[derive(Component, Default, Debug, Reflect, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Component, Default, Debug, Reflect, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub enum TileRotation {
     #[default]
     None,
@@ -186,6 +634,23 @@ pub enum TileRotation {
 }
 
 impl TileRotation {
+    pub const ALL: [TileRotation; 6] = [
+        TileRotation::None,
+        TileRotation::Clockwise60,
+        TileRotation::Clockwise120,
+        TileRotation::Clockwise180,
+        TileRotation::CounterClockwise120,
+        TileRotation::CounterClockwise60
+    ];
+
+    pub fn index(self) -> usize {
+        Self::ALL.iter().position(|r| *r == self).unwrap()
+    }
+
+    pub fn from_index(index: usize) -> Option<Self> {
+        Self::ALL.get(index).copied()
+    }
+
     pub fn clockwise(self) -> Self {
         match self {
             TileRotation::None => TileRotation::Clockwise60,
@@ -234,7 +699,8 @@ pub struct TileBundle {
     tile_ref: TileRef,
     location: map::Location,
     tile_transform: TileTransform,
-    scene: SceneRoot
+    scene: SceneRoot,
+    tile_scene: TileScene
 }
 
 impl TileBundle {
@@ -258,11 +724,14 @@ impl TileBundle {
             location,
             tile_ref: TileRef { tileset: tileset_entity, tile: tile_id },
             tile_transform,
-            scene: SceneRoot(scene)
+            scene: SceneRoot(scene.clone()),
+            tile_scene: TileScene(scene)
         }
     }
 }
 
+/// The scene a placed tile was given; removing it makes `update_tile_scene`
+/// spawn the tile's current scene again
 #[derive(Component)]
 pub struct TileScene(Handle<Scene>);
 
@@ -275,27 +744,54 @@ fn tile_ref_changed(
     }
 }
 
+/// Marks a tile whose `TileRef` does not resolve. The tile is kept, with its
+/// original reference, so that saving the map does not lose it.
+#[derive(Component, Debug)]
+pub struct MissingTile;
+
+/// Gives missing tiles another chance once their reference or the tilesets change
+fn recheck_missing_tiles(
+    mut commands: Commands,
+    mut missing: Query<(Entity, &mut TileRef), With<MissingTile>>,
+    changed_tilesets: Query<(), Changed<TileSet>>
+) {
+    let tilesets_changed = !changed_tilesets.is_empty();
+
+    for (entity, mut tile_ref) in &mut missing {
+        if !tile_ref.is_changed() && !tilesets_changed {
+            continue;
+        }
+        // touch the reference so that the transform is recomputed as well
+        tile_ref.set_changed();
+        commands.entity(entity).remove::<MissingTile>();
+    }
+}
+
+fn resolve_tile<'a>(tilesets: &'a Query<&mut TileSet>, tile_ref: &TileRef) -> Option<&'a Tile> {
+    tilesets.get(tile_ref.tileset).ok()?.tiles.get(&tile_ref.tile)
+}
+
 fn update_tile_scene(
     mut commands: Commands,
-    tiles: Query<(Entity, &TileRef), Without<TileScene>>,
+    tiles: Query<(Entity, &TileRef), (Without<TileScene>, Without<MissingTile>)>,
     tilesets: Query<&mut TileSet>
 ) {
     for (entity, tile_ref) in &tiles {
-        let Ok(tileset) = tilesets.get(tile_ref.tileset) else {
-            warn!("unknown tileset for tile {:?}: {:?}; removing entity", entity, tile_ref);
-            commands.entity(entity).despawn();
-            continue;
-        };
-        let Some(tile) = tileset.tiles.get(&tile_ref.tile) else {
-            warn!("unknown tile for tile {:?}: {:?}; removing entity", entity, tile_ref);
-            commands.entity(entity).despawn();
+        let Some(tile) = resolve_tile(&tilesets, tile_ref) else {
+            warn!("unknown tile for tile {:?}: {:?}; keeping placeholder", entity, tile_ref);
+            commands
+                .entity(entity)
+                .remove::<SceneRoot>()
+                .insert(MissingTile);
             continue;
         };
         let Some(scene) = tile.scene.as_ref() else {
             debug!("scene not present for {:?}: {:?}", entity, tile_ref);
             continue;
         };
-        commands.entity(entity).insert(SceneRoot(scene.clone()));
+        commands
+            .entity(entity)
+            .insert((SceneRoot(scene.clone()), TileScene(scene.clone())));
     }
 }
 
@@ -304,58 +800,326 @@ fn update_tile_transform(
     map: Query<&map::Map>,
     tile_transforms: Query<
         (Entity, &TileRef, &TileTransform, &map::Location),
-        Or<(Changed<TileTransform>, Changed<map::Location>)>
+        Or<(Changed<TileTransform>, Changed<map::Location>, Changed<TileRef>)>
     >,
     tilesets: Query<&mut TileSet>
 ) {
     let Ok(map) = map.single() else { return; };
     for (entity, tile_ref, tile_transform, location) in &tile_transforms {
-        let Ok(tileset) = tilesets.get(tile_ref.tileset) else {
-            warn!("unknow tileset for tile: {:?}: {:?}; removing entity", entity, tile_ref);
-            commands.entity(entity).despawn();
-            continue;
-        };
-        let Some(tile) = tileset.tiles.get(&tile_ref.tile) else {
-            warn!("unknown tile for tile {:?}: {:?}; removing entity", entity, tile_ref);
-            commands.entity(entity).despawn();
-            continue;
+        let transform = match resolve_tile(&tilesets, tile_ref) {
+            Some(tile) => map.tile_transform(tile, *location, tile_transform),
+            None => Transform::from_translation(map.translation(*location))
         };
-        let transform = map.tile_transform(tile, *location, tile_transform);
         commands.entity(entity).insert(transform);
     }
 }
 
+fn draw_missing_tiles(
+    mut gizmos: Gizmos,
+    map: Query<&map::Map>,
+    tiles: Query<&map::Location, With<MissingTile>>
+) {
+    let Ok(map) = map.single() else { return; };
+    let color = Color::srgb(1.0, 0.0, 1.0);
+
+    for location in &tiles {
+        let corners = map
+            .layout
+            .hex_corners(location.hex())
+            .map(|c| Vec3::new(c.x, 0.05, c.y));
+        gizmos.linestrip(corners.into_iter().chain([corners[0]]), color);
+        gizmos.line(corners[0], corners[3], color);
+        gizmos.line(corners[1], corners[4], color);
+        gizmos.line(corners[2], corners[5], color);
+    }
+}
+
+/// Scenes reserved for tiles showing part of a glTF file, filled in once the
+/// file is loaded
+#[derive(Resource, Default)]
+struct PendingModelParts(Vec<(Handle<Gltf>, ModelPart, Handle<Scene>)>);
+
+fn spawn_gltf_node(
+    world: &mut World,
+    node: &GltfNode,
+    nodes: &Assets<GltfNode>,
+    meshes: &Assets<GltfMesh>,
+    transform: Transform
+) -> Entity {
+    let entity = world
+        .spawn((Name::new(node.name.clone()), transform, Visibility::default()))
+        .id();
+
+    if let Some(mesh) = node.mesh.as_ref().and_then(|m| meshes.get(m)) {
+        for primitive in &mesh.primitives {
+            let mut child = world.spawn((Mesh3d(primitive.mesh.clone()), ChildOf(entity)));
+            if let Some(material) = &primitive.material {
+                child.insert(MeshMaterial3d(material.clone()));
+            }
+        }
+    }
+    for child in node.children.iter().filter_map(|c| nodes.get(c)) {
+        let child = spawn_gltf_node(world, child, nodes, meshes, child.transform);
+        world.entity_mut(child).insert(ChildOf(entity));
+    }
+
+    entity
+}
+
+fn build_model_parts(
+    mut pending: ResMut<PendingModelParts>,
+    gltfs: Res<Assets<Gltf>>,
+    nodes: Res<Assets<GltfNode>>,
+    meshes: Res<Assets<GltfMesh>>,
+    mut scenes: ResMut<Assets<Scene>>,
+    type_registry: Res<AppTypeRegistry>
+) {
+    pending.0.retain(|(gltf, part, scene)| {
+        let Some(gltf) = gltfs.get(gltf) else { return true };
+
+        let result = match part {
+            ModelPart::Scene(name) => gltf
+                .named_scenes
+                .get(name.as_str())
+                .and_then(|s| scenes.get(s))
+                .context(format!("no scene named {}", name))
+                .and_then(|s| s.clone_with(&type_registry).context("failed to clone scene")),
+            ModelPart::Node(name) => gltf
+                .named_nodes
+                .get(name.as_str())
+                .and_then(|n| nodes.get(n))
+                .context(format!("no node named {}", name))
+                .map(|node| {
+                    // the node keeps its orientation but is moved to the tile's origin
+                    let mut world = World::default();
+                    let transform = Transform {
+                        translation: Vec3::ZERO,
+                        ..node.transform
+                    };
+                    spawn_gltf_node(&mut world, node, &nodes, &meshes, transform);
+                    Scene::new(world)
+                })
+        };
+
+        match result {
+            Ok(built) => {
+                scenes.insert(scene, built).ok();
+            }
+            Err(e) => warn!("failed to load model part {:?}: {:?}", part, e)
+        }
+        false
+    });
+}
+
+/// Migrates the paths of loaded tilesets, see `Tile::path`
+fn normalize_tile_paths(
+    asset_root: Res<AssetRoot>,
+    mut tilesets: Query<
+        (&mut TileSet, Option<&persistence::TilesetFile>),
+        Or<(Added<TileSet>, Changed<persistence::TilesetFile>)>
+    >
+) {
+    for (mut tileset, file) in &mut tilesets {
+        let tileset_dir = file.and_then(|file| file.path.parent());
+        let changed = tileset.bypass_change_detection().normalize_paths(&asset_root, tileset_dir);
+        if changed > 0 {
+            info!("made {} paths in tileset {} relative to {:?}", changed, tileset.name, asset_root.0);
+            tileset.set_changed();
+        }
+
+        for tile in tileset.tiles.values().filter(|tile| tile.path.is_absolute()) {
+            warn!("tile {} is outside of the asset root: {:?}", tile.name, tile.path);
+        }
+    }
+}
+
+/// Problems found in a tileset when it was loaded, until it is repaired
+#[derive(Component, Debug, Clone)]
+pub struct TilesetIntegrity(pub Vec<IntegrityProblem>);
+
+fn check_tileset_integrity(
+    mut commands: Commands,
+    tilesets: Query<
+        (Entity, &TileSet),
+        Or<(Added<TileSet>, Changed<persistence::TilesetFile>)>
+    >
+) {
+    for (entity, tileset) in &tilesets {
+        let problems = tileset.check_integrity();
+        if problems.is_empty() {
+            commands.entity(entity).remove::<TilesetIntegrity>();
+            continue;
+        }
+
+        for problem in &problems {
+            warn!("tileset {}: {}", tileset.name, problem);
+        }
+        commands.entity(entity).insert(TilesetIntegrity(problems));
+    }
+}
+
+/// Where the preview image of a tile is looked for: in `previews_dir`, named
+/// after the part or the model file
+pub fn default_preview(tile: &Tile, previews_dir: &Path) -> PathBuf {
+    let stem = match &tile.part {
+        Some(part) => part.name().to_string(),
+        None => tile.path.file_stem().unwrap_or_default().to_string_lossy().into_owned()
+    };
+    previews_dir.join(format!("{}.png", stem))
+}
+
+/// The preview image of a tile relative to the asset root: the one set on
+/// the tile, or `default_preview` if it exists
+pub fn tile_preview(tile: &Tile, previews_dir: &Path, asset_root: &AssetRoot) -> Option<PathBuf> {
+    if let Some(preview) = &tile.preview {
+        return Some(preview.clone());
+    }
+    let preview = default_preview(tile, previews_dir);
+
+    asset_root.resolve(&preview).exists().then_some(preview)
+}
+
 fn load_tiles(
     asset_server: Res<AssetServer>,
+    mut pending_parts: ResMut<PendingModelParts>,
+    mut scenes: ResMut<Assets<Scene>>,
     mut tilesets: Query<&mut TileSet, Changed<TileSet>>,
     mut images: ResMut<Assets<Image>>,
     mut render_queue: ResMut<crate::render::RenderQueue>,
+    thumbnail_settings: Res<crate::render::ThumbnailSettings>,
+    asset_root: Res<AssetRoot>,
     mut egui_user_textures: ResMut<EguiUserTextures>
 ) {
     for mut tileset in &mut tilesets {
+        let use_previews = tileset.use_previews;
+
         for tile in tileset.tiles.values_mut() {
-            let scene = match tile.scene {
-                Some(_) => continue,
+            let scene = match &tile.scene {
+                Some(scene) => scene.clone(),
                 None => {
-                    let scene = asset_server.load(format!("{}#Scene0", tile.path.to_string_lossy()));
+                    let scene = match &tile.part {
+                        None => asset_server.load(format!("{}#Scene0", tile.path.to_string_lossy())),
+                        Some(part) => {
+                            let scene = scenes.reserve_handle();
+                            pending_parts.0.push((asset_server.load(tile.path.clone()), part.clone(), scene.clone()));
+                            scene
+                        }
+                    };
                     tile.scene = Some(scene.clone());
                     scene
                 }
             };
 
-            match tile.egui_texture_id {
-                Some(_) => continue,
-                None => {
-                    let image = alloc_render_image(48 * 2, 48 * 2);
+            if tile.egui_texture_id.is_some() {
+                continue;
+            }
+            // a detected preview is not stored in the tile, or the tileset would be saved with it
+            let preview = tile_preview(tile, &thumbnail_settings.previews_dir, &asset_root);
+
+            match (preview, use_previews) {
+                (Some(preview), true) => {
+                    let handle: Handle<Image> = asset_server.load(preview);
+                    tile.egui_texture_id = Some(egui_user_textures.add_image(handle));
+                }
+                _ => {
+                    let size = thumbnail_settings.size;
+                    let image = alloc_render_image(size, size);
                     let handle = images.add(image);
                     tile.egui_texture_id = Some(egui_user_textures.add_image(handle.clone()));
-                    render_queue.push(handle, scene);
+
+                    let variant = tile.part.as_ref().map(|p| format!("{:?}", p)).unwrap_or_default();
+                    let file = asset_root.resolve(&tile.path);
+                    render_queue.push_cached(handle, scene, file, variant, &thumbnail_settings);
                 }
             }
         }
     }
 }
 
+/// Thumbnails of tiles at each `TileRotation`, rendered on request
+#[derive(Resource, Default)]
+pub struct RotatedThumbnails {
+    textures: HashMap<(TileRef, TileRotation), egui::TextureId>,
+    requested: Vec<(TileRef, TileRotation)>
+}
+
+impl RotatedThumbnails {
+    /// The thumbnail of `tile` at `rotation`; requests it if there is none yet
+    pub fn get(&mut self, tile: TileRef, rotation: TileRotation) -> Option<egui::TextureId> {
+        let texture = self.textures.get(&(tile, rotation)).copied();
+        if texture.is_none() && !self.requested.contains(&(tile, rotation)) {
+            self.requested.push((tile, rotation));
+        }
+        texture
+    }
+
+    /// Drops the thumbnails of `tile`, e.g. after its model changed
+    pub fn invalidate(&mut self, tile: TileRef) {
+        self.textures.retain(|(t, _), _| *t != tile);
+    }
+}
+
+/// Builds `tiles` again from their model files: drops their scenes and
+/// thumbnails and respawns the placed copies
+pub fn refresh_tiles(commands: &mut Commands, tiles: &[TileRef]) {
+    let tiles = tiles.to_vec();
+    commands.queue(move |world: &mut World| {
+        crate::helper::run_system(world, tiles, clear_tiles);
+    });
+}
+
+fn clear_tiles(
+    In(tiles): In<Vec<TileRef>>,
+    mut commands: Commands,
+    mut rotated: ResMut<RotatedThumbnails>,
+    mut tilesets: Query<&mut TileSet>,
+    placed: Query<(Entity, &TileRef), With<TileScene>>
+) {
+    for tile_ref in &tiles {
+        rotated.invalidate(*tile_ref);
+        let Ok(mut tileset) = tilesets.get_mut(tile_ref.tileset) else { continue };
+        let Some(tile) = tileset.tiles.get_mut(&tile_ref.tile) else { continue };
+        tile.scene = None;
+        tile.egui_texture_id = None;
+    }
+    for (entity, tile_ref) in &placed {
+        if tiles.contains(tile_ref) {
+            commands.entity(entity).remove::<TileScene>();
+        }
+    }
+}
+
+fn render_rotated_thumbnails(
+    mut thumbnails: ResMut<RotatedThumbnails>,
+    tilesets: Query<&TileSet>,
+    mut images: ResMut<Assets<Image>>,
+    mut render_queue: ResMut<crate::render::RenderQueue>,
+    thumbnail_settings: Res<crate::render::ThumbnailSettings>,
+    mut egui_user_textures: ResMut<EguiUserTextures>
+) {
+    for (tile_ref, rotation) in std::mem::take(&mut thumbnails.requested) {
+        let Some(tile) = tilesets
+            .get(tile_ref.tileset)
+            .ok()
+            .and_then(|tileset| tileset.tiles.get(&tile_ref.tile))
+        else {
+            continue;
+        };
+        let Some(scene) = tile.scene.clone() else { continue };
+
+        let size = thumbnail_settings.size;
+        let handle = images.add(alloc_render_image(size, size));
+        thumbnails
+            .textures
+            .insert((tile_ref, rotation), egui_user_textures.add_image(handle.clone()));
+
+        let transform = Transform::from_rotation(
+            tile.transform.rotation * Quat::from_euler(EulerRot::XYZ, 0.0, rotation.into(), 0.0)
+        );
+        render_queue.push_transformed(handle, scene, transform);
+    }
+}
+
 fn alloc_render_image(width: u32, height: u32) -> Image {
     let size = Extent3d {
         width,
@@ -373,6 +1137,7 @@ fn alloc_render_image(width: u32, height: u32) -> Image {
             format: TextureFormat::Bgra8UnormSrgb, 
             usage: TextureUsages::TEXTURE_BINDING 
                 | TextureUsages::COPY_DST 
+                | TextureUsages::COPY_SRC
                 | TextureUsages::RENDER_ATTACHMENT,
             view_formats: &[] 
         },
@@ -387,7 +1152,7 @@ fn alloc_render_image(width: u32, height: u32) -> Image {
 #[derive(Component, Debug)]
 pub struct TilesetImporter {
     path: PathBuf,
-    task: Task<Result<TileSet>>
+    task: Task<Result<(TileSet, u64)>>
 }
 
 impl TilesetImporter {
@@ -395,10 +1160,12 @@ impl TilesetImporter {
         let task_pool = IoTaskPool::get();
         let path_copy = path.clone();
         let task = task_pool.spawn(async move {
-            let f = std::fs::File::open(path).context("failed to open file")?;
-            let tileset: TileSet = ron::de::from_reader(f).context("failed to parse tileset")?;
+            let buf = std::fs::read_to_string(&path).context("failed to read file")?;
+            let tileset: TileSet = FileFormat::from_path(&path)
+                .from_str(&buf)
+                .context("failed to parse tileset")?;
 
-            Ok::<TileSet, anyhow::Error>(tileset)
+            Ok::<(TileSet, u64), anyhow::Error>((tileset, persistence::content_hash(buf.as_bytes())))
         });
 
         Self {
@@ -408,23 +1175,45 @@ impl TilesetImporter {
     }
 }
 
+/// Loads a tileset file into a new entity, or into the entity it is inserted
+/// on to reload that tileset
 fn tileset_importer(
     mut commands: Commands,
-    mut tileset_importers: Query<(Entity, &mut TilesetImporter)>
+    mut events: EventWriter<IoCompleted>,
+    mut tileset_importers: Query<(Entity, &mut TilesetImporter, Has<TileSet>)>
 ) {
-    for (entity, mut importer) in &mut tileset_importers {
+    for (entity, mut importer, reload) in &mut tileset_importers {
         let Some(result) = futures_lite::future::block_on(futures_lite::future::poll_once(&mut importer.task)) else { continue };
         match result {
             Err(e) => {
                 warn!("failed to load tileset: {}: {:?}", importer.path.to_string_lossy(), e);
-                commands.entity(entity).despawn();
+                events.write(IoCompleted::new(IoOperation::TilesetImport, &importer.path, &Err::<(), _>(e)));
+                if reload {
+                    commands.entity(entity).remove::<TilesetImporter>();
+                } else {
+                    commands.entity(entity).despawn();
+                }
             }
-            Ok(tileset) => {
+            Ok((tileset, hash)) => {
+                events.write(IoCompleted::new(IoOperation::TilesetImport, &importer.path, &Ok(())));
                 let name = importer.path.file_stem().unwrap().to_string_lossy();
+                let tiles: Vec<TileRef> = tileset
+                    .tiles
+                    .keys()
+                    .map(|id| TileRef { tileset: entity, tile: *id })
+                    .collect();
                 commands
                     .entity(entity)
-                    .remove::<TilesetImporter>()
-                    .insert((Name::new(format!("tileset: {}", name)), tileset));
+                    .remove::<(TilesetImporter, persistence::TilesetFileChanged)>()
+                    .insert((
+                        Name::new(format!("tileset: {}", name)),
+                        tileset,
+                        persistence::TilesetFile {
+                            path: importer.path.clone(),
+                            hash
+                        }
+                    ));
+                refresh_tiles(&mut commands, &tiles);
             }
         }
     }
@@ -432,36 +1221,152 @@ fn tileset_importer(
 
 #[derive(Component, Debug)]
 pub struct TilesetExporter {
-    task: Task<Result<()>>
+    path: PathBuf,
+    /// The exported tileset entity, whose `TilesetFile` gets the written hash
+    source: Entity,
+    task: Task<Result<u64>>
 }
 
 impl TilesetExporter {
     pub fn new(
         path: PathBuf,
+        source: Entity,
         tileset: TileSet 
     ) -> Self {
+        let path_copy = path.clone();
         let task_pool = IoTaskPool::get();
         let task = task_pool.spawn(async move {
-            let f = std::fs::File::create(path.clone()).context(format!("open tileset {:?}", path))?;
-            ron::ser::to_writer_pretty(f, &tileset, ron::ser::PrettyConfig::default())
-                .context(format!("writing tileset to {:?}", path))?;
-
-            Ok::<(), anyhow::Error>(())
+            persistence::write_tileset(&path, &tileset, persistence::BACKUP_COUNT)
         });
 
-        Self { task }
+        Self {
+            path: path_copy,
+            source,
+            task
+        }
     }
 }
 
 fn tileset_exporter(
     mut commands: Commands,
-    mut tileset_exporters: Query<(Entity, &mut TilesetExporter)>
+    mut events: EventWriter<IoCompleted>,
+    mut tileset_exporters: Query<(Entity, &mut TilesetExporter)>,
+    mut files: Query<&mut persistence::TilesetFile>
 ) {
     for (entity, mut exporter) in &mut tileset_exporters {
         let Some(result) = futures_lite::future::block_on(futures_lite::future::poll_once(&mut exporter.task)) else { continue };
-        if let Err(e) = result {
-            warn!("failed to export tileset: {:#?}", e);
+        match &result {
+            // the editor's own write is not a change on disk
+            Ok(hash) => {
+                if let Ok(mut file) = files.get_mut(exporter.source) {
+                    if file.path == exporter.path {
+                        file.hash = *hash;
+                    }
+                }
+            }
+            Err(e) => warn!("failed to export tileset: {:#?}", e)
         }
+        events.write(IoCompleted::new(IoOperation::TilesetExport, &exporter.path, &result));
         commands.entity(entity).despawn();
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tileset_with(names: &[&str]) -> TileSet {
+        let mut tileset = TileSet::new("test");
+        for name in names {
+            tileset.add_title(PathBuf::from(format!("{}.glb", name)));
+        }
+        tileset
+    }
+
+    fn reload(tileset: &TileSet) -> TileSet {
+        let mut buf = Vec::new();
+        FileFormat::Ron.to_writer(&mut buf, tileset).unwrap();
+        FileFormat::Ron.from_str(&String::from_utf8(buf).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn ids_of_deleted_tiles_are_not_reused_after_loading() {
+        let mut tileset = tileset_with(&["a", "b", "c"]);
+        tileset.tiles.remove(&2);
+        tileset.tile_order.retain(|id| *id != 2);
+
+        let mut loaded = reload(&tileset);
+        assert_eq!(loaded.next_id(), 3);
+
+        loaded.add_title(PathBuf::from("d.glb"));
+        assert_eq!(loaded.tiles[&3].name, "d");
+        assert!(loaded.check_integrity().is_empty());
+    }
+
+    #[test]
+    fn duplicate_ids_are_loaded_under_a_fresh_id_and_repaired() {
+        let mut tileset = tileset_with(&["a", "b"]);
+        tileset.tiles.get_mut(&1).unwrap().id = 0;
+
+        let mut loaded = reload(&tileset);
+        assert_eq!(loaded.tiles.len(), 2);
+        assert_eq!(loaded.tile_order, vec![0, 1]);
+
+        let problem = IntegrityProblem::DuplicateId { id: 0, key: 1, name: "b".into() };
+        assert_eq!(loaded.check_integrity(), vec![problem.clone()]);
+        assert_eq!(loaded.repair(), vec![problem]);
+        assert_eq!(loaded.tiles[&1].id, 1);
+        assert!(loaded.check_integrity().is_empty());
+    }
+
+    #[test]
+    fn repair_fixes_the_tile_order() {
+        let mut tileset = tileset_with(&["a", "b", "c"]);
+        tileset.tile_order = vec![0, 0, 5, 1];
+
+        let problems = vec![
+            IntegrityProblem::DuplicateOrder(0),
+            IntegrityProblem::OrderWithoutTile(5),
+            IntegrityProblem::TileNotOrdered(2)
+        ];
+        assert_eq!(tileset.check_integrity(), problems);
+        assert_eq!(tileset.repair(), problems);
+        assert_eq!(tileset.tile_order, vec![0, 1, 2]);
+        assert!(tileset.check_integrity().is_empty());
+        assert_eq!(tileset.next_id(), 3);
+    }
+
+    #[test]
+    fn paths_are_stored_relative_to_the_asset_root() {
+        let dir = std::env::temp_dir().join(format!("world-editor-assets-{}", std::process::id()));
+        let assets = dir.join("assets");
+        for file in ["assets/forest/tree.glb", "assets/tilesets/bush.glb", "models/rock.glb"] {
+            let file = dir.join(file);
+            std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+            std::fs::write(file, b"").unwrap();
+        }
+        let root = AssetRoot(assets.clone());
+
+        let tile_path = root.tile_path(&assets.join("forest/tree.glb"));
+        let outside = root.tile_path(&dir.join("models/rock.glb"));
+        // already relative to the root
+        let existing = root.normalize(Path::new("forest/tree.glb"), Some(&assets.join("tilesets")));
+        // relative to the tileset file, inside and outside of the root
+        let beside = root.normalize(Path::new("bush.glb"), Some(&assets.join("tilesets")));
+        let beside_outside = root.normalize(Path::new("rock.glb"), Some(&dir.join("models")));
+        let absolute = root.normalize(&std::path::absolute(assets.join("forest/tree.glb")).unwrap(), None);
+        let absolute_outside = std::path::absolute(dir.join("models/rock.glb")).unwrap();
+        let kept_outside = root.normalize(&absolute_outside, None);
+        let missing = root.normalize(Path::new("gone.glb"), Some(&assets.join("tilesets")));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(tile_path, PathBuf::from("forest/tree.glb"));
+        assert!(outside.is_absolute());
+        assert_eq!(existing, None);
+        assert_eq!(beside, Some(PathBuf::from("tilesets/bush.glb")));
+        // files outside the root stay absolute instead of relative to the tileset
+        assert_eq!(beside_outside, Some(absolute_outside));
+        assert_eq!(kept_outside, None);
+        assert_eq!(absolute, Some(PathBuf::from("forest/tree.glb")));
+        assert_eq!(missing, None);
+    }
+}
@@ -0,0 +1,335 @@
+use anyhow::{ Context,Result };
+use hexx::Hex;
+use serde::{ Deserialize,Serialize };
+use std::{
+    collections::HashMap,
+    path::Path
+};
+
+use crate::{
+    map,
+    persistence::{ self,FileFormat }
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error
+}
+
+#[derive(Clone, Debug)]
+pub struct Problem {
+    pub severity: Severity,
+    pub layer: Option<String>,
+    pub location: Option<map::Location>,
+    pub message: String
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.severity {
+            Severity::Warning => write!(f, "warning")?,
+            Severity::Error => write!(f, "error")?
+        }
+        if let Some(layer) = &self.layer {
+            write!(f, " [{}]", layer)?;
+        }
+        if let Some(location) = &self.location {
+            write!(f, " ({}, {})", location.x, location.y)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// Selects tiles by tileset and tile name; a name ending in `*` matches by prefix
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TileMatch {
+    pub tileset: String,
+    pub tile: String
+}
+
+impl TileMatch {
+    fn matches(&self, tileset: &str, tile: &str) -> bool {
+        if self.tileset != tileset {
+            return false;
+        }
+        match self.tile.strip_suffix('*') {
+            Some(prefix) => tile.starts_with(prefix),
+            None => self.tile == tile
+        }
+    }
+}
+
+/// "`tiles` must neighbour one of `neighbours`", e.g. a port next to water
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AdjacencyRule {
+    pub name: String,
+    pub tiles: Vec<TileMatch>,
+    pub neighbours: Vec<TileMatch>,
+    /// Neighbours are looked up in every layer if `None`
+    #[serde(default)]
+    pub layer: Option<String>
+}
+
+/// Tiles forming a network such as a path or river. A tile with fewer than
+/// two connected neighbours is a dangling end unless it is one of `ends`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NetworkRule {
+    pub name: String,
+    pub tiles: Vec<TileMatch>,
+    #[serde(default)]
+    pub ends: Vec<TileMatch>
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Rules {
+    #[serde(default)]
+    pub adjacency: Vec<AdjacencyRule>,
+    #[serde(default)]
+    pub networks: Vec<NetworkRule>
+}
+
+impl Rules {
+    pub fn load(path: &Path) -> Result<Self> {
+        let buf = std::fs::read_to_string(path).context(format!("failed to read {:?}", path))?;
+        FileFormat::from_path(path)
+            .from_str(&buf)
+            .context(format!("failed to parse rules {:?}", path))
+    }
+}
+
+/// Tileset and tile name of every placed tile, per layer
+type Resolved<'a> = Vec<(&'a str, HashMap<Hex, (&'a str, &'a str)>)>;
+
+fn any_match(matches: &[TileMatch], tile: Option<&(&str, &str)>) -> bool {
+    tile.is_some_and(|(tileset, name)| matches.iter().any(|m| m.matches(tileset, name)))
+}
+
+pub fn validate(
+    map: &persistence::MapFormat,
+    rules: &Rules,
+    asset_root: &Path
+) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let mut resolved: Resolved = Vec::new();
+
+    for tileset in map.tilesets.values() {
+        for problem in tileset.check_integrity() {
+            problems.push(Problem {
+                severity: Severity::Error,
+                layer: None,
+                location: None,
+                message: format!("tileset {}: {}", tileset.name, problem)
+            });
+        }
+
+        for tile in tileset.tile_order.iter().filter_map(|id| tileset.tiles.get(id)) {
+            if !asset_root.join(&tile.path).exists() {
+                problems.push(Problem {
+                    severity: Severity::Error,
+                    layer: None,
+                    location: None,
+                    message: format!(
+                        "tile {} of tileset {}: missing asset {}",
+                        tile.name,
+                        tileset.name,
+                        tile.path.to_string_lossy()
+                    )
+                });
+            }
+        }
+    }
+
+    for layer in &map.layers {
+        let mut tiles = HashMap::new();
+
+        for tile in &layer.tiles {
+            let problem = |message: String| Problem {
+                severity: Severity::Error,
+                layer: Some(layer.name.clone()),
+                location: Some(tile.location),
+                message
+            };
+
+            let Some(tileset) = map.tilesets.get(&tile.tileset) else {
+                problems.push(problem(format!("unknown tileset {:?}", tile.tileset)));
+                continue;
+            };
+            let Some(tile_def) = tileset.tiles.get(&tile.tile_id) else {
+                problems.push(problem(format!("unknown tile {} in tileset {}", tile.tile_id, tileset.name)));
+                continue;
+            };
+            if tiles
+                .insert(tile.location.hex(), (tileset.name.as_str(), tile_def.name.as_str()))
+                .is_some()
+            {
+                problems.push(problem("more than one tile on this hex".into()));
+            }
+        }
+
+        resolved.push((layer.name.as_str(), tiles));
+    }
+
+    for rule in &rules.adjacency {
+        for (layer, tiles) in &resolved {
+            for (hex, tile) in tiles {
+                if !any_match(&rule.tiles, Some(tile)) {
+                    continue;
+                }
+                let satisfied = hex.all_neighbors().iter().any(|n| {
+                    resolved
+                        .iter()
+                        .filter(|(l, _)| rule.layer.as_deref().is_none_or(|name| name == *l))
+                        .any(|(_, tiles)| any_match(&rule.neighbours, tiles.get(n)))
+                });
+                if !satisfied {
+                    problems.push(Problem {
+                        severity: Severity::Warning,
+                        layer: Some(layer.to_string()),
+                        location: Some((*hex).into()),
+                        message: format!("{}: {} has no required neighbour", rule.name, tile.1)
+                    });
+                }
+            }
+        }
+    }
+
+    for rule in &rules.networks {
+        for (layer, tiles) in &resolved {
+            for (hex, tile) in tiles {
+                if !any_match(&rule.tiles, Some(tile)) || any_match(&rule.ends, Some(tile)) {
+                    continue;
+                }
+                let connected = hex
+                    .all_neighbors()
+                    .iter()
+                    .filter(|n| any_match(&rule.tiles, tiles.get(n)))
+                    .count();
+                if connected < 2 {
+                    problems.push(Problem {
+                        severity: Severity::Warning,
+                        layer: Some(layer.to_string()),
+                        location: Some((*hex).into()),
+                        message: format!("{}: dangling end", rule.name)
+                    });
+                }
+            }
+        }
+    }
+
+    problems.sort_by_key(|p| {
+        (
+            std::cmp::Reverse(p.severity),
+            p.layer.clone(),
+            p.location.map(|l| (l.y, l.x))
+        )
+    });
+
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ persistence::SaveId,tileset };
+
+    const TILES: [&str; 4] = ["water", "port", "path", "town"];
+
+    fn coast() -> tileset::TileSet {
+        let mut coast = tileset::TileSet::new("coast");
+        for name in TILES {
+            coast.add_title(format!("coast/{}.glb", name).into());
+        }
+        coast
+    }
+
+    /// Asset root holding the models of `names`
+    fn asset_root(test: &str, names: &[&str]) -> std::path::PathBuf {
+        let root = std::env::temp_dir().join(format!("world-editor-validate-{}-{}", test, std::process::id()));
+        std::fs::create_dir_all(root.join("coast")).unwrap();
+        for name in names {
+            std::fs::write(root.join(format!("coast/{}.glb", name)), b"").unwrap();
+        }
+        root
+    }
+
+    fn layer(name: &str, tiles: &[((i32, i32), usize, tileset::TileId)]) -> persistence::Layer {
+        persistence::Layer {
+            name: name.into(),
+            tiles: tiles
+                .iter()
+                .map(|(location, tileset, tile_id)| persistence::Tile {
+                    location: (*location).into(),
+                    tileset: SaveId::from(*tileset),
+                    tile_id: *tile_id,
+                    rotation: tileset::TileRotation::default()
+                })
+                .collect()
+        }
+    }
+
+    fn tile_match(tile: &str) -> TileMatch {
+        TileMatch { tileset: "coast".into(), tile: tile.into() }
+    }
+
+    #[test]
+    fn broken_maps_are_reported_without_panicking() {
+        let mut map = persistence::MapFormat::new(hexx::HexLayout::default());
+        let mut coast = coast();
+        coast.tile_order.push(7);
+        map.tilesets.insert(SaveId::from(0), coast);
+        map.layers.push(layer("ground", &[((0, 0), 0, 0), ((0, 0), 0, 1), ((1, 0), 0, 9), ((2, 0), 5, 0)]));
+
+        let root = asset_root("broken", &["water", "port", "town"]);
+        let problems = validate(&map, &Rules::default(), &root);
+        std::fs::remove_dir_all(&root).unwrap();
+
+        let messages: Vec<String> = problems.iter().map(|p| p.message.clone()).collect();
+        assert!(problems.iter().all(|p| p.severity == Severity::Error), "{:?}", messages);
+        assert_eq!(messages.len(), 5, "{:?}", messages);
+        for expected in [
+            "tileset coast: tile order lists missing tile 7",
+            "tile path of tileset coast: missing asset coast/path.glb",
+            "more than one tile on this hex",
+            "unknown tile 9 in tileset coast",
+            "unknown tileset SaveId(5)"
+        ] {
+            assert!(messages.iter().any(|m| m == expected), "{} not in {:?}", expected, messages);
+        }
+    }
+
+    #[test]
+    fn rules_report_missing_neighbours_and_dangling_ends() {
+        let mut map = persistence::MapFormat::new(hexx::HexLayout::default());
+        map.tilesets.insert(SaveId::from(0), coast());
+        map.layers.push(layer("ground", &[((0, 0), 0, 1), ((1, 0), 0, 0), ((5, 5), 0, 1)]));
+        map.layers.push(layer("roads", &[((0, 2), 0, 2), ((1, 2), 0, 2), ((2, 2), 0, 2), ((3, 2), 0, 3)]));
+        let rules = Rules {
+            adjacency: vec![AdjacencyRule {
+                name: "ports".into(),
+                tiles: vec![tile_match("port")],
+                neighbours: vec![tile_match("water")],
+                layer: Some("ground".into())
+            }],
+            networks: vec![NetworkRule {
+                name: "roads".into(),
+                tiles: vec![tile_match("pa*"), tile_match("town")],
+                ends: vec![tile_match("town")]
+            }]
+        };
+
+        let root = asset_root("rules", &TILES);
+        let problems = validate(&map, &rules, &root);
+        std::fs::remove_dir_all(&root).unwrap();
+
+        let found: Vec<(Option<String>, Option<(i32, i32)>, String)> = problems
+            .iter()
+            .map(|p| (p.layer.clone(), p.location.map(|l| (l.x, l.y)), p.message.clone()))
+            .collect();
+        assert!(problems.iter().all(|p| p.severity == Severity::Warning));
+        assert_eq!(found, vec![
+            (Some("ground".into()), Some((5, 5)), "ports: port has no required neighbour".into()),
+            (Some("roads".into()), Some((0, 2)), "roads: dangling end".into())
+        ]);
+    }
+}